@@ -0,0 +1,103 @@
+use std;
+use std::io::Write;
+
+
+//a labelled point in time, in seconds from the start of the source
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub time_s: f64,
+    pub label: String,
+}
+
+//keeps the list in time order
+pub fn insert(annotations: &mut Vec<Annotation>, annotation: Annotation) {
+    let i = annotations.iter().position(|a| a.time_s > annotation.time_s).unwrap_or(annotations.len());
+    annotations.insert(i, annotation);
+}
+
+//recordings leave their markers in <file>.events.csv, which is picked up when the file is opened
+pub fn events_path_for(data_path: &std::path::Path) -> Option<std::path::PathBuf> {
+    let path = data_path.with_extension("events.csv");
+    if path.is_file() {Some(path)} else {None}
+}
+
+//EDF and EDF+ files are read for their annotation signal, anything else is taken to be a csv
+pub fn load(path: &std::path::Path) -> Result<Vec<Annotation>, String> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("edf") => load_edf(path),
+        _ => load_csv(path),
+    }
+}
+
+//one annotation per line as time (s), label
+//lines starting with % or # and lines whose first field isn't a number, like a header, are skipped
+pub fn load_csv(path: &std::path::Path) -> Result<Vec<Annotation>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Unable to read annotations from {:?}: {}", path, e))?;
+    let mut annotations = Vec::<Annotation>::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.len() == 0 || line.starts_with('%') || line.starts_with('#') {continue;}
+        let mut fields = line.splitn(2, ',');
+        let time_s = match fields.next().and_then(|t| t.trim().parse::<f64>().ok()) {Some(t) => t, None => continue};
+        let label = fields.next().unwrap_or("").trim().trim_matches('"').to_string();
+        insert(&mut annotations, Annotation{time_s: time_s, label: label});
+    }
+    Ok(annotations)
+}
+
+pub fn save_csv(path: &std::path::Path, annotations: &[Annotation]) -> Result<(), String> {
+    let write = || -> std::io::Result<()> {
+        let mut f = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(f, "%time (s), label")?;
+        for a in annotations {writeln!(f, "{:.4}, {}", a.time_s, a.label)?;}
+        f.flush()
+    };
+    write().map_err(|e| format!("Unable to write annotations to {:?}: {}", path, e))
+}
+
+
+fn edf_number(header: &[u8], offset: usize, len: usize) -> Option<f64> {
+    header.get(offset..offset+len).and_then(|b| std::str::from_utf8(b).ok()).and_then(|s| s.trim().parse::<f64>().ok())
+}
+
+//reads the onsets and labels from every "EDF Annotations" signal
+//each data record holds time-stamped annotation lists: +onset[\x15duration]\x14label\x14...\x14\0
+//the first list in a record only keeps time and has no label, so it is skipped
+pub fn load_edf(path: &std::path::Path) -> Result<Vec<Annotation>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Unable to read {:?}: {}", path, e))?;
+    let bad = || format!("{:?} is not an EDF file.", path);
+    let header_bytes = edf_number(&data, 184, 8).ok_or_else(bad)? as usize;
+    let signals = edf_number(&data, 252, 4).ok_or_else(bad)? as usize;
+    if data.len() < header_bytes || header_bytes != 256*(signals+1) {return Err(bad());}
+
+    let mut annotation_signals = Vec::<usize>::new();
+    let mut samples = Vec::<usize>::new();
+    for s in 0..signals {
+        let label = String::from_utf8_lossy(&data[256 + s*16..256 + (s+1)*16]).trim().to_string();
+        if label == "EDF Annotations" {annotation_signals.push(s);}
+        samples.push(edf_number(&data, 256 + signals*216 + s*8, 8).ok_or_else(bad)? as usize);
+    }
+    if annotation_signals.is_empty() {return Err(format!("{:?} has no annotations, it is plain EDF rather than EDF+.", path));}
+
+    let record_bytes: usize = samples.iter().sum::<usize>()*2;
+    if record_bytes == 0 {return Err(bad());}
+    let records = (data.len() - header_bytes)/record_bytes; //the header's count can be -1 for an unfinished recording
+    let mut annotations = Vec::<Annotation>::new();
+    for r in 0..records {
+        let record = &data[header_bytes + r*record_bytes..header_bytes + (r+1)*record_bytes];
+        for &s in &annotation_signals {
+            let start = samples[..s].iter().sum::<usize>()*2;
+            let bytes = &record[start..start + samples[s]*2];
+            for tal in bytes.split(|b| *b == 0).filter(|t| t.len() > 0) {
+                let tal = String::from_utf8_lossy(tal);
+                let mut parts = tal.split('\x14');
+                let onset = parts.next().and_then(|o| o.split('\x15').next()).and_then(|o| o.parse::<f64>().ok());
+                let onset = match onset {Some(onset) => onset, None => continue};
+                for label in parts.filter(|l| l.len() > 0) {
+                    insert(&mut annotations, Annotation{time_s: onset, label: label.to_string()});
+                }
+            }
+        }
+    }
+    Ok(annotations)
+}
@@ -1,6 +1,35 @@
 use std;
+use glium;
 
-use waveformdrawer::{WaveformDrawer};
+use waveformdrawer::{WaveformDrawer, WaveformDrawerSettings};
+use openbci_file::{OpenBCIFile};
+use pastuff;
+use bandpower;
+use bandpower::{Band, BandRatio};
+use filters::{FilterSettings, FilterChain};
+use detrend::{Detrend};
+use dftwindower;
+use dftwindower::{WindowShape, WindowMetrics};
+use derived::{DerivedChannel};
+use channelinfo;
+use channelinfo::{ChannelInfo};
+use layout::{Layout};
+use viewport::{Viewport};
+use presets::{Preset, PresetFile};
+use recorder::{Recorder, RecordFormat, StreamInfo};
+use export::{MatrixFormat};
+use annotations;
+use annotations::{Annotation};
+use ersp::{Ersp, ErspSettings};
+use worker::{Worker};
+use panelimage::{PanelImages};
+use artifacts;
+use artifacts::{ArtifactSettings, Span};
+use ica::{Ica, Algorithm};
+use neurofeedback::{Neurofeedback, FeedbackSettings};
+use ssvep::{Ssvep, SsvepSettings};
+use connectivity::{Connectivity, ConnectivitySettings};
+use topomap::{TopoMap, TopoSettings};
 
 
 pub struct Ticker {
@@ -30,23 +59,123 @@ pub enum DataSource {
 
 
 pub struct WaveData{
-    pub buffer: Vec<Vec<f32>>,
-    pub channels: u32,
+    pub raw: Vec<Vec<f32>>, //samples as read from the file
+    pub buffer: Vec<Vec<f32>>, //samples after the filter chain followed by the derived channels, this is what gets analysed
+    pub derived: Vec<DerivedChannel>,
+    pub info: Vec<ChannelInfo>, //one entry per raw channel
+    pub aux: Vec<Vec<f32>>, //accelerometer columns when the file has them
+    pub artifacts: Vec<Span>, //from the last detection, see artifacts::detect
+    pub artifact_settings: Option<ArtifactSettings>, //what the last detection used, it is run again when the filters change
+    pub channels: u32, //number of raw channels
     pub sample_rate: u32,
     pub buffer_length: usize
 }
 
+impl WaveData{
+    pub fn new(mut raw: Vec<Vec<f32>>, sample_rate: u32, info: Vec<ChannelInfo>, aux: Vec<Vec<f32>>) -> WaveData{
+        for (channel, ci) in raw.iter_mut().zip(info.iter()) {
+            if ci.gain != 1.0 {
+                for x in channel.iter_mut() {*x *= ci.gain;}
+            }
+        }
+        WaveData{
+            channels: raw.len() as u32,
+            buffer_length: raw[0].len(),
+            buffer: raw.clone(),
+            raw: raw,
+            derived: Vec::<DerivedChannel>::new(),
+            info: info,
+            aux: aux,
+            artifacts: Vec::<Span>::new(),
+            artifact_settings: None,
+            sample_rate: sample_rate,
+        }
+    }
+    //the whole file is available so we can filter with zero phase
+    pub fn apply_filters(&mut self, settings: &FilterSettings){
+        let sample_rate = self.sample_rate;
+        self.buffer = self.raw.iter().map(|channel| FilterChain::new(settings, sample_rate).filtfilt(channel)).collect();
+        self.compute_derived();
+        //flat lines and line noise are judged on the filtered channels, so the old spans no longer hold
+        if let Some(artifact_settings) = self.artifact_settings.clone() {
+            self.detect_artifacts(&artifact_settings, settings.mains_hz);
+        }
+    }
+    //derived channels are always built from the filtered raw channels
+    pub fn compute_derived(&mut self){
+        let channels = self.channels as usize;
+        self.buffer.truncate(channels);
+        let rows: Vec<Vec<f32>> = self.derived.iter().map(|d| d.compute(&self.buffer[..channels])).collect();
+        self.buffer.extend(rows);
+    }
+    pub fn set_derived(&mut self, derived: Vec<DerivedChannel>){
+        self.derived = derived;
+        self.compute_derived();
+    }
+    pub fn detect_artifacts(&mut self, settings: &ArtifactSettings, mains_hz: f32){
+        let channels = self.channels as usize;
+        self.artifacts = artifacts::detect(&self.raw, &self.buffer[..channels], &self.info, &self.aux, self.sample_rate, mains_hz, settings);
+        self.artifact_settings = Some(settings.clone());
+    }
+    //the spans that touch a channel, derived channels mix raw ones so any of theirs might
+    pub fn artifacts_for(&self, channel: usize) -> Vec<Span>{
+        self.artifacts.iter().filter(|s| channel >= self.channels as usize || s.channel.map(|c| c == channel).unwrap_or(true)).cloned().collect()
+    }
+    pub fn raw_channel_names(&self) -> Vec<String>{
+        self.info.iter().map(|ci| ci.label.clone()).collect()
+    }
+    //names of every channel a drawer can select, raw channels first
+    pub fn channel_names(&self) -> Vec<String>{
+        let mut names = self.raw_channel_names();
+        let derived_names: Vec<String> = self.derived.iter().map(|d| d.name(&names)).collect();
+        names.extend(derived_names);
+        names
+    }
+}
+
 pub struct StreamingData{
     pub deque: Vec<f32>,
+    pub filtered: Vec<f32>, //deque after the filter chain
+    pub filter_chain: FilterChain,
     pub channels: u32,
     pub sample_rate: u32,
     pub samples_written: usize,
     pub frames_lag: usize,
-    pub acceptable_frames_lag: usize
+    pub acceptable_frames_lag: usize,
+    pub full_scale: f32, //largest magnitude a sample can take
+    pub recorder: Option<Recorder>,
+}
+
+impl StreamingData{
+    pub fn push_samples(&mut self, samples: &[f32]){
+        self.deque.extend_from_slice(samples);
+        self.filter_chain.process_into(samples, &mut self.filtered);
+        self.samples_written += samples.len();
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.push(samples);
+        }
+    }
+    //the raw samples are recorded, not the filtered ones
+    pub fn start_recording(&mut self, format: RecordFormat, path: &std::path::Path, labels: Vec<String>) -> Result<(), String>{
+        if self.recorder.is_some() {return Err(String::from("Already recording."));}
+        let info = StreamInfo{channels: self.channels, sample_rate: self.sample_rate, labels: labels, full_scale: self.full_scale};
+        self.recorder = Some(Recorder::start(format, path, info)?);
+        Ok(())
+    }
+    //stopping waits for the writer, so take the recorder out and stop it once the data lock is released
+    pub fn take_recorder(&mut self) -> Option<Recorder>{
+        self.recorder.take()
+    }
+    //live data can only be filtered causally, the filter picks up from where it is
+    //and anything already in the buffer is left as it was
+    pub fn set_filters(&mut self, settings: &FilterSettings){
+        self.filter_chain = FilterChain::new(settings, self.sample_rate);
+    }
 }
 
 pub struct AppData{
     pub data_source: DataSource,
+    pub source_path: Option<std::path::PathBuf>, //the file the data came from, if it came from a file
     pub wave_data: Option<WaveData>,
     pub streaming_data: Option<StreamingData>
 }
@@ -70,6 +199,30 @@ impl AppData{
         }
         Err("No AppData: DataSource is None")
     }
+    //names of every channel a drawer can read from the current source
+    pub fn channel_names(&self) -> Vec<String>{
+        match self.data_source {
+            DataSource::WavBuffer => self.wave_data.as_ref().unwrap().channel_names(),
+            DataSource::PortAudio => vec![String::from("Microphone")],
+            DataSource::NoSource => Vec::<String>::new(),
+        }
+    }
+    //everything a channel has to analyse so far, filtered
+    pub fn samples(&self, channel: usize) -> &[f32]{
+        match self.data_source {
+            DataSource::WavBuffer => &self.wave_data.as_ref().unwrap().buffer[channel],
+            DataSource::PortAudio => &self.streaming_data.as_ref().unwrap().filtered,
+            DataSource::NoSource => &[],
+        }
+    }
+    pub fn set_filters(&mut self, settings: &FilterSettings){
+        if let Some(ref mut wave_data) = self.wave_data {
+            wave_data.apply_filters(settings);
+        }
+        if let Some(ref mut streaming_data) = self.streaming_data {
+            streaming_data.set_filters(settings);
+        }
+    }
     /*pub fn readbuffer(&mut self, channel: usize, i: usize) -> f32 {
         if self.data_source == DataSource::WavBuffer {
             return self.wave_data.as_ref().unwrap().buffer[channel][i];
@@ -100,7 +253,7 @@ impl AppData{
             let ib = ib - sd.frames_lag;
             let ia = ia - sd.frames_lag;
         //    println!("Reading from location {}", ib);
-            return &sd.deque[ia..ib];
+            return &sd.filtered[ia..ib];
         }
 
         assert!(false,"Failure to read buffer: DataSource is None.");
@@ -108,19 +261,157 @@ impl AppData{
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum GuiDisplay{
     FileOpen,
     FilterOptions,
+    BandPower,
+    Montage,
+    Layout,
+    Presets,
+    Session,
+    Record,
+    Export,
+    Annotations,
+    Ersp,
+    Transform,
+    Peaks,
+    Connectivity,
+    ScalpMap,
+    Artifacts,
+    Ica,
+    Neurofeedback,
+    Ssvep,
     Nothing,
 }
+impl GuiDisplay{
+    //the panels that can be picked from the drop down at the top of the settings canvas
+    pub const PANELS: [GuiDisplay; 18] = [GuiDisplay::FilterOptions, GuiDisplay::BandPower, GuiDisplay::Montage, GuiDisplay::Layout, GuiDisplay::Presets, GuiDisplay::Session, GuiDisplay::Record, GuiDisplay::Export, GuiDisplay::Annotations, GuiDisplay::Ersp, GuiDisplay::Transform, GuiDisplay::Peaks, GuiDisplay::Connectivity, GuiDisplay::ScalpMap, GuiDisplay::Artifacts, GuiDisplay::Ica, GuiDisplay::Neurofeedback, GuiDisplay::Ssvep];
+
+    pub fn name(&self) -> &'static str{
+        match *self {
+            GuiDisplay::FileOpen => "Open File",
+            GuiDisplay::FilterOptions => "Filter Options",
+            GuiDisplay::BandPower => "Band Powers",
+            GuiDisplay::Montage => "Montage",
+            GuiDisplay::Layout => "Layout",
+            GuiDisplay::Presets => "Presets",
+            GuiDisplay::Session => "Session",
+            GuiDisplay::Record => "Record",
+            GuiDisplay::Export => "Export",
+            GuiDisplay::Annotations => "Annotations",
+            GuiDisplay::Ersp => "ERSP",
+            GuiDisplay::Transform => "Transforms",
+            GuiDisplay::Peaks => "Peaks",
+            GuiDisplay::Connectivity => "Connectivity",
+            GuiDisplay::ScalpMap => "Scalp Map",
+            GuiDisplay::Artifacts => "Artifacts",
+            GuiDisplay::Ica => "ICA",
+            GuiDisplay::Neurofeedback => "Neurofeedback",
+            GuiDisplay::Ssvep => "SSVEP",
+            GuiDisplay::Nothing => "",
+        }
+    }
+}
+
+//what is being built in the montage panel before it is added as a derived channel
+pub struct DerivedChannelEditor{
+    pub kind: usize, //index into DerivedChannel::KIND_NAMES
+    pub channel: u32,
+    pub reference: u32,
+    pub second_reference: u32,
+    pub neighbours: Vec<bool>,
+}
+impl Default for DerivedChannelEditor{
+    fn default() -> DerivedChannelEditor{
+        DerivedChannelEditor{
+            kind: 0,
+            channel: 0,
+            reference: 0,
+            second_reference: 0,
+            neighbours: Vec::<bool>::new(),
+        }
+    }
+}
+impl DerivedChannelEditor{
+    pub fn build(&self) -> DerivedChannel{
+        match self.kind {
+            1 => DerivedChannel::Reference(self.channel, self.reference),
+            2 => DerivedChannel::LinkedMastoids(self.channel, self.reference, self.second_reference),
+            3 => DerivedChannel::Bipolar(self.channel, self.reference),
+            4 => DerivedChannel::Laplacian(self.channel, (0..self.neighbours.len() as u32).filter(|n| self.neighbours[*n as usize] && *n != self.channel).collect()),
+            _ => DerivedChannel::CommonAverage(self.channel),
+        }
+    }
+}
 
 pub struct GuiData{
     pub gui_display: GuiDisplay,
     pub file_selection: Option<std::path::PathBuf>,
-    pub cursor_xy: (f32,f32),
+    pub cursor_xy: (f32,f32), //logical position from the top left, see Viewport::cursor_to_percent
+    pub viewport: Viewport,
+    pub derived_editor: DerivedChannelEditor,
+    pub preset_name: String,
+    pub preset_file: PresetFile,
+    pub preset_path: std::path::PathBuf,
+    pub session_path: String,
+    pub session_status: String,
+    pub record_format: RecordFormat,
+    pub record_path: String,
+    pub record_status: String,
+    pub marker_label: String,
+    pub export_channel: Option<usize>, //None exports every channel
+    pub export_range: Option<(f32,f32)>, //seconds, None is the whole file
+    pub export_path: String, //channel labels and extensions are added to this
+    pub matrix_format: MatrixFormat,
+    pub export_status: String,
+    pub annotation_path: String,
+    pub annotation_status: String,
+    pub ersp_settings: ErspSettings,
+    pub ersp: Option<Ersp>,
+    pub ersp_worker: Option<Worker<Ersp>>, //while an ERSP is being computed
+    pub ersp_status: String,
+    pub panel_images: PanelImages,
+    pub peaks_path: String,
+    pub peaks_status: String,
+    pub window_metrics: Option<(WindowShape, WindowMetrics)>, //for the shape last shown in filter options, they take an fft to work out
+    pub filters_pending: bool, //the filter settings have changed since the data was last filtered
+    pub connectivity_settings: ConnectivitySettings,
+    pub connectivity: Option<Connectivity>,
+    pub connectivity_worker: Option<Worker<Connectivity>>, //while connectivity is being worked out
+    pub connectivity_status: String,
+    pub topo_settings: TopoSettings,
+    pub topo_map: Option<TopoMap>, //pixel weights for the electrodes last shown
+    pub topo_shown: Vec<(String, f32)>, //the electrodes and values in the map as drawn, it is only redrawn when they change
+    pub artifact_settings: ArtifactSettings,
+    pub ica_algorithm: Algorithm,
+    pub ica: Option<Ica>,
+    pub ica_worker: Option<Worker<Ica>>, //while ICA is running
+    pub ica_component: usize, //the one plotted and labelled in the panel
+    pub ica_status: String,
+    pub feedback_settings: FeedbackSettings,
+    pub feedback_threshold: String, //as typed, parsed into feedback_settings.threshold
+    pub feedback_path: String,
+    pub feedback_status: String,
+    pub neurofeedback: Option<Neurofeedback>, //while a session is running
+    pub ssvep_settings: SsvepSettings,
+    pub ssvep_frequencies: String, //as typed, parsed into ssvep_settings.frequencies
+    pub ssvep: Option<Ssvep>, //while the detector is running
+    pub ssvep_status: String,
+}
+
+impl GuiData{
+    //components only fit the data they were worked out from, so a new file or new filters drop them and any run in progress
+    pub fn clear_ica(&mut self){
+        if self.ica.is_some() || self.ica_worker.is_some() {
+            self.ica_status = String::from("The data has changed, run ICA again.");
+        }
+        self.ica = None;
+        self.ica_worker = None;
+    }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FilterData{
     pub red: (f32,f32),
     pub min_red: (f32,f32),
@@ -135,7 +426,14 @@ pub struct FilterData{
     pub amp_min: f32,
     pub amp_max: f32,
     pub amp_manual: bool,
-    pub window_shape: i32,
+    #[serde(deserialize_with = "dftwindower::shape_or_index")]
+    pub window_shape: WindowShape,
+    pub detrend: Detrend,
+    pub bands: Vec<Band>,
+    pub band_ratios: Vec<BandRatio>,
+    pub filters: FilterSettings,
+    #[serde(default)]
+    pub exclude_artifacts: bool, //leave flagged spans out of band powers, the ERSP and connectivity
 }
 impl Default for FilterData{
     fn default()->FilterData{
@@ -153,7 +451,12 @@ impl Default for FilterData{
              amp_min: -10.0,
              amp_max: 5.0,
              amp_manual: false,
-             window_shape: 0,
+             window_shape: WindowShape::Rectangular,
+             detrend: Detrend::None,
+             bands: bandpower::default_bands(),
+             band_ratios: bandpower::default_ratios(),
+             filters: FilterSettings::default(),
+             exclude_artifacts: false,
         }
     }
 }
@@ -162,6 +465,123 @@ pub struct AppState<'a>{
     pub filter_data: FilterData,
     pub gui_data: GuiData,
     pub waveform_drawers: Vec<WaveformDrawer<'a>>,
+    pub layout: Layout,
+    pub preset: Option<Preset>, //the last preset loaded, its drawer settings are reused when a new source is opened
+    pub annotations: Vec<Annotation>, //in time order, see annotations::insert
     pub ticker: Ticker,
     pub app_data: std::sync::Arc<std::sync::Mutex<AppData>>
 }
+
+impl<'a> AppState<'a>{
+    pub fn open_file(&mut self, display: &'a glium::Display, file_path: &std::path::Path){
+        // ## load OPENBCI file
+        println!("Reading OpenBCI data file.");
+        let mut openbci_file=OpenBCIFile::new(file_path.to_str().unwrap());
+        if let Some(montage_path) = channelinfo::montage_path_for(file_path) {
+            println!("Reading montage from {:?}.", montage_path);
+            if let Err(e) = channelinfo::apply_montage(&montage_path, &mut openbci_file.info) {println!("{}", e);}
+        }
+        let mut wave_data = WaveData::new(openbci_file.samples.clone(), openbci_file.sample_rate_hz, openbci_file.info.clone(), openbci_file.aux.clone());
+        wave_data.apply_filters(&self.filter_data.filters);
+        wave_data.detect_artifacts(&self.gui_data.artifact_settings, self.filter_data.filters.mains_hz);
+        {
+            let mut app_data = self.app_data.lock().unwrap();
+            app_data.wave_data = Some(wave_data);
+            app_data.data_source = DataSource::WavBuffer;
+            app_data.source_path = Some(file_path.to_path_buf());
+        }
+        self.annotations.clear();
+        self.gui_data.clear_ica();
+        if let Some(events_path) = annotations::events_path_for(file_path) {
+            println!("Reading annotations from {:?}.", events_path);
+            match annotations::load(&events_path) {
+                Ok(loaded) => self.annotations = loaded,
+                Err(e) => println!("{}", e),
+            }
+        }
+
+        println!("Initialising waveform drawer.");
+        self.waveform_drawers.clear();
+        for i in 0..openbci_file.channels{
+        self.waveform_drawers.push( WaveformDrawer::new( display,
+            WaveformDrawerSettings{
+                    y: 37.5 - 25.0 *i as f32,
+                    height: 20.0,
+                    channel: i,
+                    ..WaveformDrawerSettings::default()}))
+        }
+        self.layout.reset(self.waveform_drawers.len());
+        self.layout.apply(&mut self.waveform_drawers, self.gui_data.viewport.aspect());
+        if let Some(preset) = self.preset.clone() {self.apply_drawer_settings(&preset.drawers);}
+
+        let ticks=self.ticker.ticks();
+        for wfd in &mut self.waveform_drawers{
+            wfd.start(ticks);
+        }
+    }
+
+    pub fn open_portaudio(&mut self, display: &'a glium::Display){
+        pastuff::pa_read_from_mic(self);
+        {
+            //a file opened before would otherwise lend its artifact spans to the live data
+            let mut app_data = self.app_data.lock().unwrap();
+            app_data.source_path = None;
+            app_data.wave_data = None;
+        }
+        self.annotations.clear();
+        self.gui_data.clear_ica();
+
+        println!("Initialising waveform drawer.");
+        self.waveform_drawers.clear();
+        self.waveform_drawers.push( WaveformDrawer::new( display,
+            WaveformDrawerSettings{
+                    milliseconds_per_pixel: 5.0,
+                    time_pixels: 1600,
+                    dtft_samples: 1800,
+                    dtft_display_samples: 300,
+                    ..WaveformDrawerSettings::default()}));
+        self.layout.reset(self.waveform_drawers.len());
+        self.layout.apply(&mut self.waveform_drawers, self.gui_data.viewport.aspect());
+        if let Some(preset) = self.preset.clone() {self.apply_drawer_settings(&preset.drawers);}
+
+        let ticks=self.ticker.ticks();
+        for wfd in &mut self.waveform_drawers{
+            wfd.start(ticks);
+        }
+    }
+
+    //marks where playback has got to, and the recording if there is one
+    pub fn add_marker(&mut self, label: &str){
+        let ticks = self.ticker.ticks();
+        let time_s = match self.waveform_drawers.first() {
+            Some(wfd) => wfd.position_ms(ticks) as f64/1000.0,
+            None => return,
+        };
+        annotations::insert(&mut self.annotations, Annotation{time_s: time_s, label: label.to_string()});
+        let mut app_data = self.app_data.lock().unwrap();
+        if let Some(recorder) = app_data.streaming_data.as_mut().and_then(|sd| sd.recorder.as_mut()) {
+            recorder.mark(label);
+        }
+    }
+
+    pub fn apply_preset(&mut self, preset: &Preset){
+        self.filter_data = preset.filter_data.clone();
+        self.app_data.lock().unwrap().set_filters(&self.filter_data.filters);
+        self.gui_data.clear_ica();
+        self.apply_drawer_settings(&preset.drawers);
+        self.preset = Some(preset.clone());
+    }
+
+    //drawers are matched up by index, a channel the current source doesn't have is left alone
+    pub fn apply_drawer_settings(&mut self, drawers: &[WaveformDrawerSettings]){
+        let channels = self.app_data.lock().unwrap().channel_names().len() as u32;
+        for (wfd, settings) in self.waveform_drawers.iter_mut().zip(drawers.iter()) {
+            let mut settings = settings.clone();
+            if settings.channel >= channels {settings.channel = wfd.settings.channel;}
+            wfd.set_settings(settings);
+        }
+        for (i, wfd) in self.waveform_drawers.iter().enumerate() {
+            if i < self.layout.hidden.len() {self.layout.hidden[i] = !wfd.settings.visible;}
+        }
+    }
+}
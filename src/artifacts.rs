@@ -0,0 +1,328 @@
+use std;
+
+use channelinfo::{ChannelInfo};
+
+//a sample this close to the largest in its channel, repeated, is taken as the amplifier sitting on its rail
+const CLIP_LEVEL: f32 = 0.99;
+//line noise is judged over windows this long, stepping by half of one
+const LINE_WINDOW_S: f64 = 1.0;
+//blinks are a smoothed deflection from a slower running mean
+const BLINK_SMOOTH_S: f64 = 0.05;
+const BLINK_BASELINE_S: f64 = 1.0;
+//anything longer than this is a drift rather than a blink
+const BLINK_MAX_S: f64 = 1.0;
+//electrodes this far forward on the flattened head count as frontal, the Fp and AF row
+const FRONTAL_Y: f32 = 0.6;
+//motion is movement of the accelerometer away from its mean over this long
+const MOTION_WINDOW_S: f64 = 1.0;
+//flagged spans are widened by this much either side so the edges of an artifact are caught too
+const PAD_S: f64 = 0.1;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArtifactKind {
+    Clipping,
+    Flatline,
+    LineNoise,
+    Blink,
+    Motion,
+}
+
+impl ArtifactKind {
+    pub const ALL: [ArtifactKind; 5] = [ArtifactKind::Clipping, ArtifactKind::Flatline, ArtifactKind::LineNoise, ArtifactKind::Blink, ArtifactKind::Motion];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ArtifactKind::Clipping => "Clipping",
+            ArtifactKind::Flatline => "Flat line",
+            ArtifactKind::LineNoise => "Line noise",
+            ArtifactKind::Blink => "Blink",
+            ArtifactKind::Motion => "Motion",
+        }
+    }
+
+    //shading over the spectrogram
+    pub fn colour(&self) -> (f32, f32, f32) {
+        match *self {
+            ArtifactKind::Clipping => (1.0, 0.2, 0.2),
+            ArtifactKind::Flatline => (0.6, 0.6, 0.6),
+            ArtifactKind::LineNoise => (1.0, 0.6, 0.1),
+            ArtifactKind::Blink => (0.3, 0.6, 1.0),
+            ArtifactKind::Motion => (0.8, 0.3, 1.0),
+        }
+    }
+}
+
+//what the artifact panel is set to, enabled follows ArtifactKind::ALL
+#[derive(Clone)]
+pub struct ArtifactSettings {
+    pub enabled: [bool; 5],
+    pub clip_run: u32, //repeated samples at the rail before it counts
+    pub flat_uv: f32, //peak to peak below this is flat
+    pub flat_s: f32, //for at least this long
+    pub line_fraction: f32, //share of a window's power at the mains frequency
+    pub blink_uv: f32,
+    pub motion_g: f32,
+}
+
+impl Default for ArtifactSettings {
+    fn default() -> ArtifactSettings {
+        ArtifactSettings{
+            enabled: [true; 5],
+            clip_run: 3,
+            flat_uv: 0.5,
+            flat_s: 0.5,
+            line_fraction: 0.3,
+            blink_uv: 100.0,
+            motion_g: 0.1,
+        }
+    }
+}
+
+//a stretch of bad data, on one channel or all of them
+#[derive(Clone, Copy, PartialEq)]
+pub struct Span {
+    pub kind: ArtifactKind,
+    pub channel: Option<usize>, //None for blinks and motion, which reach every channel
+    pub start_s: f64,
+    pub end_s: f64,
+}
+
+impl Span {
+    pub fn overlaps(&self, start_s: f64, end_s: f64) -> bool {
+        self.start_s < end_s && self.end_s > start_s
+    }
+}
+
+//the runs of true in flags as spans, padded and clipped to the data
+fn runs(flags: &[bool], sample_rate: u32, kind: ArtifactKind, channel: Option<usize>, max_s: Option<f64>) -> Vec<Span> {
+    let rate = sample_rate as f64;
+    let length_s = flags.len() as f64/rate;
+    let mut spans = Vec::<Span>::new();
+    let mut start: Option<usize> = None;
+    for i in 0..flags.len()+1 {
+        let flagged = i < flags.len() && flags[i];
+        match (start, flagged) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                start = None;
+                if max_s.map(|max| (i - s) as f64/rate > max).unwrap_or(false) {continue;}
+                spans.push(Span{
+                    kind: kind,
+                    channel: channel,
+                    start_s: (s as f64/rate - PAD_S).max(0.0),
+                    end_s: (i as f64/rate + PAD_S).min(length_s),
+                });
+            },
+            _ => {},
+        }
+    }
+    spans
+}
+
+//a running mean over width samples centred on each one, shortened at the ends
+fn running_mean(x: &[f32], width: usize) -> Vec<f32> {
+    let mut sums = vec![0.0f64; x.len()+1];
+    for i in 0..x.len() {sums[i+1] = sums[i] + x[i] as f64;}
+    let half = width/2;
+    (0..x.len()).map(|i| {
+        let (a, b) = (i.saturating_sub(half), std::cmp::min(x.len(), i + half + 1));
+        ((sums[b] - sums[a])/(b - a) as f64) as f32
+    }).collect()
+}
+
+fn clipping(raw: &[f32], channel: usize, sample_rate: u32, settings: &ArtifactSettings) -> Vec<Span> {
+    let extreme = raw.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    if extreme == 0.0 {return Vec::new();}
+    let mut flags = vec![false; raw.len()];
+    let mut run = 0;
+    for i in 0..raw.len() {
+        run = if raw[i].abs() >= CLIP_LEVEL*extreme && i > 0 && raw[i] == raw[i-1] {run + 1} else {0};
+        //the run counts the repeats, so the first sample of it is run back
+        if run + 1 >= settings.clip_run as usize {
+            for j in i-run..i+1 {flags[j] = true;}
+        }
+    }
+    runs(&flags, sample_rate, ArtifactKind::Clipping, Some(channel), None)
+}
+
+fn flatline(raw: &[f32], channel: usize, sample_rate: u32, settings: &ArtifactSettings) -> Vec<Span> {
+    let window = std::cmp::max(2, (settings.flat_s*sample_rate as f32) as usize);
+    let mut flags = vec![false; raw.len()];
+    let mut start = 0;
+    while start + window <= raw.len() {
+        let (low, high) = raw[start..start+window].iter().fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |r, x| (r.0.min(*x), r.1.max(*x)));
+        if high - low < settings.flat_uv {
+            for f in flags[start..start+window].iter_mut() {*f = true;}
+        }
+        start += window/2;
+    }
+    runs(&flags, sample_rate, ArtifactKind::Flatline, Some(channel), None)
+}
+
+//the share of each window's variance in a single dft bin at the mains frequency, a pure hum gives a half
+//so this is doubled to run from 0 to 1
+fn line_noise(raw: &[f32], channel: usize, sample_rate: u32, mains_hz: f32, settings: &ArtifactSettings) -> Vec<Span> {
+    let window = (LINE_WINDOW_S*sample_rate as f64) as usize;
+    if window < 2 || mains_hz*2.0 >= sample_rate as f32 {return Vec::new();}
+    let mut flags = vec![false; raw.len()];
+    let mut start = 0;
+    while start + window <= raw.len() {
+        let x = &raw[start..start+window];
+        let mean = x.iter().map(|v| *v as f64).sum::<f64>()/window as f64;
+        let (mut re, mut im, mut energy) = (0.0f64, 0.0f64, 0.0f64);
+        for (n, v) in x.iter().enumerate() {
+            let v = *v as f64 - mean;
+            let phase = 2.0*std::f64::consts::PI*mains_hz as f64*n as f64/sample_rate as f64;
+            re += v*phase.cos();
+            im -= v*phase.sin();
+            energy += v*v;
+        }
+        if energy > 0.0 && 2.0*(re*re + im*im)/(window as f64*energy) > settings.line_fraction as f64 {
+            for f in flags[start..start+window].iter_mut() {*f = true;}
+        }
+        start += window/2;
+    }
+    runs(&flags, sample_rate, ArtifactKind::LineNoise, Some(channel), None)
+}
+
+//short large deflections on a frontal channel
+fn blinks(filtered: &[f32], sample_rate: u32, settings: &ArtifactSettings) -> Vec<Span> {
+    let rate = sample_rate as f64;
+    let smooth = running_mean(filtered, std::cmp::max(1, (BLINK_SMOOTH_S*rate) as usize));
+    let baseline = running_mean(filtered, std::cmp::max(1, (BLINK_BASELINE_S*rate) as usize));
+    let flags: Vec<bool> = smooth.iter().zip(baseline.iter()).map(|(s, b)| (s - b).abs() > settings.blink_uv).collect();
+    runs(&flags, sample_rate, ArtifactKind::Blink, None, Some(BLINK_MAX_S))
+}
+
+//OpenBCI boards only send the accelerometer every few samples and write zeros in between,
+//so a row of all zeros holds the last reading
+fn motion(aux: &[Vec<f32>], sample_rate: u32, settings: &ArtifactSettings) -> Vec<Span> {
+    let len = aux.iter().map(|a| a.len()).min().unwrap_or(0);
+    if len == 0 {return Vec::new();}
+    let mut held = aux.iter().map(|a| a[..len].to_vec()).collect::<Vec<Vec<f32>>>();
+    for i in 1..len {
+        if held.iter().all(|a| a[i] == 0.0) {
+            for a in held.iter_mut() {a[i] = a[i-1];}
+        }
+    }
+    let window = std::cmp::max(1, (MOTION_WINDOW_S*sample_rate as f64) as usize);
+    let means: Vec<Vec<f32>> = held.iter().map(|a| running_mean(a, window)).collect();
+    let flags: Vec<bool> = (0..len).map(|i| {
+        held.iter().zip(means.iter()).map(|(a, m)| (a[i] - m[i])*(a[i] - m[i])).sum::<f32>().sqrt() > settings.motion_g
+    }).collect();
+    runs(&flags, sample_rate, ArtifactKind::Motion, None, None)
+}
+
+//every enabled detector over the whole recording. raw is as read, the rails and hum show best there,
+//blinks are looked for in the filtered data so slow drifts don't count
+pub fn detect(raw: &[Vec<f32>], filtered: &[Vec<f32>], info: &[ChannelInfo], aux: &[Vec<f32>], sample_rate: u32, mains_hz: f32, settings: &ArtifactSettings) -> Vec<Span> {
+    let enabled = |kind: ArtifactKind| ArtifactKind::ALL.iter().position(|k| *k == kind).map(|i| settings.enabled[i]).unwrap_or(false);
+    let mut spans = Vec::<Span>::new();
+    for (channel, x) in raw.iter().enumerate() {
+        if enabled(ArtifactKind::Clipping) {spans.extend(clipping(x, channel, sample_rate, settings));}
+        if enabled(ArtifactKind::Flatline) {spans.extend(flatline(x, channel, sample_rate, settings));}
+        if enabled(ArtifactKind::LineNoise) {spans.extend(line_noise(x, channel, sample_rate, mains_hz, settings));}
+    }
+    if enabled(ArtifactKind::Blink) {
+        for (channel, ci) in info.iter().enumerate() {
+            let frontal = ci.xy().map(|xy| xy.1 >= FRONTAL_Y).unwrap_or(false);
+            if frontal && channel < filtered.len() {spans.extend(blinks(&filtered[channel], sample_rate, settings));}
+        }
+    }
+    if enabled(ArtifactKind::Motion) {spans.extend(motion(aux, sample_rate, settings));}
+    merge(spans)
+}
+
+//overlapping spans of the same kind on the same channel become one, in order of start
+fn merge(mut spans: Vec<Span>) -> Vec<Span> {
+    spans.sort_by(|a, b| a.start_s.partial_cmp(&b.start_s).unwrap_or(std::cmp::Ordering::Equal));
+    let mut merged = Vec::<Span>::new();
+    for span in spans {
+        match merged.iter_mut().rev().find(|m| m.kind == span.kind && m.channel == span.channel && m.end_s >= span.start_s) {
+            Some(m) => {m.end_s = m.end_s.max(span.end_s); continue;},
+            None => {},
+        }
+        merged.push(span);
+    }
+    merged
+}
+
+//how many spans of each kind, for the panel
+pub fn summary(spans: &[Span]) -> String {
+    if spans.is_empty() {return String::from("No artifacts found.");}
+    let total: f64 = spans.iter().map(|s| s.end_s - s.start_s).sum();
+    let counts: Vec<String> = ArtifactKind::ALL.iter()
+        .map(|k| (k, spans.iter().filter(|s| s.kind == *k).count()))
+        .filter(|&(_, n)| n > 0)
+        .map(|(k, n)| format!("{} {}", n, k.name().to_lowercase()))
+        .collect();
+    format!("{} spans, {:.1} s in all: {}", spans.len(), total, counts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 250;
+
+    //only the one detector switched on
+    fn only(kind: ArtifactKind) -> ArtifactSettings {
+        let mut settings = ArtifactSettings::default();
+        settings.enabled = [false; 5];
+        settings.enabled[ArtifactKind::ALL.iter().position(|k| *k == kind).unwrap()] = true;
+        settings
+    }
+
+    fn tone(hz: f32, size: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds*RATE as f32) as usize).map(|i| size*(2.0*std::f32::consts::PI*hz*i as f32/RATE as f32).sin()).collect()
+    }
+
+    fn run(x: Vec<f32>, settings: &ArtifactSettings) -> Vec<Span> {
+        detect(&[x], &[], &[], &[], RATE, 50.0, settings)
+    }
+
+    #[test]
+    fn rail_runs_count_from_the_set_length() {
+        let settings = only(ArtifactKind::Clipping);
+        let mut x = tone(7.0, 50.0, 4.0);
+        //clip_run samples on the positive rail at 2 s, one fewer on the negative one at 3 s
+        for i in 500..500+settings.clip_run as usize {x[i] = 100.0;}
+        for i in 750..750+settings.clip_run as usize-1 {x[i] = -100.0;}
+        let spans = run(x, &settings);
+        assert_eq!(spans.len(), 1);
+        let span = spans[0];
+        assert!(span.kind == ArtifactKind::Clipping && span.channel == Some(0));
+        assert!(span.overlaps(2.0, 2.012));
+        assert!(span.start_s >= 2.0 - PAD_S - 1e-6 && span.end_s <= 2.012 + PAD_S + 1e-6, "{} to {}", span.start_s, span.end_s);
+    }
+
+    #[test]
+    fn flat_stretches_count_from_the_set_length() {
+        let settings = only(ArtifactKind::Flatline);
+        let mut x = tone(7.0, 20.0, 8.0);
+        //a second of flat line from 2 s and a shorter stretch than flat_s from 5 s
+        for v in x[500..750].iter_mut() {*v = 3.0;}
+        let short = (settings.flat_s*RATE as f32) as usize - 10;
+        for v in x[1250..1250+short].iter_mut() {*v = 3.0;}
+        let spans = run(x, &settings);
+        assert_eq!(spans.len(), 1, "{:?}", spans.iter().map(|s| (s.start_s, s.end_s)).collect::<Vec<_>>());
+        assert!(spans[0].kind == ArtifactKind::Flatline);
+        assert!(spans[0].start_s >= 2.0 - PAD_S - 1e-6 && spans[0].end_s <= 3.0 + PAD_S + 1e-6);
+        assert!(spans[0].overlaps(2.2, 2.8));
+    }
+
+    #[test]
+    fn mains_hum_above_the_set_share_is_flagged() {
+        let settings = only(ArtifactKind::LineNoise);
+        //hum as strong as the rest of the signal from 4 s, only a faint hum before
+        let background = tone(7.0, 10.0, 8.0);
+        let hum = tone(50.0, 10.0, 8.0);
+        let x: Vec<f32> = (0..background.len()).map(|i| background[i] + if i >= 1000 {hum[i]} else {0.3*hum[i]}).collect();
+        let spans = run(x, &settings);
+        assert_eq!(spans.len(), 1);
+        assert!(spans[0].kind == ArtifactKind::LineNoise && spans[0].channel == Some(0));
+        assert!(spans[0].start_s >= 3.0 && spans[0].start_s <= 4.0, "{}", spans[0].start_s);
+        assert!((spans[0].end_s - 8.0).abs() < 1e-6);
+        assert!(!spans[0].overlaps(0.0, 3.0));
+    }
+}
@@ -0,0 +1,147 @@
+use std;
+use rustfft;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Band {
+    pub name: String,
+    pub low_hz: f32,
+    pub high_hz: f32,
+}
+
+impl Band {
+    pub fn new(name: &str, low_hz: f32, high_hz: f32) -> Band {
+        Band{
+            name: String::from(name),
+            low_hz: low_hz,
+            high_hz: high_hz,
+        }
+    }
+}
+
+//the usual clinical EEG bands
+pub fn default_bands() -> Vec<Band> {
+    vec![
+        Band::new("delta", 1.0, 4.0),
+        Band::new("theta", 4.0, 8.0),
+        Band::new("alpha", 8.0, 13.0),
+        Band::new("beta", 13.0, 30.0),
+        Band::new("gamma", 30.0, 45.0),
+    ]
+}
+
+//a ratio between two bands, stored as indices into the band list
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BandRatio {
+    pub numerator: usize,
+    pub denominator: usize,
+}
+
+pub fn default_ratios() -> Vec<BandRatio> {
+    vec![BandRatio{numerator: 1, denominator: 3}] //theta/beta
+}
+
+impl BandRatio {
+    pub fn name(&self, bands: &[Band]) -> String {
+        format!("{}/{}", bands[self.numerator].name, bands[self.denominator].name)
+    }
+    pub fn value(&self, powers: &[f32]) -> f32 {
+        let d = powers[self.denominator];
+        if d > 0.0 {powers[self.numerator]/d} else {0.0}
+    }
+}
+
+//integrates the one sided power spectrum over each band
+//spectrum is the raw fft output, zero padding included, bin_hz is the width of a single bin and window_energy is
+//the sum of the squared window coefficients, so by Parseval a sine of amplitude a gives a*a/2 whatever the window or padding
+//every bin but DC and nyquist stands for its negative frequency twin too
+pub fn band_powers(spectrum: &[rustfft::num_complex::Complex<f32>], bin_hz: f32, bands: &[Band], window_energy: f32) -> Vec<f32> {
+    let n = spectrum.len();
+    let mut powers = vec![0.0 as f32; bands.len()];
+    if n == 0 || window_energy <= 0.0 {return powers;}
+    let scale = 1.0 / (n as f32 * window_energy);
+    for (b, band) in bands.iter().enumerate() {
+        let first = std::cmp::max((band.low_hz / bin_hz).ceil() as usize, 1);
+        let last = std::cmp::min((band.high_hz / bin_hz).ceil() as usize, n/2 + 1);
+        for k in first..last {
+            let sides = if 2*k == n {1.0} else {2.0};
+            powers[b] += spectrum[k].norm_sqr() * sides * scale;
+        }
+    }
+    powers
+}
+
+//rolling history of band powers for one drawer, one entry per stft frame
+pub struct BandPowerHistory {
+    pub frames: std::collections::VecDeque<(u64, Vec<f32>)>,
+    pub capacity: usize,
+}
+
+impl BandPowerHistory {
+    pub fn new(capacity: usize) -> BandPowerHistory {
+        BandPowerHistory{
+            frames: std::collections::VecDeque::with_capacity(capacity),
+            capacity: capacity,
+        }
+    }
+    pub fn push(&mut self, ticks: u64, powers: Vec<f32>) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((ticks, powers));
+    }
+    pub fn latest(&self) -> Option<&Vec<f32>> {
+        self.frames.back().map(|f| &f.1)
+    }
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+    //the power of a single band over time
+    pub fn trace(&self, band: usize) -> Vec<f32> {
+        self.frames.iter().map(|f| if band < f.1.len() {f.1[band]} else {0.0}).collect()
+    }
+    pub fn ratio_trace(&self, ratio: &BandRatio) -> Vec<f32> {
+        self.frames.iter().map(|f| ratio.value(&f.1)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dftwindower::{WindowShape};
+
+    //a sine's band power from one windowed and zero padded frame
+    fn sine_power(shape: WindowShape, len: usize, fft_len: usize) -> f32 {
+        let (sample_rate, amplitude, hz) = (256.0f32, 3.0f32, 20.0f32);
+        let window = shape.coefficients(len as u32);
+        let mut signal: Vec<rustfft::num_complex::Complex<f32>> = (0..fft_len).map(|i| {
+            let x = if i < len {amplitude*(2.0*std::f32::consts::PI*hz*i as f32/sample_rate).sin()*window[i]} else {0.0};
+            rustfft::num_complex::Complex{re: x, im: 0.0}
+        }).collect();
+        let mut spectrum = signal.clone();
+        rustfft::FFTplanner::new(false).plan_fft(fft_len).process(&mut signal, &mut spectrum);
+        let energy: f32 = window.iter().map(|w| w*w).sum();
+        band_powers(&spectrum, sample_rate/fft_len as f32, &[Band::new("around", 10.0, 30.0)], energy)[0]
+    }
+
+    #[test]
+    fn sine_power_is_half_amplitude_squared_for_any_window_or_padding() {
+        let expected = 3.0f32*3.0/2.0;
+        for shape in &[WindowShape::Rectangular, WindowShape::Hann, WindowShape::FlatTop] {
+            for fft_len in &[256, 1024] {
+                let power = sine_power(*shape, 256, *fft_len);
+                assert!((power - expected).abs() < 0.02*expected, "{} with fft {}: {} not {}", shape.name(), fft_len, power, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn nyquist_is_counted_once() {
+        //alternating samples put all their power in the nyquist bin, a cosine of amplitude 1 has power 1 there
+        let n = 64;
+        let mut signal: Vec<rustfft::num_complex::Complex<f32>> = (0..n).map(|i| rustfft::num_complex::Complex{re: if i % 2 == 0 {1.0} else {-1.0}, im: 0.0}).collect();
+        let mut spectrum = signal.clone();
+        rustfft::FFTplanner::new(false).plan_fft(n).process(&mut signal, &mut spectrum);
+        let power = band_powers(&spectrum, 1.0, &[Band::new("top", 1.0, 40.0)], n as f32)[0];
+        assert!((power - 1.0).abs() < 1e-4, "{}", power);
+    }
+}
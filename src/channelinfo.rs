@@ -0,0 +1,115 @@
+use std;
+use regex;
+
+
+#[derive(Clone)]
+pub struct ChannelInfo {
+    pub label: String,
+    pub position: Option<String>, //10-20 electrode name, see electrode_position
+    pub units: String,
+    pub gain: f32, //raw samples are multiplied by this when they are loaded
+}
+
+impl ChannelInfo {
+    pub fn new(index: u32, units: &str) -> ChannelInfo {
+        ChannelInfo{
+            label: format!("Ch {}", index+1),
+            position: None,
+            units: String::from(units),
+            gain: 1.0,
+        }
+    }
+    pub fn xy(&self) -> Option<(f32, f32)> {
+        self.position.as_ref().and_then(|p| electrode_position(p))
+    }
+}
+
+
+//2D positions of the 10-20 electrodes on a flattened head, x to the right ear, y to the nose
+//distances from Cz are proportional to the arc length, each 10% step is 0.2 so Fpz/T7/Oz/T8 sit at radius 0.8
+const ELECTRODES: [(&'static str, f32, f32); 31] = [
+    ("Nz", 0.0, 1.0),
+    ("Fpz", 0.0, 0.8),
+    ("Fp1", -0.247, 0.761),
+    ("Fp2", 0.247, 0.761),
+    ("AFz", 0.0, 0.6),
+    ("F7", -0.647, 0.470),
+    ("F3", -0.33, 0.43),
+    ("Fz", 0.0, 0.4),
+    ("F4", 0.33, 0.43),
+    ("F8", 0.647, 0.470),
+    ("FCz", 0.0, 0.2),
+    ("T7", -0.8, 0.0),
+    ("C3", -0.4, 0.0),
+    ("Cz", 0.0, 0.0),
+    ("C4", 0.4, 0.0),
+    ("T8", 0.8, 0.0),
+    ("CPz", 0.0, -0.2),
+    ("P7", -0.647, -0.470),
+    ("P3", -0.33, -0.43),
+    ("Pz", 0.0, -0.4),
+    ("P4", 0.33, -0.43),
+    ("P8", 0.647, -0.470),
+    ("POz", 0.0, -0.6),
+    ("O1", -0.247, -0.761),
+    ("Oz", 0.0, -0.8),
+    ("O2", 0.247, -0.761),
+    ("Iz", 0.0, -1.0),
+    ("A1", -0.98, -0.1),
+    ("A2", 0.98, -0.1),
+    ("M1", -0.95, -0.3),
+    ("M2", 0.95, -0.3),
+];
+
+//older names for the same sites
+const ALIASES: [(&'static str, &'static str); 4] = [("T3", "T7"), ("T4", "T8"), ("T5", "P7"), ("T6", "P8")];
+
+pub fn electrode_position(name: &str) -> Option<(f32, f32)> {
+    let name = ALIASES.iter().find(|a| a.0.eq_ignore_ascii_case(name)).map(|a| a.1).unwrap_or(name);
+    ELECTRODES.iter().find(|e| e.0.eq_ignore_ascii_case(name)).map(|e| (e.1, e.2))
+}
+
+
+//the montage file sits next to the data file, either as <data file>.montage or montage.txt in the same folder
+pub fn montage_path_for(data_path: &std::path::Path) -> Option<std::path::PathBuf> {
+    let own = data_path.with_extension("montage");
+    if own.is_file() {return Some(own);}
+    let shared = data_path.with_file_name("montage.txt");
+    if shared.is_file() {return Some(shared);}
+    None
+}
+
+//reads a montage file and overwrites the matching entries of info
+//one channel per line: index, label[, position[, units[, gain]]] with 1 based indices,
+//lines starting with % are comments, a position of - means none and a missing position uses the label
+pub fn apply_montage(path: &std::path::Path, info: &mut Vec<ChannelInfo>) -> Result<(), String> {
+    use std::io::BufRead;
+    let f = std::fs::File::open(path).map_err(|e| format!("Unable to open montage file {:?}: {}", path, e))?;
+    let re = regex::Regex::new(r"[ \t]*,[ \t]*").unwrap();
+    for (n, linew) in std::io::BufReader::new(f).lines().enumerate() {
+        let line = linew.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if line.len() == 0 || line.starts_with('%') {continue;}
+        let fields: Vec<&str> = re.split(line).collect();
+        let index = fields[0].parse::<usize>().map_err(|_| format!("Montage line {}: bad channel index {:?}", n+1, fields[0]))?;
+        if index == 0 || index > info.len() {
+            println!("Montage line {}: channel {} is not in the file, ignoring it.", n+1, index);
+            continue;
+        }
+        let ref mut channel = info[index-1];
+        if let Some(label) = fields.get(1) {
+            channel.label = label.to_string();
+            channel.position = if electrode_position(label).is_some() {Some(label.to_string())} else {None};
+        }
+        if let Some(position) = fields.get(2) {
+            channel.position = if *position == "-" {None} else {Some(position.to_string())};
+        }
+        if let Some(units) = fields.get(3) {
+            channel.units = units.to_string();
+        }
+        if let Some(gain) = fields.get(4) {
+            channel.gain = gain.parse::<f32>().map_err(|_| format!("Montage line {}: bad gain {:?}", n+1, gain))?;
+        }
+    }
+    Ok(())
+}
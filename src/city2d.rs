@@ -43,6 +43,18 @@ impl City2D {
         }
     }
 
+    pub fn set_pixel(&mut self, x: u32, y: u32, red: u8, green: u8, blue: u8){
+        let offset = (4*(x + y*self.w)) as usize;
+        self.data[offset]=red;
+        self.data[offset+1]=green;
+        self.data[offset+2]=blue;
+    }
+
+    //rows from the top, without the alpha channel which is never written
+    pub fn rgb(&self) -> Vec<u8>{
+        self.data.chunks(4).flat_map(|p| p[..3].iter().cloned()).collect()
+    }
+
     pub fn into_vec(self) -> Vec<u8>{
         self.data
     }
@@ -0,0 +1,258 @@
+use std;
+use rustfft;
+
+use appstate::{FilterData};
+use waveformdrawer::{WaveformDrawerSettings, ColumnAnalyser, Transform};
+use city2d::City2D;
+use panelimage;
+use artifacts::{Span};
+
+//how far each window moves on from the last, as a fraction of its length
+const STEP_FRACTION: f32 = 0.5;
+
+//what is compared between two channels, from their frames at the same times
+#[derive(Clone, Copy, PartialEq)]
+pub enum Measure {
+    Msc,
+    ImaginaryCoherence,
+    Plv,
+}
+
+impl Measure {
+    pub const ALL: [Measure; 3] = [Measure::Msc, Measure::ImaginaryCoherence, Measure::Plv];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Measure::Msc => "Magnitude squared coherence",
+            Measure::ImaginaryCoherence => "Imaginary coherence",
+            Measure::Plv => "Phase locking value",
+        }
+    }
+
+    //imaginary coherence is signed, it says which channel leads
+    pub fn signed(&self) -> bool {
+        *self == Measure::ImaginaryCoherence
+    }
+}
+
+//what the connectivity panel is set to
+#[derive(Clone)]
+pub struct ConnectivitySettings {
+    pub measure: Measure,
+    pub pair: (usize, usize), //the channels plotted against frequency
+    pub span_s: f32, //how much data before the playback position goes in
+    pub band: (f32, f32), //Hz, averaged over for the matrix
+    pub live: bool, //recompute about once a second
+}
+
+impl Default for ConnectivitySettings {
+    fn default() -> ConnectivitySettings {
+        ConnectivitySettings{
+            measure: Measure::Msc,
+            pair: (0, 1),
+            span_s: 10.0,
+            band: (8.0, 12.0),
+            live: false,
+        }
+    }
+}
+
+//how many samples before the end go in
+pub fn span_samples(settings: &ConnectivitySettings, sample_rate: u32) -> usize {
+    (settings.span_s*sample_rate as f32) as usize
+}
+
+//the stft frames of one channel ending at each of ends, every channel has its frames at the same times
+fn frames(samples: &[f32], ends: &[usize], window_len: usize, analyser: &ColumnAnalyser, drawer: &WaveformDrawerSettings, fd: &FilterData) -> Vec<Vec<rustfft::num_complex::Complex<f32>>> {
+    ends.iter().map(|&last| {
+        let mut signal: Vec<rustfft::num_complex::Complex<f32>> = samples[last-window_len..last].iter().map(|x| rustfft::num_complex::Complex{re: *x, im: 0.0}).collect();
+        analyser.spectrum(&mut signal, drawer, fd)
+    }).collect()
+}
+
+//the measure at each of the first rows bins, averaged over the frames
+//with Sxy the mean cross spectrum:
+//  MSC = |Sxy|^2/(Sxx Syy), imaginary coherence = Im(Sxy)/sqrt(Sxx Syy), PLV = |mean of Sxy/|Sxy|| frame by frame
+fn compare(measure: Measure, a: &[Vec<rustfft::num_complex::Complex<f32>>], b: &[Vec<rustfft::num_complex::Complex<f32>>], bins: &[usize]) -> Vec<f32> {
+    bins.iter().map(|&k| {
+        let mut cross = rustfft::num_complex::Complex{re: 0.0f32, im: 0.0};
+        let mut phase = rustfft::num_complex::Complex{re: 0.0f32, im: 0.0};
+        let (mut pa, mut pb) = (0.0f32, 0.0f32);
+        for (x, y) in a.iter().zip(b.iter()) {
+            let c = x[k]*y[k].conj();
+            cross = cross + c;
+            let norm = c.norm();
+            if norm > 0.0 {phase = phase + c/norm;}
+            pa += x[k].norm_sqr();
+            pb += y[k].norm_sqr();
+        }
+        let power = pa*pb;
+        match measure {
+            _ if power <= 0.0 => 0.0,
+            Measure::Msc => cross.norm_sqr()/power,
+            Measure::ImaginaryCoherence => cross.im/power.sqrt(),
+            Measure::Plv => phase.norm()/a.len() as f32,
+        }
+    }).collect()
+}
+
+//coherence or phase locking between channels over sliding windows
+pub struct Connectivity {
+    pub measure: Measure,
+    pub freqs: Vec<f32>, //Hz, one per bin below nyquist
+    pub spectrum: Vec<f32>, //the chosen pair against frequency
+    pub matrix: Vec<Vec<f32>>, //every pair averaged over the band, 1 (or 0 when signed) on the diagonal
+    pub band: (f32, f32),
+    pub frames: usize,
+    pub rejected: usize, //windows that touched a flagged span
+    pub end_s: f32, //where the data that went in stops
+    pub computed_ms: u64, //ticks when this was worked out, for live updates
+}
+
+impl Connectivity {
+    //windows of the drawer's length and taper step back by half a window from end_ms, over span_s,
+    //within a call each channel is transformed once and every pair shares its frames, nothing is kept from one call to the next
+    //channels may be copies that begin first_sample into the source, windows overlapping any of artifacts are left out
+    pub fn compute(channels: &[&[f32]], first_sample: usize, sample_rate: u32, end_ms: u64, settings: &ConnectivitySettings, drawer: &WaveformDrawerSettings, fd: &FilterData, artifacts: &[Span]) -> Result<Connectivity, String> {
+        let n = channels.len();
+        if n < 2 {return Err(String::from("This needs at least two channels."));}
+        if settings.pair.0 >= n || settings.pair.1 >= n {return Err(String::from("Pick two channels to compare."));}
+
+        //phase is needed, so whatever the drawer shows this is its plain stft
+        let mut drawer = drawer.clone();
+        drawer.transform = Transform::Stft;
+        let mut analyser = ColumnAnalyser::new(drawer.dtft_samples);
+        analyser.prepare(&drawer, fd, sample_rate);
+        let window_len = drawer.dtft_samples as usize;
+        let step = std::cmp::max(1, (window_len as f32*STEP_FRACTION) as usize);
+        let shortest = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        let end = std::cmp::min(((end_ms*sample_rate as u64/1000) as usize).saturating_sub(first_sample), shortest);
+        let span = std::cmp::min(span_samples(settings, sample_rate), end);
+        if span < window_len + step {return Err(format!("Not enough data yet, {:.1} s are needed.", (window_len + step) as f32/sample_rate as f32));}
+        let count = (span - window_len)/step + 1;
+        let rate = sample_rate as f64;
+        let ends: Vec<usize> = (0..count).map(|s| end - (count - 1 - s)*step)
+            .filter(|last| !artifacts.iter().any(|a| a.overlaps((first_sample + last - window_len) as f64/rate, (first_sample + last) as f64/rate)))
+            .collect();
+        if ends.len() < 2 {return Err(format!("{} of the {} windows touch flagged artifacts.", count - ends.len(), count));}
+
+        let all: Vec<Vec<Vec<rustfft::num_complex::Complex<f32>>>> = channels.iter().map(|c| frames(c, &ends, window_len, &analyser, &drawer, fd)).collect();
+
+        let bin_hz = drawer.bin_hz(sample_rate);
+        let rows = drawer.fft_len() as usize/2;
+        let freqs: Vec<f32> = (0..rows).map(|k| k as f32*bin_hz).collect();
+        let in_band: Vec<usize> = (0..rows).filter(|k| freqs[*k] >= settings.band.0 && freqs[*k] <= settings.band.1).collect();
+        if in_band.is_empty() {return Err(format!("The band has no bins in it, they are {:.2} Hz apart.", bin_hz));}
+
+        let spectrum = compare(settings.measure, &all[settings.pair.0], &all[settings.pair.1], &(0..rows).collect::<Vec<usize>>());
+        let diagonal = if settings.measure.signed() {0.0} else {1.0};
+        let mut matrix = vec![vec![diagonal; n]; n];
+        for i in 0..n {
+            for j in i+1..n {
+                let values = compare(settings.measure, &all[i], &all[j], &in_band);
+                let mean = values.iter().sum::<f32>()/values.len() as f32;
+                matrix[i][j] = mean;
+                //Im(Syx) = -Im(Sxy), the rest are symmetric
+                matrix[j][i] = if settings.measure.signed() {-mean} else {mean};
+            }
+        }
+
+        Ok(Connectivity{
+            measure: settings.measure,
+            freqs: freqs,
+            spectrum: spectrum,
+            matrix: matrix,
+            band: settings.band,
+            frames: ends.len(),
+            rejected: count - ends.len(),
+            end_s: (first_sample + end) as f32/sample_rate as f32,
+            computed_ms: 0,
+        })
+    }
+
+    //row i column j is channel i against channel j, the first channel at the top
+    pub fn matrix_image(&self) -> City2D {
+        let n = self.matrix.len() as u32;
+        let mut image = City2D::new(n, n);
+        for (i, row) in self.matrix.iter().enumerate() {
+            for (j, v) in row.iter().enumerate() {
+                let (r, g, b) = if self.measure.signed() {panelimage::diverging(*v)} else {panelimage::sequential(*v)};
+                image.set_pixel(j as u32, i as u32, r, g, b);
+            }
+        }
+        image
+    }
+
+    pub fn colorbar(&self) -> City2D {
+        if self.measure.signed() {panelimage::colorbar(128, panelimage::diverging, -1.0, 1.0)}
+        else {panelimage::colorbar(128, panelimage::sequential, 0.0, 1.0)}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Frame = Vec<rustfft::num_complex::Complex<f32>>;
+
+    //frames with one bin that matters, its size and phase given frame by frame
+    fn frames_of(values: &[(f32, f32)]) -> Vec<Frame> {
+        values.iter().map(|&(size, phase)| vec![
+            rustfft::num_complex::Complex{re: 0.0, im: 0.0},
+            rustfft::num_complex::Complex::from_polar(&size, &phase),
+        ]).collect()
+    }
+
+    //a fixed pseudo random sequence in [0, 1)
+    fn noise(seed: u64, len: usize) -> Vec<f32> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 40) as f32/(1u64 << 24) as f32
+        }).collect()
+    }
+
+    fn measure(measure: Measure, a: &[Frame], b: &[Frame]) -> f32 {
+        compare(measure, a, b, &[1])[0]
+    }
+
+    #[test]
+    fn identical_channels_are_fully_coherent_with_no_imaginary_part() {
+        let sizes = noise(1, 200);
+        let phases = noise(2, 200);
+        let a = frames_of(&sizes.iter().zip(phases.iter()).map(|(s, p)| (0.5 + s, 2.0*std::f32::consts::PI*p)).collect::<Vec<_>>());
+        assert!((measure(Measure::Msc, &a, &a) - 1.0).abs() < 1e-4);
+        assert!(measure(Measure::ImaginaryCoherence, &a, &a).abs() < 1e-4);
+        assert!((measure(Measure::Plv, &a, &a) - 1.0).abs() < 1e-4);
+        //an empty bin has nothing to compare
+        assert_eq!(compare(Measure::Msc, &a, &a, &[0])[0], 0.0);
+    }
+
+    #[test]
+    fn quarter_cycle_shift_is_all_imaginary() {
+        let sizes = noise(3, 200);
+        let phases = noise(4, 200);
+        let values: Vec<(f32, f32)> = sizes.iter().zip(phases.iter()).map(|(s, p)| (0.5 + s, 2.0*std::f32::consts::PI*p)).collect();
+        let a = frames_of(&values);
+        let lagging = frames_of(&values.iter().map(|&(s, p)| (2.0*s, p - std::f32::consts::PI/2.0)).collect::<Vec<_>>());
+        //a leads, so Im(a conj(b)) is positive, and the other way round it flips
+        assert!((measure(Measure::ImaginaryCoherence, &a, &lagging) - 1.0).abs() < 1e-4);
+        assert!((measure(Measure::ImaginaryCoherence, &lagging, &a) + 1.0).abs() < 1e-4);
+        assert!((measure(Measure::Msc, &a, &lagging) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn independent_noise_is_barely_coherent() {
+        let frames = 400;
+        let channel = |seed: u64| {
+            let sizes = noise(seed, frames);
+            let phases = noise(seed + 100, frames);
+            frames_of(&sizes.iter().zip(phases.iter()).map(|(s, p)| (0.5 + s, 2.0*std::f32::consts::PI*p)).collect::<Vec<_>>())
+        };
+        let (a, b) = (channel(5), channel(6));
+        //unrelated phases leave about 1/frames of coherence by chance
+        assert!(measure(Measure::Msc, &a, &b) < 0.02, "{}", measure(Measure::Msc, &a, &b));
+        assert!(measure(Measure::ImaginaryCoherence, &a, &b).abs() < 0.15);
+        assert!(measure(Measure::Plv, &a, &b) < 0.15);
+    }
+}
@@ -0,0 +1,73 @@
+//channels computed from the raw channels, these are appended after the raw channels
+//so a drawer can select them with settings.channel just like a raw one
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum DerivedChannel {
+    CommonAverage(u32), //channel minus the mean of every channel
+    Reference(u32, u32), //channel re-referenced to another electrode
+    LinkedMastoids(u32, u32, u32), //channel minus the mean of the two mastoid electrodes
+    Bipolar(u32, u32), //difference between two neighbouring electrodes, eg Fp1-F3
+    Laplacian(u32, Vec<u32>), //surface laplacian, channel minus the mean of its neighbours
+    Cleaned(u32, Vec<f32>, f32), //channel rebuilt without artifact components, as weights over every channel plus an offset, see Ica::cleaned
+}
+
+impl DerivedChannel {
+    pub const KIND_NAMES: [&'static str; 5] = ["Common Average", "Reference", "Linked Mastoids", "Bipolar", "Laplacian"];
+
+    pub fn name(&self, raw_names: &[String]) -> String {
+        let n = |c: &u32| raw_names.get(*c as usize).cloned().unwrap_or_else(|| format!("Ch {}", c+1));
+        match *self {
+            DerivedChannel::CommonAverage(ref c) => format!("{} (CAR)", n(c)),
+            DerivedChannel::Reference(ref c, ref r) => format!("{} (ref {})", n(c), n(r)),
+            DerivedChannel::LinkedMastoids(ref c, ref m1, ref m2) => format!("{} (ref {}+{})", n(c), n(m1), n(m2)),
+            DerivedChannel::Bipolar(ref a, ref b) => format!("{}-{}", n(a), n(b)),
+            DerivedChannel::Laplacian(ref c, _) => format!("{} (Laplacian)", n(c)),
+            DerivedChannel::Cleaned(ref c, _, _) => format!("{} (ICA cleaned)", n(c)),
+        }
+    }
+
+    //the raw channel the derived channel is centred on
+    pub fn primary(&self) -> u32 {
+        match *self {
+            DerivedChannel::CommonAverage(c) => c,
+            DerivedChannel::Reference(c, _) => c,
+            DerivedChannel::LinkedMastoids(c, _, _) => c,
+            DerivedChannel::Bipolar(a, _) => a,
+            DerivedChannel::Laplacian(c, _) => c,
+            DerivedChannel::Cleaned(c, _, _) => c,
+        }
+    }
+
+    //compute the whole derived channel from the (filtered) raw channels
+    pub fn compute(&self, channels: &[Vec<f32>]) -> Vec<f32> {
+        let len = channels[0].len();
+        let mean_of = |list: &[u32]| -> Vec<f32> {
+            let mut mean = vec![0.0 as f32; len];
+            if list.is_empty() {return mean;}
+            for c in list {
+                for (m, x) in mean.iter_mut().zip(channels[*c as usize].iter()) {*m += *x;}
+            }
+            for m in mean.iter_mut() {*m /= list.len() as f32;}
+            mean
+        };
+        let minus = |c: u32, reference: &[f32]| -> Vec<f32> {
+            channels[c as usize].iter().zip(reference.iter()).map(|(x, r)| x - r).collect()
+        };
+        match *self {
+            DerivedChannel::CommonAverage(c) => {
+                let all: Vec<u32> = (0..channels.len() as u32).collect();
+                minus(c, &mean_of(&all))
+            },
+            DerivedChannel::Reference(c, r) => minus(c, &channels[r as usize]),
+            DerivedChannel::LinkedMastoids(c, m1, m2) => minus(c, &mean_of(&[m1, m2])),
+            DerivedChannel::Bipolar(a, b) => minus(a, &channels[b as usize]),
+            DerivedChannel::Laplacian(c, ref neighbours) => minus(c, &mean_of(neighbours)),
+            DerivedChannel::Cleaned(_, ref weights, offset) => {
+                let mut cleaned = vec![offset; len];
+                for (w, channel) in weights.iter().zip(channels.iter()) {
+                    for (x, y) in cleaned.iter_mut().zip(channel.iter()) {*x += w*y;}
+                }
+                cleaned
+            },
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use rustfft;
+
+//per frame preprocessing applied to the samples before windowing and the fft
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Detrend {
+    None,
+    Mean, //subtract the mean of the frame
+    Linear, //subtract the least squares straight line through the frame
+}
+
+impl Detrend {
+    pub const ALL: [Detrend; 3] = [Detrend::None, Detrend::Mean, Detrend::Linear];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Detrend::None => "No Detrending",
+            Detrend::Mean => "Mean Removal",
+            Detrend::Linear => "Linear Detrend",
+        }
+    }
+
+    pub fn apply(&self, signal: &mut [rustfft::num_complex::Complex<f32>]) {
+        let n = signal.len();
+        if n == 0 {return;}
+        match *self {
+            Detrend::None => {},
+            Detrend::Mean => {
+                let mean = signal.iter().map(|s| s.re).sum::<f32>() / n as f32;
+                for s in signal.iter_mut() {s.re -= mean;}
+            },
+            Detrend::Linear => {
+                //fit re = mean + slope*(i - centre), the centred index keeps the sums well conditioned
+                let centre = (n as f32 - 1.0) / 2.0;
+                let mean = signal.iter().map(|s| s.re).sum::<f32>() / n as f32;
+                let mut sxy: f32 = 0.0;
+                let mut sxx: f32 = 0.0;
+                for (i, s) in signal.iter().enumerate() {
+                    let x = i as f32 - centre;
+                    sxy += x*(s.re - mean);
+                    sxx += x*x;
+                }
+                let slope = if sxx > 0.0 {sxy/sxx} else {0.0};
+                for (i, s) in signal.iter_mut().enumerate() {
+                    s.re -= mean + slope*(i as f32 - centre);
+                }
+            },
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use std;
+use rustfft;
+use serde::{Deserialize, Deserializer};
 
 
 fn bessel0(x: f32)->f32{
@@ -14,46 +16,292 @@ fn bessel0(x: f32)->f32{
     result+term*term
 }
 
-#[allow(dead_code)]
-pub struct DFTWindower{
- samples: u32,
- kaiser_table: Vec<f32>,
+//chebyshev polynomial of the first kind, outside -1..1 it grows like cosh
+fn chebyshev(n: f64, x: f64)->f64{
+    if x.abs() <= 1.0 {
+        (n*x.acos()).cos()
+    } else if x > 1.0 {
+        (n*x.acosh()).cosh()
+    } else {
+        let sign = if (n as i64) % 2 == 0 {1.0} else {-1.0};
+        sign*(n*(-x).acosh()).cosh()
+    }
 }
 
-impl DFTWindower{
-    pub fn new(samples: u32)->DFTWindower{
-        let mut kaiser_table:Vec<f32> = vec![0.0; samples as usize];
-        let b:f32=std::f32::consts::PI;
-        let d=bessel0(b);
-        for n in 0..(samples){
-            let t:f32=2.0*n as f32/(samples as f32 - 1.0) - 1.0;
-            kaiser_table[n as usize]=bessel0(b*(1.0-t*t).sqrt())/d;
+//the window applied before the stft, the parameters are the ones each window is usually described by
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WindowShape{
+    Rectangular,
+    Hann,
+    Hamming,
+    Nuttall,
+    Sine,
+    Kaiser(f32), //beta, wider main lobe and lower sidelobes as it grows
+    Tukey(f32), //alpha, the fraction of the window that is tapered, 0 is rectangular and 1 is Hann
+    Gaussian(f32), //sigma as a fraction of half the window
+    Blackman,
+    BlackmanHarris,
+    FlatTop,
+    DolphChebyshev(f32), //sidelobe attenuation in dB, every sidelobe is at this level
+}
+
+impl WindowShape{
+    //one of each with its usual parameter, for the drop down
+    pub const ALL: [WindowShape; 12] = [
+        WindowShape::Rectangular,
+        WindowShape::Hann,
+        WindowShape::Hamming,
+        WindowShape::Nuttall,
+        WindowShape::Sine,
+        WindowShape::Kaiser(std::f32::consts::PI),
+        WindowShape::Tukey(0.5),
+        WindowShape::Gaussian(0.4),
+        WindowShape::Blackman,
+        WindowShape::BlackmanHarris,
+        WindowShape::FlatTop,
+        WindowShape::DolphChebyshev(100.0),
+    ];
+
+    pub fn name(&self) -> &'static str{
+        match *self {
+            WindowShape::Rectangular => "Rectangular Window",
+            WindowShape::Hann => "Hann Window",
+            WindowShape::Hamming => "Hamming Window",
+            WindowShape::Nuttall => "Nuttall Window",
+            WindowShape::Sine => "Sine Window",
+            WindowShape::Kaiser(_) => "Kaiser Window",
+            WindowShape::Tukey(_) => "Tukey Window",
+            WindowShape::Gaussian(_) => "Gaussian Window",
+            WindowShape::Blackman => "Blackman Window",
+            WindowShape::BlackmanHarris => "Blackman-Harris Window",
+            WindowShape::FlatTop => "Flat-top Window",
+            WindowShape::DolphChebyshev(_) => "Dolph-Chebyshev Window",
+        }
+    }
+
+    //the same window whatever its parameter, for finding it in ALL
+    pub fn same_kind(&self, other: &WindowShape) -> bool{
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    //label, value and range of the parameter if the window has one
+    pub fn parameter(&self) -> Option<(&'static str, f32, f32, f32)>{
+        match *self {
+            WindowShape::Kaiser(beta) => Some(("beta", beta, 0.0, 20.0)),
+            WindowShape::Tukey(alpha) => Some(("alpha", alpha, 0.0, 1.0)),
+            WindowShape::Gaussian(sigma) => Some(("sigma", sigma, 0.1, 1.0)),
+            WindowShape::DolphChebyshev(db) => Some(("sidelobes (dB)", db, 20.0, 150.0)),
+            _ => None,
+        }
+    }
+
+    pub fn with_parameter(&self, value: f32) -> WindowShape{
+        match *self {
+            WindowShape::Kaiser(_) => WindowShape::Kaiser(value),
+            WindowShape::Tukey(_) => WindowShape::Tukey(value),
+            WindowShape::Gaussian(_) => WindowShape::Gaussian(value),
+            WindowShape::DolphChebyshev(_) => WindowShape::DolphChebyshev(value),
+            other => other,
         }
+    }
+
+    //the symmetric window of len points, peaking at 1
+    pub fn coefficients(&self, len: u32) -> Vec<f32>{
+        if len < 2 {return vec![1.0; len as usize];}
+        let m = (len - 1) as f32;
+        let cosines = |a: &[f32]| -> Vec<f32> {
+            (0..len).map(|i| {
+                a.iter().enumerate().map(|(k, c)| {
+                    let sign = if k % 2 == 0 {1.0} else {-1.0};
+                    sign*c*(2.0*std::f32::consts::PI*k as f32*i as f32/m).cos()
+                }).sum()
+            }).collect()
+        };
+        match *self {
+            WindowShape::Rectangular => vec![1.0; len as usize],
+            WindowShape::Hann => cosines(&[0.5, 0.5]),
+            WindowShape::Hamming => cosines(&[0.53836, 0.46164]),
+            WindowShape::Nuttall => cosines(&[0.355768, 0.487396, 0.144232, 0.012604]),
+            WindowShape::Sine => (0..len).map(|i| (std::f32::consts::PI*i as f32/m).sin()).collect(),
+            WindowShape::Kaiser(beta) => {
+                let d = bessel0(beta);
+                (0..len).map(|i| {
+                    let t = 2.0*i as f32/m - 1.0;
+                    bessel0(beta*(1.0-t*t).max(0.0).sqrt())/d
+                }).collect()
+            },
+            WindowShape::Tukey(alpha) => {
+                let alpha = alpha.max(0.0).min(1.0);
+                (0..len).map(|i| {
+                    let x = i as f32/m;
+                    let x = if x > 0.5 {1.0 - x} else {x}; //symmetric, only the rising edge needs working out
+                    if x < alpha/2.0 {0.5*(1.0 - (2.0*std::f32::consts::PI*x/alpha).cos())} else {1.0}
+                }).collect()
+            },
+            WindowShape::Gaussian(sigma) => (0..len).map(|i| {
+                let t = (2.0*i as f32/m - 1.0)/sigma;
+                (-0.5*t*t).exp()
+            }).collect(),
+            WindowShape::Blackman => cosines(&[0.42, 0.5, 0.08]),
+            WindowShape::BlackmanHarris => cosines(&[0.35875, 0.48829, 0.14128, 0.01168]),
+            WindowShape::FlatTop => cosines(&[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368]),
+            WindowShape::DolphChebyshev(db) => {
+                //the window's spectrum is a chebyshev polynomial, sampled at len points and transformed back
+                let n = len as f64;
+                let order = n - 1.0;
+                let r = 10f64.powf(db as f64/20.0);
+                let x0 = ((1.0/order)*r.acosh()).cosh();
+                let spectrum: Vec<f64> = (0..len).map(|k| chebyshev(order, x0*(std::f64::consts::PI*k as f64/n).cos())).collect();
+                let w: Vec<f64> = (0..len).map(|i| {
+                    let t = i as f64 - order/2.0;
+                    //measured from the centre so the spectrum is real, t is a half integer for even lengths
+                    spectrum.iter().enumerate().map(|(k, s)| s*(2.0*std::f64::consts::PI*k as f64*t/n).cos()).sum()
+                }).collect();
+                let max = w.iter().fold(0.0f64, |m, v| m.max(v.abs()));
+                w.iter().map(|v| (v/max) as f32).collect()
+            },
+        }
+    }
+
+    //worked out from a 256 point window, the figures barely change with length
+    pub fn metrics(&self) -> WindowMetrics{
+        const LEN: usize = 256;
+        const OVERSAMPLE: usize = 32;
+        let w = self.coefficients(LEN as u32);
+        let sum: f32 = w.iter().sum();
+        let sum_sq: f32 = w.iter().map(|v| v*v).sum();
+
+        //half a bin off centre, the worst case for a tone between bins
+        let (re, im) = w.iter().enumerate().fold((0.0f32, 0.0f32), |(re, im), (i, v)| {
+            let phase = std::f32::consts::PI*i as f32/LEN as f32;
+            (re + v*phase.cos(), im - v*phase.sin())
+        });
+
+        //the highest peak after the main lobe's first null, on a finely zero padded spectrum
+        let padded = LEN*OVERSAMPLE;
+        let mut signal: Vec<rustfft::num_complex::Complex<f32>> = (0..padded).map(|i| rustfft::num_complex::Complex{re: if i < LEN {w[i]} else {0.0}, im: 0.0}).collect();
+        let mut spectrum = signal.clone();
+        let mut fft_planner = rustfft::FFTplanner::new(false);
+        fft_planner.plan_fft(padded).process(&mut signal, &mut spectrum);
+        let magnitude: Vec<f32> = spectrum[..padded/2].iter().map(|c| c.norm()).collect();
+        let null = (1..magnitude.len()-1).find(|&i| magnitude[i] <= magnitude[i-1] && magnitude[i] < magnitude[i+1]);
+        let sidelobe = match null {
+            Some(null) => magnitude[null..].iter().fold(0.0f32, |m, v| m.max(*v)),
+            None => 0.0,
+        };
+
+        WindowMetrics{
+            coherent_gain: sum/LEN as f32,
+            enbw_bins: LEN as f32*sum_sq/(sum*sum),
+            scalloping_loss_db: -20.0*((re*re + im*im).sqrt()/sum).log10(),
+            sidelobe_db: 20.0*(sidelobe.max(1e-12)/magnitude[0]).log10(),
+        }
+    }
+}
+
+impl Default for WindowShape{
+    fn default() -> WindowShape{
+        WindowShape::Rectangular
+    }
+}
+
+//older presets and sessions stored the window as its index in the drop down
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ShapeOrIndex{
+    Shape(WindowShape),
+    Index(i32),
+}
+
+pub fn shape_or_index<'de, D>(deserializer: D) -> Result<WindowShape, D::Error> where D: Deserializer<'de>{
+    Ok(match ShapeOrIndex::deserialize(deserializer)? {
+        ShapeOrIndex::Shape(shape) => shape,
+        ShapeOrIndex::Index(i) => WindowShape::ALL.get(i as usize).cloned().unwrap_or_default(),
+    })
+}
+
+//how a window trades resolution for leakage
+#[derive(Clone, Copy)]
+pub struct WindowMetrics{
+    pub coherent_gain: f32, //the mean of the window, how much a tone's amplitude is scaled by
+    pub enbw_bins: f32, //equivalent noise bandwidth, how many bins of noise one bin collects
+    pub scalloping_loss_db: f32, //how much a tone half way between bins drops
+    pub sidelobe_db: f32, //the highest sidelobe relative to the main lobe
+}
 
+
+//the window's coefficients for one length, rebuilt when the shape or length changes
+pub struct DFTWindower{
+ shape: WindowShape,
+ coefficients: Vec<f32>,
+}
+
+impl DFTWindower{
+    pub fn new(shape: WindowShape, samples: u32)->DFTWindower{
         DFTWindower{
-            samples: samples,
-            kaiser_table: kaiser_table,
+            shape: shape,
+            coefficients: shape.coefficients(samples),
         }
     }
-    pub fn hann(&self, i: u32, dtft_len: u32)->f32{
-        let sin=(std::f32::consts::PI*i as f32/(dtft_len - 1) as f32 ).sin();
-        sin*sin
+
+    pub fn matches(&self, shape: WindowShape, samples: u32)->bool{
+        self.shape == shape && self.coefficients.len() == samples as usize
     }
-    pub fn hamming(&self, i: u32, dtft_len: u32)->f32{
-        let cos=(std::f32::consts::PI*i as f32/(dtft_len - 1) as f32 ).cos();
-        (0.53836 - 0.46164*cos)
+
+    pub fn len(&self)->usize{
+        self.coefficients.len()
     }
-    pub fn nuttall(&self, i: u32, dtft_len: u32)->f32{
-        let cos2=(2.0*std::f32::consts::PI*i as f32/(dtft_len - 1) as f32 ).cos();
-        let cos4=(4.0*std::f32::consts::PI*i as f32/(dtft_len - 1) as f32 ).cos();
-        let cos6=(6.0*std::f32::consts::PI*i as f32/(dtft_len - 1) as f32 ).cos();
-        (0.355768 - 0.487396*cos2 + 0.144232*cos4 - 0.012604*cos6)
+
+    //the sum of the squared coefficients, what the window does to a signal's power
+    pub fn energy(&self)->f32{
+        self.coefficients.iter().map(|w| w*w).sum()
+    }
+
+    //a signal of another length gets its own window worked out on the spot
+    pub fn apply(&self, signal: &mut Vec<rustfft::num_complex::Complex<f32>>){
+        if self.shape == WindowShape::Rectangular {return;}
+        if signal.len() == self.coefficients.len() {
+            for (s, w) in signal.iter_mut().zip(self.coefficients.iter()) {s.re *= *w;}
+        } else {
+            let coefficients = self.shape.coefficients(signal.len() as u32);
+            for (s, w) in signal.iter_mut().zip(coefficients.iter()) {s.re *= *w;}
+        }
     }
-    pub fn sine(&self, i: u32, dtft_len: u32)->f32{
-        (std::f32::consts::PI*i as f32/(dtft_len - 1) as f32 ).sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(value: f32, expected: f32, tolerance: f32) -> bool {
+        (value - expected).abs() <= tolerance
     }
-    #[allow(unused_variables)]
-    pub fn kaiser(&self, i: u32, dtft_len: u32)->f32{
-        self.kaiser_table[i as usize]
+
+    #[test]
+    fn rectangular_metrics_match_the_textbook() {
+        let m = WindowShape::Rectangular.metrics();
+        assert!(close(m.coherent_gain, 1.0, 1e-6));
+        assert!(close(m.enbw_bins, 1.0, 1e-4), "{}", m.enbw_bins);
+        assert!(close(m.scalloping_loss_db, 3.92, 0.02), "{}", m.scalloping_loss_db);
+        assert!(close(m.sidelobe_db, -13.26, 0.1), "{}", m.sidelobe_db);
+    }
+
+    #[test]
+    fn hann_metrics_match_the_textbook() {
+        //symmetric rather than periodic, so a touch wider than the textbook figures
+        let m = WindowShape::Hann.metrics();
+        assert!(close(m.coherent_gain, 0.5, 0.005));
+        assert!(close(m.enbw_bins, 1.5, 0.01), "{}", m.enbw_bins);
+        assert!(close(m.scalloping_loss_db, 1.42, 0.02), "{}", m.scalloping_loss_db);
+        assert!(close(m.sidelobe_db, -31.5, 0.2), "{}", m.sidelobe_db);
+    }
+
+    #[test]
+    fn dolph_chebyshev_sidelobes_sit_at_the_attenuation() {
+        //kept well clear of where the f32 spectrum's rounding noise would hide the sidelobes
+        for db in &[40.0, 60.0, 80.0] {
+            let m = WindowShape::DolphChebyshev(*db).metrics();
+            assert!(close(m.sidelobe_db, -db, 0.5), "{} dB: {}", db, m.sidelobe_db);
+        }
     }
 }
@@ -0,0 +1,173 @@
+use std;
+use rustfft;
+
+use appstate::{FilterData};
+use waveformdrawer::{WaveformDrawerSettings, ColumnAnalyser, Transform};
+use annotations::{Annotation};
+use city2d::City2D;
+use panelimage;
+use artifacts::{Span};
+
+//how many time steps an epoch is cut into, whatever its length
+const ERSP_COLUMNS: usize = 200;
+
+//what the ERSP panel is set to
+#[derive(Clone)]
+pub struct ErspSettings {
+    pub label: Option<String>, //the marker type epochs are cut around
+    pub channel: usize,
+    pub pre_s: f32, //epochs run from pre_s before each marker
+    pub post_s: f32, //to post_s after it
+    pub baseline: (f32, f32), //seconds relative to the marker, usually before it
+}
+
+impl Default for ErspSettings {
+    fn default() -> ErspSettings {
+        ErspSettings{
+            label: None,
+            channel: 0,
+            pre_s: 1.0,
+            post_s: 2.0,
+            baseline: (-0.5, 0.0),
+        }
+    }
+}
+
+//event-related spectral perturbation: the power at each time and frequency relative to the baseline, averaged over trials
+pub struct Ersp {
+    pub times: Vec<f32>, //seconds relative to the marker
+    pub freqs: Vec<f32>,
+    pub db: Vec<Vec<f32>>, //indexed by time then frequency
+    pub trials: usize,
+    pub rejected: usize, //epochs that ran off either end of the data or touched a flagged span
+    pub empty_rows: usize, //frequencies with no baseline power in any trial, shown as 0 dB
+}
+
+//adds one trial's power, indexed by column then row, to the sums in dB against the trial's own mean baseline
+//a row with no power over the baseline is left out of this trial only, counts keeps how many trials each row has
+fn accumulate(power: &[Vec<f32>], baseline_columns: &[usize], sum: &mut [Vec<f32>], counts: &mut [usize]) {
+    for k in 0..counts.len() {
+        let baseline = baseline_columns.iter().map(|c| power[*c][k]).sum::<f32>()/baseline_columns.len() as f32;
+        if baseline <= 0.0 {continue;}
+        for (total, column) in sum.iter_mut().zip(power.iter()) {
+            total[k] += 10.0*(column[k].max(1e-30)/baseline).log10();
+        }
+        counts[k] += 1;
+    }
+}
+
+//each row over its own number of trials
+fn average(sum: &[Vec<f32>], counts: &[usize]) -> Vec<Vec<f32>> {
+    sum.iter().map(|column| column.iter().zip(counts.iter()).map(|(v, n)| if *n > 0 {v/ *n as f32} else {0.0}).collect()).collect()
+}
+
+impl Ersp {
+    //every epoch is transformed with a window of the drawer's dtft_samples centred on each time step, tapered the drawer's way,
+    //each trial is divided by its own mean baseline power and put in dB, then the trials are averaged
+    //epochs overlapping any of artifacts are left out
+    pub fn compute(samples: &[f32], sample_rate: u32, events: &[&Annotation], settings: &ErspSettings, drawer: &WaveformDrawerSettings, fd: &FilterData, artifacts: &[Span]) -> Result<Ersp, String> {
+        let dtft_len = drawer.dtft_samples as usize;
+        let rows = std::cmp::min(drawer.dtft_display_samples, drawer.fft_len()/2) as usize;
+        //the wavelets are lined up with the end of the window rather than its centre, so they fall back to the stft here
+        let mut drawer = drawer.clone();
+        if drawer.transform == Transform::Morlet {drawer.transform = Transform::Stft;}
+        let mut analyser = ColumnAnalyser::new(drawer.dtft_samples);
+        analyser.prepare(&drawer, fd, sample_rate);
+        let times: Vec<f32> = (0..ERSP_COLUMNS).map(|c| -settings.pre_s + (settings.pre_s + settings.post_s)*c as f32/(ERSP_COLUMNS - 1) as f32).collect();
+        let baseline_columns: Vec<usize> = (0..ERSP_COLUMNS).filter(|c| times[*c] >= settings.baseline.0 && times[*c] <= settings.baseline.1).collect();
+        if baseline_columns.is_empty() {return Err(String::from("The baseline is outside the epoch."));}
+
+        let mut sum = vec![vec![0.0f32; rows]; ERSP_COLUMNS];
+        let mut counts = vec![0usize; rows];
+        let mut trials = 0;
+        let mut rejected = 0;
+        for event in events {
+            let first = (event.time_s - settings.pre_s as f64)*sample_rate as f64 - dtft_len as f64/2.0;
+            let last = (event.time_s + settings.post_s as f64)*sample_rate as f64 + dtft_len as f64/2.0;
+            if first < 0.0 || last > samples.len() as f64 || artifacts.iter().any(|s| s.overlaps(first/sample_rate as f64, last/sample_rate as f64)) {
+                rejected += 1;
+                continue;
+            }
+
+            let mut power = vec![vec![0.0f32; rows]; ERSP_COLUMNS];
+            for c in 0..ERSP_COLUMNS {
+                let centre = ((event.time_s + times[c] as f64)*sample_rate as f64) as usize;
+                let start = centre - dtft_len/2;
+                let mut signal: Vec<rustfft::num_complex::Complex<f32>> = samples[start..start+dtft_len].iter().map(|s| rustfft::num_complex::Complex{re: *s, im: 0.0}).collect();
+                let spectrum = analyser.spectrum(&mut signal, &drawer, fd);
+                for k in 0..rows {power[c][k] = spectrum[k].norm_sqr();}
+            }
+            accumulate(&power, &baseline_columns, &mut sum, &mut counts);
+            trials += 1;
+        }
+        if trials == 0 {return Err(format!("None of the {} epochs fit inside the data clear of artifacts.", events.len()));}
+
+        Ok(Ersp{
+            times: times,
+            freqs: (0..rows).map(|k| k as f32*drawer.bin_hz(sample_rate)).collect(),
+            db: average(&sum, &counts),
+            trials: trials,
+            rejected: rejected,
+            empty_rows: counts.iter().filter(|n| **n == 0).count(),
+        })
+    }
+
+    //the largest change either way, the colour scale runs from minus this to plus this
+    pub fn range_db(&self) -> f32 {
+        let max = self.db.iter().flat_map(|column| column.iter()).fold(0.0f32, |m, v| m.max(v.abs()));
+        if max > 0.0 {max} else {1.0}
+    }
+
+    //time across and frequency up, the lowest frequency at the bottom like the spectrograms
+    pub fn image(&self) -> City2D {
+        let range = self.range_db();
+        let rows = self.freqs.len() as u32;
+        let mut image = City2D::new(self.times.len() as u32, rows);
+        for (x, column) in self.db.iter().enumerate() {
+            for (k, v) in column.iter().enumerate() {
+                let (r, g, b) = panelimage::diverging(v/range);
+                image.set_pixel(x as u32, rows-1-k as u32, r, g, b);
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //power of 1 before the marker and step after it in every row, over columns at -1, -0.5, 0, 0.5 and 1 s
+    fn stepped(rows: usize, step: f32) -> Vec<Vec<f32>> {
+        (0..5).map(|c| vec![if c < 2 {1.0} else {step}; rows]).collect()
+    }
+
+    #[test]
+    fn power_step_gives_its_db_change() {
+        let (mut sum, mut counts) = (vec![vec![0.0; 2]; 5], vec![0; 2]);
+        accumulate(&stepped(2, 10.0), &[0, 1], &mut sum, &mut counts);
+        accumulate(&stepped(2, 100.0), &[0, 1], &mut sum, &mut counts);
+        let db = average(&sum, &counts);
+        for k in 0..2 {
+            assert!(db[0][k].abs() < 1e-4 && db[1][k].abs() < 1e-4, "{:?}", db);
+            for c in 2..5 {assert!((db[c][k] - 15.0).abs() < 1e-4, "{:?}", db);}
+        }
+    }
+
+    #[test]
+    fn row_without_baseline_power_leaves_the_others_alone() {
+        let (mut sum, mut counts) = (vec![vec![0.0; 2]; 5], vec![0; 2]);
+        let mut silent = stepped(2, 10.0);
+        for column in silent.iter_mut() {column[1] = 0.0;}
+        accumulate(&silent, &[0, 1], &mut sum, &mut counts);
+        accumulate(&stepped(2, 10.0), &[0, 1], &mut sum, &mut counts);
+        assert_eq!(counts, vec![2, 1]);
+        let db = average(&sum, &counts);
+        //the row that was silent in one trial is the other trial's alone, not halved towards 0 dB
+        for c in 2..5 {
+            assert!((db[c][0] - 10.0).abs() < 1e-4 && (db[c][1] - 10.0).abs() < 1e-4, "{:?}", db);
+        }
+        //a row with no trials at all stays at 0 dB
+        assert_eq!(average(&sum, &[2, 0])[4][1], 0.0);
+    }
+}
@@ -0,0 +1,204 @@
+use std;
+use std::io::Write;
+use rustfft;
+use png;
+use png::HasParameters;
+
+use appstate::{AppState, FilterData};
+use waveformdrawer;
+use waveformdrawer::{WaveformDrawerSettings, ColumnAnalyser, Transform};
+use reassign::{ReassignedColumns};
+use city2d::City2D;
+use annotations;
+use annotations::{Annotation};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MatrixFormat {
+    Csv,
+    Npy,
+}
+
+impl MatrixFormat {
+    pub const ALL: [MatrixFormat; 2] = [MatrixFormat::Csv, MatrixFormat::Npy];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MatrixFormat::Csv => "CSV",
+            MatrixFormat::Npy => "NPY",
+        }
+    }
+}
+
+//what to write for each channel
+#[derive(Clone, Copy, PartialEq)]
+pub enum Export {
+    Image,
+    Matrix(MatrixFormat),
+}
+
+//an offline copy of what a drawer shows, one column per pixel
+pub struct Spectrogram {
+    pub times: Vec<f32>, //seconds from the start of the source, one per column
+    pub freqs: Vec<f32>, //Hz, one per row
+    pub magnitudes: Vec<Vec<f32>>, //indexed by column then frequency
+    pub image: City2D, //highest frequency in the top row, like the display
+}
+
+impl Spectrogram {
+    //analyses from start_ms to end_ms the way a drawer with these settings would
+    //windows are zero filled before the start like the drawer's, so every column has the same frequency axis
+    pub fn compute(samples: &[f32], sample_rate: u32, start_ms: u64, end_ms: u64, settings: &WaveformDrawerSettings, fd: &FilterData) -> Spectrogram {
+        let rows = std::cmp::min(settings.dtft_display_samples, settings.fft_len()/2);
+        let columns = ((end_ms.saturating_sub(start_ms)) as f32 / settings.milliseconds_per_pixel) as u32;
+        let mut analyser = ColumnAnalyser::new(settings.dtft_samples);
+        analyser.prepare(settings, fd, sample_rate);
+        let window_len = analyser.window_length(settings);
+
+        let mut times = Vec::<f32>::with_capacity(columns as usize);
+        let mut spectra = Vec::<Vec<rustfft::num_complex::Complex<f32>>>::with_capacity(columns as usize);
+        let mut reassigned = ReassignedColumns::new();
+        for x in 0..columns {
+            let ms = start_ms as f32 + (x+1) as f32*settings.milliseconds_per_pixel;
+            let sample_point = std::cmp::min((ms*sample_rate as f32/1000.0) as usize, samples.len());
+            let taken = std::cmp::min(window_len, sample_point);
+            let mut signal: Vec<rustfft::num_complex::Complex<f32>> = samples[sample_point-taken..sample_point].iter().map(|s| rustfft::num_complex::Complex{re: *s, im: 0.0}).collect();
+            match analyser.reassign(&mut signal, settings, fd) {
+                Some((_, points)) => {
+                    reassigned.push((ms - settings.milliseconds_per_pixel) as f64, ms as f64, 1, settings.fft_len() as usize);
+                    for p in points {reassigned.add(ms as f64 + p.time as f64*1000.0/sample_rate as f64, p.bin, p.power);}
+                },
+                None => spectra.push(analyser.spectrum(&mut signal, settings, fd)),
+            }
+            times.push(ms/1000.0);
+        }
+        //the whole range is known, so every reassigned column is already final
+        if settings.transform == Transform::Reassigned {
+            spectra = reassigned.ready(std::f64::INFINITY).iter().map(|column| column.spectrum()).collect();
+        }
+
+        let mut magnitudes = Vec::<Vec<f32>>::with_capacity(columns as usize);
+        let mut image = City2D::new(columns, rows);
+        for (x, spectrum) in spectra.iter().enumerate() {
+            for (i, colour) in waveformdrawer::column_colours(spectrum, rows, fd).iter().enumerate() {
+                image.set_pixel(x as u32, rows-1-i as u32, colour.0, colour.1, colour.2);
+            }
+            magnitudes.push(spectrum[..rows as usize].iter().map(|c| c.norm()).collect());
+        }
+        Spectrogram{
+            times: times,
+            freqs: (0..rows).map(|k| k as f32*settings.bin_hz(sample_rate)).collect(),
+            magnitudes: magnitudes,
+            image: image,
+        }
+    }
+
+    pub fn save_png(&self, path: &std::path::Path) -> Result<(), String> {
+        let file = std::fs::File::create(path).map_err(|e| format!("Unable to create {:?}: {}", path, e))?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), self.image.w, self.image.h);
+        encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| format!("Unable to write {:?}: {}", path, e))?;
+        writer.write_image_data(&self.image.rgb()).map_err(|e| format!("Unable to write {:?}: {}", path, e))
+    }
+
+    //one row per time, the first row holds the frequencies and the first column the times
+    pub fn save_csv(&self, path: &std::path::Path) -> Result<(), String> {
+        let write = || -> std::io::Result<()> {
+            let mut f = std::io::BufWriter::new(std::fs::File::create(path)?);
+            write!(f, "time (s) \\ frequency (Hz)")?;
+            for freq in &self.freqs {write!(f, ", {}", freq)?;}
+            writeln!(f)?;
+            for (time, column) in self.times.iter().zip(self.magnitudes.iter()) {
+                write!(f, "{}", time)?;
+                for m in column {write!(f, ", {}", m)?;}
+                writeln!(f)?;
+            }
+            f.flush()
+        };
+        write().map_err(|e| format!("Unable to write {:?}: {}", path, e))
+    }
+
+    //the matrix as time x frequency float32, with the axes in <name>_times.npy and <name>_freqs.npy
+    pub fn save_npy(&self, path: &std::path::Path) -> Result<(), String> {
+        let axis_path = |axis: &str| {
+            let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or(String::new());
+            path.with_file_name(format!("{}_{}.npy", stem, axis))
+        };
+        let flat: Vec<f32> = self.magnitudes.iter().flat_map(|column| column.iter().cloned()).collect();
+        write_npy(path, &flat, &[self.times.len(), self.freqs.len()])?;
+        write_npy(&axis_path("times"), &self.times, &[self.times.len()])?;
+        write_npy(&axis_path("freqs"), &self.freqs, &[self.freqs.len()])
+    }
+}
+
+//version 1.0 of the numpy format, little endian float32 in C order
+fn write_npy(path: &std::path::Path, data: &[f32], shape: &[usize]) -> Result<(), String> {
+    let shape_text = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape_text);
+    //magic, version and length take 10 bytes, the header is padded so the data starts on a multiple of 64
+    while (10 + header.len() + 1) % 64 != 0 {header.push(' ');}
+    header.push('\n');
+    let write = || -> std::io::Result<()> {
+        let mut f = std::io::BufWriter::new(std::fs::File::create(path)?);
+        f.write_all(b"\x93NUMPY\x01\x00")?;
+        f.write_all(&[header.len() as u8, (header.len() >> 8) as u8])?;
+        f.write_all(header.as_bytes())?;
+        for x in data {
+            let bits = x.to_bits();
+            f.write_all(&[bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8])?;
+        }
+        f.flush()
+    };
+    write().map_err(|e| format!("Unable to write {:?}: {}", path, e))
+}
+
+//labels can hold spaces and slashes, keep file names to something safe
+fn file_label(label: &str) -> String {
+    label.chars().map(|c| if c.is_alphanumeric() || c == '-' {c} else {'_'}).collect()
+}
+
+//writes <base>_<channel label>.png/.csv/.npy for each channel, range_s is in seconds and None means everything
+//each channel is analysed with the settings of a drawer showing it, or the first drawer if none is
+pub fn export_channels(app: &AppState, channels: &[usize], range_s: Option<(f32, f32)>, base: &str, export: Export) -> Result<String, String> {
+    let first = match app.waveform_drawers.first() {
+        Some(wfd) => wfd.settings.clone(),
+        None => return Err(String::from("Nothing is open to export.")),
+    };
+    let mut written = Vec::<String>::new();
+    let mut exported_range_s = (0.0, 0.0);
+    for &channel in channels {
+        let settings = app.waveform_drawers.iter().map(|wfd| &wfd.settings).find(|s| s.channel as usize == channel).cloned().unwrap_or(first.clone());
+        //copy the samples out so a live source isn't held up while this runs
+        let (samples, sample_rate, label) = {
+            let app_data = app.app_data.lock().unwrap();
+            let names = app_data.channel_names();
+            let sample_rate = app_data.get_sample_rate().map_err(|e| e.to_string())?;
+            (app_data.samples(channel).to_vec(), sample_rate, names.get(channel).cloned().unwrap_or(format!("ch{}", channel+1)))
+        };
+        let length_ms = samples.len() as u64*1000/sample_rate as u64;
+        let (start_ms, end_ms) = match range_s {
+            Some((a, b)) => (((a.max(0.0)*1000.0) as u64).min(length_ms), ((b.max(0.0)*1000.0) as u64).min(length_ms)),
+            None => (0, length_ms),
+        };
+        if end_ms <= start_ms {return Err(String::from("The export range is empty."));}
+        exported_range_s = (start_ms as f64/1000.0, end_ms as f64/1000.0);
+        let spectrogram = Spectrogram::compute(&samples, sample_rate, start_ms, end_ms, &settings, &app.filter_data);
+        let path = std::path::PathBuf::from(format!("{}_{}", base, file_label(&label)));
+        let path = match export {
+            Export::Image => {let path = path.with_extension("png"); spectrogram.save_png(&path)?; path},
+            Export::Matrix(MatrixFormat::Csv) => {let path = path.with_extension("csv"); spectrogram.save_csv(&path)?; path},
+            Export::Matrix(MatrixFormat::Npy) => {let path = path.with_extension("npy"); spectrogram.save_npy(&path)?; path},
+        };
+        written.push(path.display().to_string());
+    }
+    //the annotations inside the range go alongside, in the same seconds as the exported time axis
+    let inside: Vec<Annotation> = app.annotations.iter().filter(|a| a.time_s >= exported_range_s.0 && a.time_s <= exported_range_s.1).cloned().collect();
+    if !inside.is_empty() {
+        let path = std::path::PathBuf::from(format!("{}_annotations.csv", base));
+        annotations::save_csv(&path, &inside)?;
+        written.push(path.display().to_string());
+    }
+    Ok(format!("Wrote {}", written.join(", ")))
+}
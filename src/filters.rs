@@ -0,0 +1,253 @@
+use std;
+
+
+//a single second order section in transposed direct form II
+//coefficients and state are kept in f64 as low cutoffs at audio rates are not stable in f32
+#[derive(Clone)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    //coefficients follow the RBJ audio eq cookbook, a0 is normalised out
+    fn from_coefficients(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Biquad {
+        Biquad{
+            b0: b0/a0,
+            b1: b1/a0,
+            b2: b2/a0,
+            a1: a1/a0,
+            a2: a2/a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+    fn omega(sample_rate: f64, f0: f64, q: f64) -> (f64, f64) {
+        let w0 = 2.0*std::f64::consts::PI*f0/sample_rate;
+        (w0.cos(), w0.sin()/(2.0*q))
+    }
+    pub fn lowpass(sample_rate: f64, f0: f64, q: f64) -> Biquad {
+        let (cos, alpha) = Biquad::omega(sample_rate, f0, q);
+        Biquad::from_coefficients((1.0-cos)/2.0, 1.0-cos, (1.0-cos)/2.0, 1.0+alpha, -2.0*cos, 1.0-alpha)
+    }
+    pub fn highpass(sample_rate: f64, f0: f64, q: f64) -> Biquad {
+        let (cos, alpha) = Biquad::omega(sample_rate, f0, q);
+        Biquad::from_coefficients((1.0+cos)/2.0, -(1.0+cos), (1.0+cos)/2.0, 1.0+alpha, -2.0*cos, 1.0-alpha)
+    }
+    pub fn notch(sample_rate: f64, f0: f64, q: f64) -> Biquad {
+        let (cos, alpha) = Biquad::omega(sample_rate, f0, q);
+        Biquad::from_coefficients(1.0, -2.0*cos, 1.0, 1.0+alpha, -2.0*cos, 1.0-alpha)
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0*x + self.z1;
+        self.z1 = self.b1*x - self.a1*y + self.z2;
+        self.z2 = self.b2*x - self.a2*y;
+        y
+    }
+
+    //puts the filter into the steady state it would reach after a long run of constant input x
+    //this stops large dc offsets from ringing through the filter when it starts
+    pub fn settle(&mut self, x: f64) -> f64 {
+        let y = x*(self.b0 + self.b1 + self.b2)/(1.0 + self.a1 + self.a2);
+        self.z2 = self.b2*x - self.a2*y;
+        self.z1 = self.b1*x - self.a1*y + self.z2;
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterSettings {
+    pub highpass: bool,
+    pub highpass_hz: f32,
+    pub lowpass: bool,
+    pub lowpass_hz: f32,
+    pub order: u32, //butterworth order for the high and low pass, rounded up to an even number
+    pub notch: bool,
+    pub mains_hz: f32, //50 or 60
+    pub notch_harmonics: u32, //how many multiples of the mains frequency to notch out, including the fundamental
+    pub notch_q: f32,
+}
+
+impl Default for FilterSettings {
+    fn default() -> FilterSettings {
+        FilterSettings{
+            highpass: false,
+            highpass_hz: 1.0,
+            lowpass: false,
+            lowpass_hz: 45.0,
+            order: 4,
+            notch: false,
+            mains_hz: 50.0,
+            notch_harmonics: 1,
+            notch_q: 30.0,
+        }
+    }
+}
+
+
+//filtfilt pads each end with this many periods of the chain's lowest cutoff
+const PAD_PERIODS: f64 = 3.0;
+
+//a cascade of biquads built from a FilterSettings
+//high pass and low pass together give a band pass
+#[derive(Clone)]
+pub struct FilterChain {
+    stages: Vec<Biquad>,
+    settled: bool,
+    pad_samples: usize, //how much filtfilt reflects onto each end
+}
+
+impl FilterChain {
+    pub fn new(settings: &FilterSettings, sample_rate: u32) -> FilterChain {
+        let fs = sample_rate as f64;
+        let nyquist = fs/2.0;
+        let mut stages = Vec::<Biquad>::new();
+        //the narrowest feature decides how long the filter rings for, a notch is as narrow as its bandwidth
+        let mut narrowest_hz = std::f64::INFINITY;
+
+        //butterworth sections, section k of an order n filter has q = 1/(2cos(pi(2k+1)/2n))
+        let sections = (std::cmp::max(settings.order, 2) + 1)/2;
+        let order = 2*sections;
+        let butterworth_q = |k: u32| 1.0/(2.0*(std::f64::consts::PI*(2*k+1) as f64/(2*order) as f64).cos());
+
+        if settings.highpass && (settings.highpass_hz as f64) < nyquist*0.98 {
+            for k in 0..sections {
+                stages.push(Biquad::highpass(fs, settings.highpass_hz as f64, butterworth_q(k)));
+            }
+            narrowest_hz = narrowest_hz.min(settings.highpass_hz as f64);
+        }
+        if settings.lowpass && (settings.lowpass_hz as f64) < nyquist*0.98 {
+            for k in 0..sections {
+                stages.push(Biquad::lowpass(fs, settings.lowpass_hz as f64, butterworth_q(k)));
+            }
+            narrowest_hz = narrowest_hz.min(settings.lowpass_hz as f64);
+        }
+        if settings.notch {
+            for h in 1..(settings.notch_harmonics+1) {
+                let f0 = settings.mains_hz as f64 * h as f64;
+                if f0 >= nyquist*0.98 {break;}
+                stages.push(Biquad::notch(fs, f0, settings.notch_q as f64));
+                narrowest_hz = narrowest_hz.min(f0/settings.notch_q as f64);
+            }
+        }
+
+        FilterChain{
+            stages: stages,
+            settled: false,
+            pad_samples: if narrowest_hz.is_finite() && narrowest_hz > 0.0 {(PAD_PERIODS*fs/narrowest_hz).ceil() as usize} else {0},
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {stage.reset();}
+        self.settled = false;
+    }
+
+    fn settle(&mut self, x: f64) {
+        let mut y = x;
+        for stage in &mut self.stages {y = stage.settle(y);}
+        self.settled = true;
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        if !self.settled {self.settle(x as f64);}
+        let mut y = x as f64;
+        for stage in &mut self.stages {y = stage.process(y);}
+        y as f32
+    }
+
+    //causal filtering, keeps the filter state between calls so it can be used on a live stream
+    pub fn process_into(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        output.extend(input.iter().map(|x| self.process(*x)));
+    }
+
+    //zero phase filtering for whole buffers: run forwards then backwards so the phase shifts cancel
+    //the ends are padded with an odd reflection of the signal to cut down on edge transients,
+    //PAD_PERIODS periods of the lowest cutoff long so slow high passes get as long to settle as they need
+    pub fn filtfilt(&mut self, input: &[f32]) -> Vec<f32> {
+        let n = input.len();
+        if n < 2 || self.is_empty() {return input.to_vec();}
+        let pad = std::cmp::min(n-1, self.pad_samples);
+
+        let mut padded = Vec::<f32>::with_capacity(n + 2*pad);
+        for i in (1..(pad+1)).rev() {padded.push(2.0*input[0] - input[i]);}
+        padded.extend_from_slice(input);
+        for i in 1..(pad+1) {padded.push(2.0*input[n-1] - input[n-1-i]);}
+
+        self.reset();
+        let mut forward: Vec<f32> = padded.iter().map(|x| self.process(*x)).collect();
+        forward.reverse();
+        self.reset();
+        let mut backward: Vec<f32> = forward.iter().map(|x| self.process(*x)).collect();
+        backward.reverse();
+        self.reset();
+
+        backward[pad..(pad+n)].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the amplitude a sine comes out with once the filter has settled, ten seconds in
+    fn gain(settings: &FilterSettings, hz: f32) -> f32 {
+        let sample_rate = 256;
+        let mut chain = FilterChain::new(settings, sample_rate);
+        let input: Vec<f32> = (0..10*sample_rate).map(|i| (2.0*std::f32::consts::PI*hz*i as f32/sample_rate as f32).sin()).collect();
+        let mut output = Vec::<f32>::new();
+        chain.process_into(&input, &mut output);
+        output[8*sample_rate as usize..].iter().fold(0.0, |m, y| m.max(y.abs()))
+    }
+
+    #[test]
+    fn butterworth_is_3db_down_at_the_cutoff() {
+        let half_power = std::f32::consts::FRAC_1_SQRT_2;
+        for order in &[2, 4, 6] {
+            let lowpass = FilterSettings{lowpass: true, lowpass_hz: 20.0, order: *order, ..FilterSettings::default()};
+            assert!((gain(&lowpass, 20.0) - half_power).abs() < 0.01, "low pass order {}: {}", order, gain(&lowpass, 20.0));
+            assert!(gain(&lowpass, 5.0) > 0.99);
+            let highpass = FilterSettings{highpass: true, highpass_hz: 5.0, order: *order, ..FilterSettings::default()};
+            assert!((gain(&highpass, 5.0) - half_power).abs() < 0.01, "high pass order {}: {}", order, gain(&highpass, 5.0));
+            assert!(gain(&highpass, 40.0) > 0.99);
+        }
+    }
+
+    #[test]
+    fn notch_removes_mains_and_its_harmonics() {
+        let notch = FilterSettings{notch: true, mains_hz: 50.0, notch_harmonics: 2, ..FilterSettings::default()};
+        assert!(gain(&notch, 50.0) < 0.01, "{}", gain(&notch, 50.0));
+        assert!(gain(&notch, 100.0) < 0.01, "{}", gain(&notch, 100.0));
+        assert!(gain(&notch, 30.0) > 0.98, "{}", gain(&notch, 30.0));
+    }
+
+    #[test]
+    fn filtfilt_padding_follows_the_lowest_cutoff() {
+        let highpass = FilterSettings{highpass: true, highpass_hz: 0.5, ..FilterSettings::default()};
+        assert_eq!(FilterChain::new(&highpass, 250).pad_samples, 1500);
+        let band = FilterSettings{lowpass: true, lowpass_hz: 30.0, ..highpass};
+        assert_eq!(FilterChain::new(&band, 250).pad_samples, 1500);
+        //odd reflection carries a drift straight on, so with long enough padding the high pass removes it right up to the ends
+        let input: Vec<f32> = (0..4000).map(|i| 5.0 + 0.002*i as f32).collect();
+        let output = FilterChain::new(&highpass, 250).filtfilt(&input);
+        for y in &output {
+            assert!(y.abs() < 0.01, "{}", y);
+        }
+    }
+}
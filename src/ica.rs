@@ -0,0 +1,322 @@
+use std;
+use rustfft;
+
+use derived::{DerivedChannel};
+use linalg::{Matrix, multiply, transpose, identity, eigen, invert};
+
+//fitting uses at most this many samples, evenly spaced through the recording
+const MAX_FIT_SAMPLES: usize = 60000;
+//whitening drops directions with less variance than this share of the largest, eg the one a common average reference removes
+const RANK_TOLERANCE: f64 = 1e-7;
+const FASTICA_TOLERANCE: f64 = 1e-5;
+const INFOMAX_TOLERANCE: f64 = 1e-7;
+//component spectra are averaged over windows this long
+const SPECTRUM_WINDOW_S: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    FastIca,
+    Infomax,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::FastIca, Algorithm::Infomax];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Algorithm::FastIca => "FastICA (tanh)",
+            Algorithm::Infomax => "Extended Infomax",
+        }
+    }
+}
+
+//what a component has been marked as, anything but Keep is left out of the cleaned channels
+#[derive(Clone, Copy, PartialEq)]
+pub enum ComponentLabel {
+    Keep,
+    Eye,
+    Muscle,
+}
+
+impl ComponentLabel {
+    pub const ALL: [ComponentLabel; 3] = [ComponentLabel::Keep, ComponentLabel::Eye, ComponentLabel::Muscle];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ComponentLabel::Keep => "Brain / keep",
+            ComponentLabel::Eye => "Eye artifact",
+            ComponentLabel::Muscle => "Muscle artifact",
+        }
+    }
+}
+
+//(W W')^-1/2 W, the nearest matrix with orthonormal rows, so no two components converge to the same one
+fn decorrelate(w: &Matrix) -> Matrix {
+    let (values, vectors) = eigen(multiply(w, &transpose(w)));
+    let n = values.len();
+    let scaled: Matrix = (0..n).map(|i| (0..n).map(|j| vectors[i][j]/values[j].max(1e-300).sqrt()).collect()).collect();
+    multiply(&multiply(&scaled, &transpose(&vectors)), w)
+}
+
+//a fixed sequence so the same file always gives the same components
+struct Lcg(u64);
+impl Lcg {
+    fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64/(1u64 << 53) as f64
+    }
+}
+
+//symmetric FastICA with g = tanh, all components updated together then decorrelated
+fn fastica(z: &Matrix, max_iterations: usize) -> (Matrix, usize, bool) {
+    let (m, t) = (z.len(), z[0].len());
+    let mut random = Lcg(1);
+    let mut w = decorrelate(&(0..m).map(|_| (0..m).map(|_| random.next() - 0.5).collect()).collect());
+    for iteration in 0..max_iterations {
+        let mut next = vec![vec![0.0f64; m]; m];
+        for i in 0..m {
+            let mut mean_derivative = 0.0;
+            for s in 0..t {
+                let u: f64 = (0..m).map(|j| w[i][j]*z[j][s]).sum();
+                let g = u.tanh();
+                mean_derivative += 1.0 - g*g;
+                for j in 0..m {next[i][j] += z[j][s]*g;}
+            }
+            for j in 0..m {next[i][j] = next[i][j]/t as f64 - mean_derivative/t as f64*w[i][j];}
+        }
+        let next = decorrelate(&next);
+        //converged once every row points the same way as before, up to sign
+        let change = (0..m).map(|i| 1.0 - (0..m).map(|j| next[i][j]*w[i][j]).sum::<f64>().abs()).fold(0.0f64, f64::max);
+        w = next;
+        if change < FASTICA_TOLERANCE {return (w, iteration+1, true);}
+    }
+    (w, max_iterations, false)
+}
+
+//Extended infomax (Lee, Girolami and Sejnowski) with the natural gradient. Each component is treated as super-gaussian,
+//like blinks and muscle bursts, or sub-gaussian, like line noise, by the sign of a kurtosis-like statistic worked out
+//every pass. Blocks are taken in a shuffled order and the learning rate is cut when it overshoots.
+fn infomax(z: &Matrix, max_iterations: usize) -> (Matrix, usize, bool) {
+    let (m, t) = (z.len(), z[0].len());
+    let block = std::cmp::max(16, std::cmp::min(t, ((t as f64/3.0).sqrt()) as usize));
+    let mut rate = 0.00065/(m as f64).max(2.0).ln();
+    let mut w = identity(m);
+    let mut random = Lcg(1);
+    let mut last_change = std::f64::INFINITY;
+    for iteration in 0..max_iterations {
+        let start = w.clone();
+        //+1 super-gaussian, -1 sub-gaussian: the sign of E[sech^2 u] E[u^2] - E[u tanh u]
+        let mut moments = vec![(0.0f64, 0.0f64, 0.0f64); m];
+        for s in (0..t).step_by(std::cmp::max(1, t/5000)) {
+            for i in 0..m {
+                let u: f64 = (0..m).map(|j| w[i][j]*z[j][s]).sum();
+                let th = u.tanh();
+                moments[i].0 += 1.0 - th*th;
+                moments[i].1 += u*u;
+                moments[i].2 += u*th;
+            }
+        }
+        let kind: Vec<f64> = moments.iter().map(|&(sech2, u2, utanh)| {
+            let samples = (t as f64/std::cmp::max(1, t/5000) as f64).ceil();
+            if sech2*u2/samples - utanh < 0.0 {-1.0} else {1.0}
+        }).collect();
+
+        let mut order: Vec<usize> = (0..t).collect();
+        for i in (1..t).rev() {
+            let j = (random.next()*(i + 1) as f64) as usize;
+            order.swap(i, j.min(i));
+        }
+        for chunk in order.chunks(block) {
+            //dW = rate (I - K tanh(u) u' - u u') W, summed over the block
+            let mut gradient = identity(m);
+            for i in 0..m {gradient[i][i] *= chunk.len() as f64;}
+            for &s in chunk {
+                let u: Vec<f64> = (0..m).map(|i| (0..m).map(|j| w[i][j]*z[j][s]).sum()).collect();
+                for i in 0..m {
+                    let g = kind[i]*u[i].tanh() + u[i];
+                    for j in 0..m {gradient[i][j] -= g*u[j];}
+                }
+            }
+            let step = multiply(&gradient, &w);
+            for i in 0..m {
+                for j in 0..m {w[i][j] += rate*step[i][j];}
+            }
+        }
+        if w.iter().flat_map(|r| r.iter()).any(|x| !x.is_finite()) {
+            //blew up, start again more gently
+            w = identity(m);
+            rate *= 0.5;
+            last_change = std::f64::INFINITY;
+            continue;
+        }
+        let change: f64 = (0..m).map(|i| (0..m).map(|j| (w[i][j] - start[i][j]).powi(2)).sum::<f64>()).sum();
+        if change < INFOMAX_TOLERANCE {return (w, iteration+1, true);}
+        if change > last_change {rate *= 0.9;}
+        last_change = change;
+    }
+    (w, max_iterations, false)
+}
+
+//Independent components of the raw channels of a file. Components are sorted by how much of the channels' variance they
+//account for, biggest first.
+pub struct Ica {
+    pub algorithm: Algorithm,
+    pub means: Vec<f32>, //per channel
+    pub unmixing: Vec<Vec<f32>>, //component by channel, whitening included, applied to the channels less their means
+    pub mixing: Vec<Vec<f32>>, //channel by component, each column is how a component shows on the scalp
+    pub sources: Vec<Vec<f32>>, //component time courses over the whole file
+    pub variance: Vec<f32>, //share of the total each component accounts for
+    pub labels: Vec<ComponentLabel>,
+    pub spectrum_hz: Vec<f32>,
+    pub spectra: Vec<Vec<f32>>, //per component in dB, one per spectrum_hz
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+impl Ica {
+    pub fn compute(channels: &[Vec<f32>], sample_rate: u32, algorithm: Algorithm, max_iterations: usize) -> Result<Ica, String> {
+        let n = channels.len();
+        let len = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        if n < 2 {return Err(String::from("ICA needs at least two channels."));}
+        if len < n*20 {return Err(String::from("There is too little data for this many channels."));}
+
+        let means: Vec<f64> = channels.iter().map(|c| c[..len].iter().map(|x| *x as f64).sum::<f64>()/len as f64).collect();
+        let stride = std::cmp::max(1, len/MAX_FIT_SAMPLES);
+        let fit: Matrix = channels.iter().zip(means.iter()).map(|(c, m)| c[..len].iter().step_by(stride).map(|x| *x as f64 - m).collect()).collect();
+        let t = fit[0].len();
+
+        //whiten: rotate onto the principal axes and scale each to unit variance
+        let covariance: Matrix = (0..n).map(|i| (0..n).map(|j| (0..t).map(|s| fit[i][s]*fit[j][s]).sum::<f64>()/t as f64).collect()).collect();
+        let (values, vectors) = eigen(covariance);
+        let largest = values.first().cloned().unwrap_or(0.0);
+        let m = values.iter().filter(|v| **v > largest*RANK_TOLERANCE).count();
+        if m < 2 {return Err(String::from("The channels are too alike to separate."));}
+        let whitening: Matrix = (0..m).map(|k| (0..n).map(|j| vectors[j][k]/values[k].sqrt()).collect()).collect();
+        let dewhitening: Matrix = (0..n).map(|j| (0..m).map(|k| vectors[j][k]*values[k].sqrt()).collect()).collect();
+        let z = multiply(&whitening, &fit);
+
+        let (w, iterations, converged) = match algorithm {
+            Algorithm::FastIca => fastica(&z, max_iterations),
+            Algorithm::Infomax => infomax(&z, max_iterations),
+        };
+        //the data is white, so unit length rows give unit variance sources and a component's size is all in its column of the mixing matrix
+        let w: Matrix = w.iter().map(|row| {
+            let norm = row.iter().map(|x| x*x).sum::<f64>().sqrt().max(1e-300);
+            row.iter().map(|x| x/norm).collect()
+        }).collect();
+        let w_inverse = invert(w.clone()).ok_or(String::from("The unmixing matrix came out singular."))?;
+        let unmixing = multiply(&w, &whitening);
+        let mixing = multiply(&dewhitening, &w_inverse);
+
+        let total: f64 = (0..n).map(|j| (0..m).map(|k| mixing[j][k]*mixing[j][k]).sum::<f64>()).sum();
+        let mut order: Vec<usize> = (0..m).collect();
+        let power = |k: usize| (0..n).map(|j| mixing[j][k]*mixing[j][k]).sum::<f64>();
+        order.sort_by(|a, b| power(*b).partial_cmp(&power(*a)).unwrap_or(std::cmp::Ordering::Equal));
+
+        let unmixing: Vec<Vec<f32>> = order.iter().map(|k| unmixing[*k].iter().map(|x| *x as f32).collect()).collect();
+        let mixing: Vec<Vec<f32>> = (0..n).map(|j| order.iter().map(|k| mixing[j][*k] as f32).collect()).collect();
+        let means: Vec<f32> = means.iter().map(|x| *x as f32).collect();
+        let sources: Vec<Vec<f32>> = unmixing.iter().map(|row| {
+            (0..len).map(|s| row.iter().zip(channels.iter()).zip(means.iter()).map(|((w, c), m)| w*(c[s] - m)).sum()).collect()
+        }).collect();
+        let (spectrum_hz, spectra) = spectra(&sources, sample_rate);
+
+        Ok(Ica{
+            algorithm: algorithm,
+            variance: order.iter().map(|k| (power(*k)/total) as f32).collect(),
+            labels: vec![ComponentLabel::Keep; m],
+            means: means,
+            unmixing: unmixing,
+            mixing: mixing,
+            sources: sources,
+            spectrum_hz: spectrum_hz,
+            spectra: spectra,
+            iterations: iterations,
+            converged: converged,
+        })
+    }
+
+    pub fn components(&self) -> usize {
+        self.sources.len()
+    }
+
+    //every channel rebuilt from the components marked Keep, as weights over the raw channels
+    //x = mean + sum over kept k of mixing[c][k] * unmixing[k] . (x - means)
+    pub fn cleaned(&self) -> Vec<DerivedChannel> {
+        let n = self.means.len();
+        let kept: Vec<usize> = (0..self.components()).filter(|k| self.labels[*k] == ComponentLabel::Keep).collect();
+        (0..n).map(|c| {
+            let weights: Vec<f32> = (0..n).map(|j| kept.iter().map(|k| self.mixing[c][*k]*self.unmixing[*k][j]).sum()).collect();
+            let offset = self.means[c] - weights.iter().zip(self.means.iter()).map(|(w, m)| w*m).sum::<f32>();
+            DerivedChannel::Cleaned(c as u32, weights, offset)
+        }).collect()
+    }
+}
+
+//Welch's average of Hann windowed periodograms, half overlapping
+fn spectra(sources: &[Vec<f32>], sample_rate: u32) -> (Vec<f32>, Vec<Vec<f32>>) {
+    let len = sources.first().map(|s| s.len()).unwrap_or(0);
+    let window = std::cmp::max(2, std::cmp::min(len, (SPECTRUM_WINDOW_S*sample_rate as f32) as usize));
+    let hann: Vec<f32> = (0..window).map(|i| 0.5 - 0.5*(2.0*std::f32::consts::PI*i as f32/window as f32).cos()).collect();
+    let mut fft_planner = rustfft::FFTplanner::new(false);
+    let fft = fft_planner.plan_fft(window);
+    let bins = window/2;
+    let spectra = sources.iter().map(|source| {
+        let mut power = vec![0.0f32; bins];
+        let mut count = 0;
+        let mut start = 0;
+        while start + window <= len {
+            let mut input: Vec<rustfft::num_complex::Complex<f32>> = source[start..start+window].iter().zip(hann.iter())
+                .map(|(x, h)| rustfft::num_complex::Complex{re: x*h, im: 0.0}).collect();
+            let mut output = input.clone();
+            fft.process(&mut input, &mut output);
+            for (p, c) in power.iter_mut().zip(output.iter()) {*p += c.norm_sqr();}
+            count += 1;
+            start += window/2;
+        }
+        power.iter().map(|p| 10.0*(p/std::cmp::max(1, count) as f32).max(1e-30).log10()).collect()
+    }).collect();
+    ((0..bins).map(|k| k as f32*sample_rate as f32/window as f32).collect(), spectra)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn correlation(a: &[f32], b: &[f32]) -> f32 {
+        let mean = |x: &[f32]| x.iter().sum::<f32>()/x.len() as f32;
+        let (ma, mb) = (mean(a), mean(b));
+        let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+        for (x, y) in a.iter().zip(b.iter()) {
+            ab += (x - ma)*(y - mb);
+            aa += (x - ma)*(x - ma);
+            bb += (y - mb)*(y - mb);
+        }
+        ab/(aa*bb).sqrt()
+    }
+
+    #[test]
+    fn unmixes_two_known_sources() {
+        //a sine, sub-Gaussian, and a sawtooth at an unrelated rate, mixed into two channels with offsets
+        let (sample_rate, len) = (250, 5000);
+        let sine: Vec<f32> = (0..len).map(|i| (2.0*std::f32::consts::PI*10.0*i as f32/sample_rate as f32).sin()).collect();
+        let saw: Vec<f32> = (0..len).map(|i| ((i as f32*0.0137) % 1.0) - 0.5).collect();
+        let channels = vec![
+            sine.iter().zip(saw.iter()).map(|(s, w)| 0.8*s + 0.6*w + 5.0).collect::<Vec<f32>>(),
+            sine.iter().zip(saw.iter()).map(|(s, w)| 0.3*s - 1.2*w - 2.0).collect::<Vec<f32>>(),
+        ];
+        for algorithm in &Algorithm::ALL {
+            let ica = Ica::compute(&channels, sample_rate, *algorithm, 500).unwrap();
+            assert_eq!(ica.components(), 2);
+            for source in &[&sine, &saw] {
+                let best = ica.sources.iter().map(|c| correlation(c, source).abs()).fold(0.0, f32::max);
+                assert!(best > 0.99, "{}: {}", algorithm.name(), best);
+            }
+            //keeping every component gives the channels back
+            for (c, d) in ica.cleaned().iter().enumerate() {
+                let rebuilt = d.compute(&channels);
+                assert!(rebuilt.iter().zip(channels[c].iter()).all(|(r, x)| (r - x).abs() < 1e-3));
+            }
+        }
+    }
+}
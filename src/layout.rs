@@ -0,0 +1,190 @@
+use std;
+use waveformdrawer::{WaveformDrawer};
+
+
+//the part of the window the spectrograms live in, in the same percentage coordinates as WaveformDrawerSettings
+//(0,0) is the centre of the window, the settings panel takes up the right quarter
+const REGION_LEFT: f32 = -50.0;
+const REGION_WIDTH: f32 = 75.0;
+const REGION_TOP: f32 = 50.0;
+const REGION_HEIGHT: f32 = 100.0;
+//spectrograms are easiest to read when they are wider than they are tall, the grid aims for this shape in pixels
+const TARGET_CELL_ASPECT: f32 = 3.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LayoutMode {
+    Stacked,
+    Grid,
+}
+
+impl LayoutMode {
+    pub const ALL: [LayoutMode; 2] = [LayoutMode::Stacked, LayoutMode::Grid];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LayoutMode::Stacked => "Stacked",
+            LayoutMode::Grid => "Grid",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Layout {
+    pub mode: LayoutMode,
+    pub order: Vec<usize>, //drawer indices in the order they are shown, top to bottom then left to right
+    pub hidden: Vec<bool>, //indexed by drawer
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout{
+            mode: LayoutMode::Stacked,
+            order: Vec::<usize>::new(),
+            hidden: Vec::<bool>::new(),
+        }
+    }
+}
+
+impl Layout {
+    //start again with every drawer shown in its natural order
+    pub fn reset(&mut self, drawers: usize) {
+        self.order = (0..drawers).collect();
+        self.hidden = vec![false; drawers];
+    }
+
+    //whether this layout describes exactly this many drawers, each shown once in the order
+    pub fn fits(&self, drawers: usize) -> bool {
+        let mut seen = vec![false; drawers];
+        self.hidden.len() == drawers && self.order.len() == drawers &&
+            self.order.iter().all(|i| *i < drawers && !std::mem::replace(&mut seen[*i], true))
+    }
+
+    pub fn move_up(&mut self, position: usize) {
+        if position > 0 && position < self.order.len() {
+            self.order.swap(position, position-1);
+        }
+    }
+
+    pub fn move_down(&mut self, position: usize) {
+        if position+1 < self.order.len() {
+            self.order.swap(position, position+1);
+        }
+    }
+
+    //picks the number of columns whose cells come closest to TARGET_CELL_ASPECT
+    //window_aspect is the window width over its height
+    fn grid_shape(n: usize, window_aspect: f32) -> (usize, usize) {
+        let mut best = (1, n);
+        let mut best_error = std::f32::INFINITY;
+        for cols in 1..(n+1) {
+            let rows = (n + cols - 1)/cols;
+            let cell_aspect = (REGION_WIDTH/cols as f32) / (REGION_HEIGHT/rows as f32) * window_aspect;
+            let error = (cell_aspect/TARGET_CELL_ASPECT).ln().abs();
+            if error < best_error {
+                best_error = error;
+                best = (cols, rows);
+            }
+        }
+        best
+    }
+
+    //where each of this many drawers goes as (x, y, width, height), None for hidden ones
+    pub fn cells(&self, drawers: usize, window_aspect: f32) -> Vec<Option<(f32, f32, f32, f32)>> {
+        let mut cells = vec![None; drawers];
+        let shown: Vec<usize> = self.order.iter().cloned().filter(|i| *i < drawers && !self.hidden.get(*i).cloned().unwrap_or(false)).collect();
+        let n = shown.len();
+        if n == 0 {return cells;}
+
+        let (cols, rows) = match self.mode {
+            LayoutMode::Stacked => (1, n),
+            LayoutMode::Grid => Layout::grid_shape(n, window_aspect),
+        };
+        let cell_w = REGION_WIDTH / cols as f32;
+        let cell_h = REGION_HEIGHT / rows as f32;
+        let gap_x = if cols > 1 {(cell_w*0.05).min(2.0)} else {0.0};
+        let gap_y = (cell_h*0.2).min(5.0);
+
+        for (k, i) in shown.iter().enumerate() {
+            let col = k % cols;
+            let row = k / cols;
+            cells[*i] = Some((
+                REGION_LEFT + cell_w*(col as f32 + 0.5),
+                REGION_TOP - cell_h*(row as f32 + 0.5),
+                cell_w - gap_x,
+                cell_h - gap_y));
+        }
+        cells
+    }
+
+    //sets the position and size of every drawer, hidden drawers keep their old place but are not drawn
+    pub fn apply(&self, drawers: &mut [WaveformDrawer], window_aspect: f32) {
+        let cells = self.cells(drawers.len(), window_aspect);
+        for (wfd, cell) in drawers.iter_mut().zip(cells.into_iter()) {
+            let ref mut settings = wfd.settings;
+            settings.visible = cell.is_some();
+            if let Some((x, y, width, height)) = cell {
+                settings.x = x;
+                settings.y = y;
+                settings.width = width;
+                settings.height = height;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlap(a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)) -> bool {
+        (a.0 - b.0).abs() < (a.2 + b.2)/2.0 && (a.1 - b.1).abs() < (a.3 + b.3)/2.0
+    }
+
+    #[test]
+    fn drawers_tile_the_region_without_overlapping() {
+        for mode in &LayoutMode::ALL {
+            for n in 1..10 {
+                let mut layout = Layout::default();
+                layout.mode = *mode;
+                layout.reset(n);
+                let cells: Vec<(f32, f32, f32, f32)> = layout.cells(n, 16.0/9.0).into_iter().map(|c| c.unwrap()).collect();
+                for (i, a) in cells.iter().enumerate() {
+                    assert!(a.2 > 0.0 && a.3 > 0.0);
+                    assert!(a.0 - a.2/2.0 >= -50.0 - 1e-3 && a.0 + a.2/2.0 <= 50.0 + 1e-3, "{} {:?}", mode.name(), a);
+                    assert!(a.1 - a.3/2.0 >= -50.0 - 1e-3 && a.1 + a.3/2.0 <= 50.0 + 1e-3, "{} {:?}", mode.name(), a);
+                    for b in &cells[i+1..] {
+                        assert!(!overlap(*a, *b), "{} with {}: {:?} {:?}", mode.name(), n, a, b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hidden_drawers_are_skipped() {
+        let mut layout = Layout::default();
+        layout.reset(3);
+        layout.hidden[1] = true;
+        let cells = layout.cells(3, 1.0);
+        assert!(cells[1].is_none());
+        //the two left share the height between them
+        assert_eq!(cells[0].unwrap().3, cells[2].unwrap().3);
+        assert!(cells[0].unwrap().3 > REGION_HEIGHT/3.0);
+        //a hidden list shorter than the drawers shows the rest
+        layout.hidden.truncate(2);
+        layout.order.push(3);
+        assert!(layout.cells(4, 1.0)[3].is_some());
+    }
+
+    #[test]
+    fn moves_stop_at_the_ends() {
+        let mut layout = Layout::default();
+        layout.reset(3);
+        layout.move_up(0);
+        layout.move_down(2);
+        assert_eq!(layout.order, vec![0, 1, 2]);
+        layout.move_down(0);
+        layout.move_up(2);
+        assert_eq!(layout.order, vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,81 @@
+use std;
+
+//small dense matrices, rows of columns, for the ica and the ssvep detector's canonical correlation
+pub type Matrix = Vec<Vec<f64>>;
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let (n, m, p) = (a.len(), b.len(), b.first().map(|r| r.len()).unwrap_or(0));
+    (0..n).map(|i| (0..p).map(|j| (0..m).map(|k| a[i][k]*b[k][j]).sum()).collect()).collect()
+}
+
+pub fn transpose(a: &Matrix) -> Matrix {
+    let (n, m) = (a.len(), a.first().map(|r| r.len()).unwrap_or(0));
+    (0..m).map(|j| (0..n).map(|i| a[i][j]).collect()).collect()
+}
+
+pub fn identity(n: usize) -> Matrix {
+    (0..n).map(|i| (0..n).map(|j| if i == j {1.0} else {0.0}).collect()).collect()
+}
+
+//cyclic Jacobi rotations for a symmetric matrix, eigenvalues largest first with their eigenvectors as columns
+pub fn eigen(mut a: Matrix) -> (Vec<f64>, Matrix) {
+    let n = a.len();
+    let mut v = identity(n);
+    for _ in 0..100 {
+        let off: f64 = (0..n).map(|i| (0..n).filter(|j| *j != i).map(|j| a[i][j]*a[i][j]).sum::<f64>()).sum();
+        if off < 1e-22 {break;}
+        for p in 0..n {
+            for q in p+1..n {
+                if a[p][q].abs() < 1e-300 {continue;}
+                let theta = (a[q][q] - a[p][p])/(2.0*a[p][q]);
+                let t = theta.signum()/(theta.abs() + (theta*theta + 1.0).sqrt());
+                let c = 1.0/(t*t + 1.0).sqrt();
+                let s = t*c;
+                for k in 0..n {
+                    let (akp, akq) = (a[k][p], a[k][q]);
+                    a[k][p] = c*akp - s*akq;
+                    a[k][q] = s*akp + c*akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p][k], a[q][k]);
+                    a[p][k] = c*apk - s*aqk;
+                    a[q][k] = s*apk + c*aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k][p], v[k][q]);
+                    v[k][p] = c*vkp - s*vkq;
+                    v[k][q] = s*vkp + c*vkq;
+                }
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|i, j| a[*j][*j].partial_cmp(&a[*i][*i]).unwrap_or(std::cmp::Ordering::Equal));
+    (order.iter().map(|i| a[*i][*i]).collect(), (0..n).map(|r| order.iter().map(|i| v[r][*i]).collect()).collect())
+}
+
+//Gauss-Jordan with partial pivoting, None if the matrix is singular
+pub fn invert(mut a: Matrix) -> Option<Matrix> {
+    let n = a.len();
+    let mut inverse = identity(n);
+    for col in 0..n {
+        let pivot = (col..n).fold(col, |best, row| if a[row][col].abs() > a[best][col].abs() {row} else {best});
+        if a[pivot][col].abs() < 1e-12 {return None;}
+        a.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let scale = a[col][col];
+        for j in 0..n {
+            a[col][j] /= scale;
+            inverse[col][j] /= scale;
+        }
+        for row in (0..n).filter(|row| *row != col) {
+            let factor = a[row][col];
+            if factor == 0.0 {continue;}
+            for j in 0..n {
+                a[row][j] -= factor*a[col][j];
+                inverse[row][j] -= factor*inverse[col][j];
+            }
+        }
+    }
+    Some(inverse)
+}
@@ -7,9 +7,15 @@ extern crate glium;
 extern crate portaudio;
 extern crate find_folder;
 extern crate rustfft;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate toml;
+extern crate serde_json;
+extern crate png;
 
 //use glium::DisplayBuild;
 use glium::Surface;
+use glium::glutin::GlContext;
 
 //use glium::{DisplayBuild, Surface};
 
@@ -29,6 +35,53 @@ pub mod ui;
 
 pub mod dftwindower;
 
+pub mod bandpower;
+
+pub mod filters;
+
+pub mod detrend;
+
+pub mod derived;
+
+pub mod channelinfo;
+
+pub mod layout;
+
+pub mod viewport;
+
+pub mod presets;
+
+pub mod session;
+
+pub mod recorder;
+
+pub mod export;
+
+pub mod annotations;
+
+pub mod panelimage;
+
+pub mod ersp;
+pub mod worker;
+
+pub mod morlet;
+pub mod multitaper;
+pub mod peaks;
+pub mod reassign;
+
+pub mod connectivity;
+
+pub mod topomap;
+
+pub mod artifacts;
+
+pub mod linalg;
+pub mod ica;
+
+pub mod neurofeedback;
+
+pub mod ssvep;
+
 
 pub fn main() {
     const WIDTH: u32 = 1920;
@@ -48,6 +101,11 @@ pub fn main() {
         .with_multisampling(0);
         //.with_vsync(true);
     let display = glium::Display::new(window, context, &events_loop).unwrap();
+    let viewport = {
+        let gl_window = display.gl_window();
+        let size = gl_window.get_inner_size().unwrap_or((WIDTH, HEIGHT).into());
+        viewport::Viewport::new(size.width, size.height, gl_window.get_hidpi_factor())
+    };
 
     println!("Constructing UI.");
     let mut ui = conrod::UiBuilder::new([WIDTH as f64, HEIGHT as f64]).theme(support::theme()).build();
@@ -66,10 +124,63 @@ pub fn main() {
         gui_data: GuiData{
             gui_display: GuiDisplay::FileOpen,
             file_selection: None,
-            cursor_xy: (0.0,0.0)},
+            cursor_xy: (0.0,0.0),
+            viewport: viewport,
+            derived_editor: appstate::DerivedChannelEditor::default(),
+            preset_name: String::new(),
+            preset_file: presets::PresetFile::default(),
+            preset_path: std::path::PathBuf::from(presets::PRESETS_FILE),
+            session_path: String::from(session::SESSION_FILE),
+            session_status: String::new(),
+            record_format: recorder::RecordFormat::Wav,
+            record_path: String::from("recording.wav"),
+            record_status: String::new(),
+            marker_label: String::from("marker"),
+            export_channel: None,
+            export_range: None,
+            export_path: String::from("export"),
+            matrix_format: export::MatrixFormat::Csv,
+            export_status: String::new(),
+            annotation_path: String::from("annotations.csv"),
+            annotation_status: String::new(),
+            ersp_settings: ersp::ErspSettings::default(),
+            ersp: None,
+            ersp_worker: None,
+            ersp_status: String::new(),
+            panel_images: panelimage::PanelImages::default(),
+            peaks_path: String::from("peaks.csv"),
+            peaks_status: String::new(),
+            window_metrics: None,
+            filters_pending: false,
+            connectivity_settings: connectivity::ConnectivitySettings::default(),
+            connectivity: None,
+            connectivity_worker: None,
+            connectivity_status: String::new(),
+            topo_settings: topomap::TopoSettings::default(),
+            topo_map: None,
+            topo_shown: Vec::new(),
+            artifact_settings: artifacts::ArtifactSettings::default(),
+            ica_algorithm: ica::Algorithm::FastIca,
+            ica: None,
+            ica_worker: None,
+            ica_component: 0,
+            ica_status: String::new(),
+            feedback_settings: neurofeedback::FeedbackSettings::default(),
+            feedback_threshold: String::from("0"),
+            feedback_path: String::from("neurofeedback.csv"),
+            feedback_status: String::new(),
+            neurofeedback: None,
+            ssvep_settings: ssvep::SsvepSettings::default(),
+            ssvep_frequencies: String::from("8.57, 10, 12, 15"),
+            ssvep: None,
+            ssvep_status: String::new()},
         waveform_drawers: Vec::<WaveformDrawer>::new(),
+        layout: layout::Layout::default(),
+        preset: None,
+        annotations: Vec::<annotations::Annotation>::new(),
         app_data: std::sync::Arc::new(std::sync::Mutex::new(AppData{
             data_source: appstate::DataSource::NoSource,
+            source_path: None,
             wave_data: None,
             streaming_data: None})),
         ticker: Ticker::default()
@@ -78,8 +189,37 @@ pub fn main() {
 
     // end waveform create
 
+    //command line: --presets <file> picks the presets file and --preset <name> loads one at startup
+    let args: Vec<String> = std::env::args().collect();
+    let arg_value = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i+1)).cloned();
+    if let Some(presets_path) = arg_value("--presets") {
+        app.gui_data.preset_path = std::path::PathBuf::from(presets_path);
+    }
+    match presets::PresetFile::load(&app.gui_data.preset_path) {
+        Ok(preset_file) => app.gui_data.preset_file = preset_file,
+        Err(e) => println!("{}", e),
+    }
+    if let Some(name) = arg_value("--preset") {
+        match app.gui_data.preset_file.presets.get(&name).cloned() {
+            Some(preset) => {
+                println!("Loading preset {:?}.", name);
+                app.apply_preset(&preset);
+                app.gui_data.preset_name = name;
+            },
+            None => println!("No preset called {:?} in {:?}.", name, app.gui_data.preset_path),
+        }
+    }
+
+    //--session <file> reopens a saved workspace, this needs the display so comes after the presets
+    if let Some(session_path) = arg_value("--session") {
+        match session::Session::load(std::path::Path::new(&session_path)).and_then(|session| session.restore(&mut app, &display)) {
+            Ok(()) => app.gui_data.session_path = session_path,
+            Err(e) => println!("{}", e),
+        }
+    }
+
     // A unique identifier for each widget.
-    let ids = ui::Ids::new(ui.widget_id_generator());
+    let mut ids = ui::Ids::new(ui.widget_id_generator());
 
     // Add a `Font` to the `Ui`'s `font::Map` from file.
     let assets = find_folder::Search::KidsThenParents(3, 5).for_folder("assets").unwrap();
@@ -93,8 +233,8 @@ pub fn main() {
     // for drawing to the glium `Surface`.
     let mut renderer = conrod::backend::glium::Renderer::new(&display).unwrap();
 
-    // The image map describing each of our widget->image mappings (in our case, none).
-    let image_map = conrod::image::Map::<glium::texture::Texture2d>::new();
+    // The image map describing each of our widget->image mappings, filled from PanelImages.
+    let mut image_map = conrod::image::Map::<glium::texture::Texture2d>::new();
 
     println!("Starting main event loop.");
     let mut frame_rater=support::FrameRater::new(0);
@@ -122,10 +262,53 @@ pub fn main() {
                         ..
                     } => break 'main,
 
+                    //markers by keypress: M uses the label from the annotations panel and 1-9 are numbered
+                    //keys are left alone while a text box is being typed in
+                    glium::glutin::WindowEvent::KeyboardInput {
+                        input: glium::glutin::KeyboardInput {
+                            state: glium::glutin::ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                        ..
+                    } => {
+                        use glium::glutin::VirtualKeyCode;
+                        if ui.global_input().current.widget_capturing_keyboard.is_none() && app.gui_data.gui_display != GuiDisplay::FileOpen {
+                            let label = match key {
+                                VirtualKeyCode::M => Some(app.gui_data.marker_label.trim().to_string()),
+                                VirtualKeyCode::Key1 => Some(String::from("1")),
+                                VirtualKeyCode::Key2 => Some(String::from("2")),
+                                VirtualKeyCode::Key3 => Some(String::from("3")),
+                                VirtualKeyCode::Key4 => Some(String::from("4")),
+                                VirtualKeyCode::Key5 => Some(String::from("5")),
+                                VirtualKeyCode::Key6 => Some(String::from("6")),
+                                VirtualKeyCode::Key7 => Some(String::from("7")),
+                                VirtualKeyCode::Key8 => Some(String::from("8")),
+                                VirtualKeyCode::Key9 => Some(String::from("9")),
+                                _ => None,
+                            };
+                            if let Some(label) = label {app.add_marker(&label);}
+                        }
+                    }
+
                     glium::glutin::WindowEvent::CursorMoved{ position, .. } => {
                         app.gui_data.cursor_xy=(position.x as f32,position.y as f32);
                     }
 
+                    glium::glutin::WindowEvent::Resized(size) => {
+                        app.gui_data.viewport.resize(size.width, size.height);
+                        display.gl_window().resize(size.to_physical(app.gui_data.viewport.hidpi_factor));
+                        app.layout.apply(&mut app.waveform_drawers, app.gui_data.viewport.aspect());
+                    }
+
+                    //the logical size stays put but the framebuffer changes, so do what a resize does rather than wait for one
+                    glium::glutin::WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                        app.gui_data.viewport.hidpi_factor = hidpi_factor;
+                        let size = glium::glutin::dpi::LogicalSize::new(app.gui_data.viewport.width, app.gui_data.viewport.height);
+                        display.gl_window().resize(size.to_physical(hidpi_factor));
+                        app.layout.apply(&mut app.waveform_drawers, app.gui_data.viewport.aspect());
+                    }
+
                     _ => (),
                 },
                 _ => (),
@@ -145,7 +328,8 @@ pub fn main() {
 
 
 
-        ui::gui(ui.set_widgets(), &ids, &display, &mut app);
+        ui::gui(ui.set_widgets(), &mut ids, &display, &mut app);
+        app.gui_data.panel_images.upload(&display, &mut image_map);
 
         // Render the `Ui` and then display it on the screen.
         let mut target = display.draw();
@@ -159,7 +343,7 @@ pub fn main() {
             for wfd in &mut app.waveform_drawers {
                 //gliumtexdraw.draw(&mut target,&waveform_textures[i as usize],0.0,wy(400.0-250.0*i as f64),wx(1600.0),wy(192.0));
 
-                wfd.generate_and_draw_texture(&mut target);
+                wfd.generate_and_draw_texture(&mut target, &app.gui_data.viewport);
             }
 
             //###### MY DRAWING ENDS HERE ######
@@ -0,0 +1,73 @@
+use std;
+use rustfft;
+
+//longest half wavelet in seconds, stops the lowest rows from needing minutes of data
+const MAX_HALF_SECONDS: f32 = 8.0;
+
+//Complex Morlet wavelets for a continuous wavelet transform on the same frequency rows as the stft,
+//row k is k*sample_rate/fft_len Hz. Each wavelet is a gaussian of cycles/(2 pi f) seconds standard deviation
+//under a complex sinusoid, so low frequencies get long windows and high frequencies short ones.
+pub struct MorletBank {
+    sample_rate: u32,
+    fft_len: u32,
+    dtft_samples: u32,
+    rows: u32,
+    cycles: f32,
+    kernels: Vec<Vec<rustfft::num_complex::Complex<f32>>>, //conjugated, one per row, row 0 (DC) is empty
+}
+
+impl MorletBank {
+    pub fn new(sample_rate: u32, fft_len: u32, dtft_samples: u32, rows: u32, cycles: f32) -> MorletBank {
+        let mut kernels = vec![Vec::<rustfft::num_complex::Complex<f32>>::new()];
+        for k in 1..rows {
+            let f = k as f32*sample_rate as f32/fft_len as f32;
+            let sigma = cycles/(2.0*std::f32::consts::PI*f)*sample_rate as f32; //in samples
+            let half = ((3.5*sigma).ceil() as usize).min((MAX_HALF_SECONDS*sample_rate as f32) as usize);
+            let gauss: Vec<f32> = (0..2*half+1).map(|i| {
+                let n = i as f32 - half as f32;
+                (-n*n/(2.0*sigma*sigma)).exp()
+            }).collect();
+            //scaled like a dtft_samples long rectangular stft, so a sine of amplitude a comes out as a*dtft_samples/2
+            let scale = dtft_samples as f32/gauss.iter().sum::<f32>();
+            kernels.push(gauss.iter().enumerate().map(|(i, g)| {
+                let phase = -2.0*std::f32::consts::PI*f*(i as f32 - half as f32)/sample_rate as f32;
+                rustfft::num_complex::Complex{re: g*scale*phase.cos(), im: g*scale*phase.sin()}
+            }).collect());
+        }
+        MorletBank{
+            sample_rate: sample_rate,
+            fft_len: fft_len,
+            dtft_samples: dtft_samples,
+            rows: rows,
+            cycles: cycles,
+            kernels: kernels,
+        }
+    }
+
+    pub fn matches(&self, sample_rate: u32, fft_len: u32, dtft_samples: u32, rows: u32, cycles: f32) -> bool {
+        self.sample_rate == sample_rate && self.fft_len == fft_len && self.dtft_samples == dtft_samples && self.rows == rows && self.cycles == cycles
+    }
+
+    //how many samples the longest wavelet needs
+    pub fn longest(&self) -> usize {
+        self.kernels.iter().map(|k| k.len()).max().unwrap_or(0)
+    }
+
+    //signal ends at the newest sample and each row's wavelet is centred as late as its length allows,
+    //so the high rows are nearly current and the low rows lag by half their wavelet
+    //the result is laid out like an fft of fft_len so it can go through the same colouring and band powers
+    pub fn transform(&self, signal: &[f32]) -> Vec<rustfft::num_complex::Complex<f32>> {
+        let mut spectrum = vec![rustfft::num_complex::Complex{re: 0.0, im: 0.0}; self.fft_len as usize];
+        for (k, kernel) in self.kernels.iter().enumerate().skip(1) {
+            let n = std::cmp::min(kernel.len(), signal.len());
+            let segment = &signal[signal.len()-n..];
+            let kernel = &kernel[kernel.len()-n..]; //missing history counts as zeros
+            let mut sum = rustfft::num_complex::Complex{re: 0.0, im: 0.0};
+            for (s, w) in segment.iter().zip(kernel.iter()) {
+                sum = sum + *w * *s;
+            }
+            spectrum[k] = sum;
+        }
+        spectrum
+    }
+}
@@ -0,0 +1,174 @@
+use std;
+use rustfft;
+
+//Discrete prolate spheroidal sequences for multitaper spectra. The tapers are the eigenvectors with the largest eigenvalues
+//of the symmetric tridiagonal matrix with diagonal ((N-1-2n)/2)^2 cos(2 pi W) and off diagonal n(N-n)/2, W = NW/N.
+//Each is orthogonal to the others and as concentrated as possible inside +-W, so averaging the spectra they give
+//cuts the variance of a single window by about the number of tapers for a resolution of 2W.
+pub struct Tapers {
+    len: usize,
+    nw: f32,
+    count: u32,
+    tapers: Vec<Vec<f32>>, //unit energy
+}
+
+//how many eigenvalues of the matrix are below x, by counting the negative pivots of T - xI
+fn below(diag: &[f64], off: &[f64], x: f64) -> usize {
+    let mut count = 0;
+    let mut q = 1.0;
+    for n in 0..diag.len() {
+        let e2 = if n == 0 {0.0} else {off[n]*off[n]};
+        q = diag[n] - x - if q != 0.0 {e2/q} else {e2/std::f64::EPSILON};
+        if q < 0.0 {count += 1;}
+    }
+    count
+}
+
+//solves (T - shift I) x = b in place, a tridiagonal system so one sweep down and one back
+fn solve(diag: &[f64], off: &[f64], shift: f64, b: &mut Vec<f64>) {
+    let n = diag.len();
+    let mut c = vec![0.0f64; n];
+    let mut pivot = diag[0] - shift;
+    for i in 0..n {
+        if i > 0 {
+            pivot = diag[i] - shift - off[i]*c[i-1];
+            b[i] -= off[i]*b[i-1];
+        }
+        if pivot.abs() < 1e-12 {pivot = 1e-12;} //the shift is an eigenvalue so this is nearly singular, which is the point
+        if i+1 < n {c[i] = off[i+1]/pivot;}
+        b[i] /= pivot;
+    }
+    for i in (0..n-1).rev() {
+        b[i] -= c[i]*b[i+1];
+    }
+}
+
+impl Tapers {
+    //count is capped at 2NW-1, past that the tapers leak too much outside the band to be worth having
+    pub fn new(len: usize, nw: f32, count: u32) -> Tapers {
+        let count = std::cmp::max(1, std::cmp::min(count, Tapers::max_tapers(nw)));
+        let n = len as f64;
+        let w = nw as f64/n;
+        let diag: Vec<f64> = (0..len).map(|i| {
+            let d = (n - 1.0 - 2.0*i as f64)/2.0;
+            d*d*(2.0*std::f64::consts::PI*w).cos()
+        }).collect();
+        let off: Vec<f64> = (0..len).map(|i| i as f64*(n - i as f64)/2.0).collect();
+        let bound = diag.iter().fold(0.0f64, |m, d| m.max(d.abs())) + 2.0*off.iter().fold(0.0f64, |m, e| m.max(*e));
+
+        let mut tapers = Vec::<Vec<f32>>::new();
+        for k in 0..std::cmp::min(count as usize, len) {
+            //bisect for the k-th largest eigenvalue
+            let target = len - 1 - k;
+            let (mut low, mut high) = (-bound, bound);
+            for _ in 0..100 {
+                let mid = (low + high)/2.0;
+                if below(&diag, &off, mid) > target {high = mid;} else {low = mid;}
+            }
+            let eigenvalue = (low + high)/2.0;
+
+            //then inverse iteration for its eigenvector
+            let mut v: Vec<f64> = (0..len).map(|i| 1.0 + (i % 7) as f64*0.01).collect();
+            for _ in 0..3 {
+                solve(&diag, &off, eigenvalue + bound*1e-10, &mut v);
+                let norm = v.iter().map(|x| x*x).sum::<f64>().sqrt();
+                for x in v.iter_mut() {*x /= norm;}
+            }
+            //the sign is arbitrary, make even tapers positive in the middle and odd ones start positive
+            let sign = if k % 2 == 0 {v.iter().sum::<f64>()} else {v.iter().enumerate().map(|(i, x)| (len as f64 - 1.0 - 2.0*i as f64)*x).sum::<f64>()};
+            tapers.push(v.iter().map(|x| if sign < 0.0 {-*x as f32} else {*x as f32}).collect());
+        }
+        Tapers{
+            len: len,
+            nw: nw,
+            count: count,
+            tapers: tapers,
+        }
+    }
+
+    pub fn max_tapers(nw: f32) -> u32 {
+        std::cmp::max(1, (2.0*nw).floor() as i32 - 1) as u32
+    }
+
+    pub fn matches(&self, len: usize, nw: f32, count: u32) -> bool {
+        self.len == len && self.nw == nw && self.count == std::cmp::max(1, std::cmp::min(count, Tapers::max_tapers(nw)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    //the average power over the tapers as magnitudes, so it goes through the same colouring and band powers as an fft
+    //a short signal is zero filled at the front and each tapered copy is zero padded up to fft_len,
+    //so the frequency bins don't move while the history fills up
+    pub fn spectrum(&self, signal: &[f32], fft_len: u32) -> Vec<rustfft::num_complex::Complex<f32>> {
+        let len = self.len;
+        let fft_len = std::cmp::max(fft_len as usize, len);
+        let offset = len - std::cmp::min(len, signal.len());
+        let signal = &signal[signal.len() - (len - offset)..];
+        let mut power = vec![0.0f32; fft_len];
+        let mut fft_planner = rustfft::FFTplanner::new(false);
+        let fft = fft_planner.plan_fft(fft_len);
+        for taper in &self.tapers {
+            let mut tapered = vec![rustfft::num_complex::Complex{re: 0.0, im: 0.0}; fft_len];
+            for (i, s) in signal.iter().enumerate() {
+                //scaled to the energy of a rectangular window so the levels match the other transforms
+                tapered[offset+i].re = s*taper[offset+i]*(len as f32).sqrt();
+            }
+            let mut spectrum = tapered.clone();
+            fft.process(&mut tapered, &mut spectrum);
+            for (p, c) in power.iter_mut().zip(spectrum.iter()) {*p += c.norm_sqr();}
+        }
+        power.iter().map(|p| rustfft::num_complex::Complex{re: (p/self.tapers.len() as f32).sqrt(), im: 0.0}).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the share of a taper's energy inside +-W, the eigenvalue of the sinc kernel the tapers are defined by
+    fn concentration(taper: &[f32], w: f64) -> f64 {
+        let mut sum = 0.0;
+        for (i, a) in taper.iter().enumerate() {
+            for (j, b) in taper.iter().enumerate() {
+                let d = i as f64 - j as f64;
+                let kernel = if i == j {2.0*w} else {(2.0*std::f64::consts::PI*w*d).sin()/(std::f64::consts::PI*d)};
+                sum += *a as f64 * *b as f64 * kernel;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn tapers_are_orthonormal() {
+        let tapers = Tapers::new(128, 4.0, 7);
+        assert_eq!(tapers.tapers.len(), 7);
+        for (i, a) in tapers.tapers.iter().enumerate() {
+            for (j, b) in tapers.tapers.iter().enumerate() {
+                let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x*y).sum();
+                let expected = if i == j {1.0} else {0.0};
+                assert!((dot - expected).abs() < 1e-4, "tapers {} and {}: {}", i, j, dot);
+            }
+        }
+    }
+
+    #[test]
+    fn tapers_come_most_concentrated_first() {
+        let (len, nw) = (128, 4.0);
+        let tapers = Tapers::new(len, nw, 7);
+        let eigenvalues: Vec<f64> = tapers.tapers.iter().map(|t| concentration(t, nw as f64/len as f64)).collect();
+        assert!(eigenvalues[0] > 0.9999, "{:?}", eigenvalues);
+        assert!(eigenvalues[6] > 0.9, "{:?}", eigenvalues);
+        for pair in eigenvalues.windows(2) {
+            assert!(pair[0] > pair[1], "{:?}", eigenvalues);
+        }
+    }
+
+    #[test]
+    fn taper_count_is_capped() {
+        assert_eq!(Tapers::max_tapers(3.0), 5);
+        assert_eq!(Tapers::new(64, 3.0, 9).tapers.len(), 5);
+        assert_eq!(Tapers::new(64, 0.5, 3).tapers.len(), 1);
+    }
+}
@@ -0,0 +1,478 @@
+use std;
+use std::io::Write;
+
+use portaudio as pa;
+
+use bandpower::{BandPowerHistory, BandRatio};
+
+//the tone is generated on its own PortAudio output stream
+const TONE_SAMPLE_RATE: f64 = 44_100.0;
+const TONE_FRAMES: u32 = 256;
+//pitch goes up an octave from here as the metric moves further past the threshold
+const TONE_BASE_HZ: f32 = 440.0;
+const TONE_VOLUME: f32 = 0.2;
+//seconds for the tone to fade in or out, so it doesn't click
+const TONE_FADE_S: f32 = 0.02;
+//fewer values than this in the adaptive window and the fixed threshold is used
+const ADAPTIVE_MIN_VALUES: usize = 10;
+
+//what is trained, indices are into the filter options' bands and ratios
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metric {
+    Power(usize), //in dB
+    Relative(usize), //the band's share of the total over all bands
+    Ratio(usize),
+}
+
+impl Metric {
+    //None if the frame doesn't have the band or the ratio doesn't exist
+    pub fn value(&self, powers: &[f32], ratios: &[BandRatio]) -> Option<f32> {
+        match *self {
+            Metric::Power(b) if b < powers.len() => Some(10.0*powers[b].max(1e-30).log10()),
+            Metric::Relative(b) if b < powers.len() => {
+                let total: f32 = powers.iter().sum();
+                Some(if total > 0.0 {powers[b]/total} else {0.0})
+            },
+            Metric::Ratio(r) if r < ratios.len() && ratios[r].numerator < powers.len() && ratios[r].denominator < powers.len() => Some(ratios[r].value(powers)),
+            _ => None,
+        }
+    }
+}
+
+//which side of the threshold is rewarded, theta/beta training for instance rewards below
+#[derive(Clone, Copy, PartialEq)]
+pub enum Reward {
+    Above,
+    Below,
+}
+
+impl Reward {
+    pub const ALL: [Reward; 2] = [Reward::Above, Reward::Below];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Reward::Above => "Reward above threshold",
+            Reward::Below => "Reward below threshold",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FeedbackKind {
+    Bar,
+    Wash,
+    Tone,
+}
+
+impl FeedbackKind {
+    pub const ALL: [FeedbackKind; 3] = [FeedbackKind::Bar, FeedbackKind::Wash, FeedbackKind::Tone];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FeedbackKind::Bar => "Bar",
+            FeedbackKind::Wash => "Colour wash",
+            FeedbackKind::Tone => "Tone",
+        }
+    }
+}
+
+//what the neurofeedback panel is set to, the drawer, metric and direction are copied when a session starts
+pub struct FeedbackSettings {
+    pub drawer: usize, //the metric comes from this drawer's band powers
+    pub metric: Metric,
+    pub reward: Reward,
+    pub adaptive: bool,
+    pub threshold: f32, //in the metric's units, used when not adaptive
+    pub target_rate: f32, //adaptive: the share of the time the threshold should be met
+    pub adaptive_s: f32, //adaptive: how far back the threshold looks
+    pub smoothing_s: f32, //the metric is averaged over this long before it is compared
+    pub kind: FeedbackKind,
+}
+
+impl Default for FeedbackSettings {
+    fn default() -> FeedbackSettings {
+        FeedbackSettings{
+            drawer: 0,
+            metric: Metric::Power(2), //alpha in the default bands
+            reward: Reward::Above,
+            adaptive: true,
+            threshold: 0.0,
+            target_rate: 0.6,
+            adaptive_s: 30.0,
+            smoothing_s: 1.0,
+            kind: FeedbackKind::Bar,
+        }
+    }
+}
+
+//shared with the tone thread
+struct ToneControl {
+    on: bool,
+    pitch_hz: f32,
+    stop: bool,
+    error: Option<String>,
+}
+
+//a sine on the default output device, on while the metric is rewarded
+struct Tone {
+    control: std::sync::Arc<std::sync::Mutex<ToneControl>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Tone {
+    fn start() -> Tone {
+        let control = std::sync::Arc::new(std::sync::Mutex::new(ToneControl{on: false, pitch_hz: TONE_BASE_HZ, stop: false, error: None}));
+        let closure_control = control.clone();
+        let thread = std::thread::spawn(move || {
+            if let Err(e) = Tone::play(&closure_control) {
+                closure_control.lock().unwrap().error = Some(format!("Tone output failed: {}", e));
+            }
+        });
+        Tone{control: control, thread: Some(thread)}
+    }
+
+    fn play(control: &std::sync::Mutex<ToneControl>) -> Result<(), pa::Error> {
+        let pa = pa::PortAudio::new()?;
+        let def_output = pa.default_output_device()?;
+        let output_info = pa.device_info(def_output)?;
+        let output_params = pa::StreamParameters::<f32>::new(def_output, 1, true, output_info.default_low_output_latency);
+        let settings = pa::OutputStreamSettings::new(output_params, TONE_SAMPLE_RATE, TONE_FRAMES);
+        let mut stream = pa.open_blocking_stream(settings)?;
+        stream.start()?;
+
+        let step = 1.0/(TONE_FADE_S*TONE_SAMPLE_RATE as f32);
+        let mut phase = 0.0f32;
+        let mut gain = 0.0f32;
+        loop {
+            let (on, pitch_hz) = {
+                let control = control.lock().unwrap();
+                if control.stop {break;}
+                (control.on, control.pitch_hz)
+            };
+            let frames = match stream.write_available()? {
+                pa::StreamAvailable::Frames(frames) => std::cmp::min(frames as u32, TONE_FRAMES),
+                _ => 0,
+            };
+            if frames == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                continue;
+            }
+            let increment = 2.0*std::f32::consts::PI*pitch_hz/TONE_SAMPLE_RATE as f32;
+            stream.write(frames, |output| {
+                for sample in output.iter_mut() {
+                    gain = if on {(gain + step).min(1.0)} else {(gain - step).max(0.0)};
+                    *sample = TONE_VOLUME*gain*phase.sin();
+                    phase = (phase + increment) % (2.0*std::f32::consts::PI);
+                }
+            })?;
+        }
+        stream.stop()?;
+        Ok(())
+    }
+
+    fn set(&self, on: bool, pitch_hz: f32) {
+        let mut control = self.control.lock().unwrap();
+        control.on = on;
+        control.pitch_hz = pitch_hz;
+    }
+
+    fn error(&self) -> Option<String> {
+        self.control.lock().unwrap().error.clone()
+    }
+}
+
+//waits for the stream to close, so a tone started straight after never has two streams open at once
+//the thread only writes what the device has room for before it looks at stop again, so this is quick
+impl Drop for Tone {
+    fn drop(&mut self) {
+        self.control.lock().unwrap().stop = true;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+//A running session: each new frame of the drawer's band powers gives a metric value, which is smoothed,
+//compared with the threshold and written to the log. When it stops, a summary goes next to the log as <name>.summary.txt.
+pub struct Neurofeedback {
+    pub drawer: usize,
+    pub metric: Metric,
+    pub metric_name: String,
+    pub reward: Reward,
+    pub path: std::path::PathBuf,
+    log: std::io::BufWriter<std::fs::File>,
+    started_ticks: u64,
+    last_ticks: u64, //the newest band power frame used so far
+    raw: std::collections::VecDeque<(u64, f32)>, //within the smoothing time
+    recent: std::collections::VecDeque<(u64, f32)>, //smoothed values within the adaptive window
+    pub value: Option<f32>, //smoothed
+    pub threshold: f32,
+    pub rewarded: bool,
+    tone: Option<Tone>,
+    //for the summary
+    frames: usize,
+    rewarded_frames: usize,
+    episodes: usize,
+    episode_start: u64,
+    longest_ms: u64,
+    sum: f64,
+    sum_sq: f64,
+    min: f32,
+    max: f32,
+    first_threshold: Option<f32>,
+}
+
+impl Neurofeedback {
+    //creates the log straight away so a bad path is reported before the session starts
+    pub fn start(settings: &FeedbackSettings, metric_name: &str, path: &std::path::Path, ticks: u64) -> Result<Neurofeedback, String> {
+        let file = std::fs::File::create(path).map_err(|e| format!("Unable to create session log {:?}: {}", path, e))?;
+        let mut log = std::io::BufWriter::new(file);
+        writeln!(log, "time_s,{},smoothed,threshold,rewarded", metric_name.replace(',', " "))
+            .map_err(|e| format!("Unable to write session log {:?}: {}", path, e))?;
+        Ok(Neurofeedback{
+            drawer: settings.drawer,
+            metric: settings.metric,
+            metric_name: metric_name.to_string(),
+            reward: settings.reward,
+            path: path.to_path_buf(),
+            log: log,
+            started_ticks: ticks,
+            last_ticks: ticks,
+            raw: std::collections::VecDeque::new(),
+            recent: std::collections::VecDeque::new(),
+            value: None,
+            threshold: settings.threshold,
+            rewarded: false,
+            tone: None,
+            frames: 0,
+            rewarded_frames: 0,
+            episodes: 0,
+            episode_start: ticks,
+            longest_ms: 0,
+            sum: 0.0,
+            sum_sq: 0.0,
+            min: std::f32::INFINITY,
+            max: std::f32::NEG_INFINITY,
+            first_threshold: None,
+        })
+    }
+
+    //takes in every frame newer than the last one seen, then drives the tone
+    pub fn update(&mut self, history: &BandPowerHistory, ratios: &[BandRatio], settings: &FeedbackSettings) -> Result<(), String> {
+        let new: Vec<(u64, f32)> = history.frames.iter()
+            .filter(|f| f.0 > self.last_ticks)
+            .filter_map(|f| self.metric.value(&f.1, ratios).map(|v| (f.0, v)))
+            .collect();
+        for (ticks, v) in new {
+            self.last_ticks = ticks;
+            self.frame(ticks, v, settings)?;
+        }
+
+        if settings.kind == FeedbackKind::Tone {
+            if self.tone.is_none() {self.tone = Some(Tone::start());}
+        } else {
+            self.tone = None;
+        }
+        if let Some(ref tone) = self.tone {
+            tone.set(self.rewarded, TONE_BASE_HZ*2.0f32.powf(self.margin()));
+            if let Some(e) = tone.error() {return Err(e);}
+        }
+        Ok(())
+    }
+
+    fn frame(&mut self, ticks: u64, v: f32, settings: &FeedbackSettings) -> Result<(), String> {
+        let smoothing_ms = (settings.smoothing_s*1000.0) as u64;
+        self.raw.push_back((ticks, v));
+        while self.raw.front().map(|r| r.0 + smoothing_ms < ticks).unwrap_or(false) {self.raw.pop_front();}
+        let smoothed = self.raw.iter().map(|r| r.1).sum::<f32>()/self.raw.len() as f32;
+
+        let adaptive_ms = (settings.adaptive_s*1000.0) as u64;
+        self.recent.push_back((ticks, smoothed));
+        while self.recent.front().map(|r| r.0 + adaptive_ms < ticks).unwrap_or(false) {self.recent.pop_front();}
+
+        //adaptive thresholds sit where the recent values would have been rewarded target_rate of the time
+        self.threshold = if settings.adaptive && self.recent.len() >= ADAPTIVE_MIN_VALUES {
+            let mut sorted: Vec<f32> = self.recent.iter().map(|r| r.1).collect();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let q = match self.reward {Reward::Above => 1.0 - settings.target_rate, Reward::Below => settings.target_rate};
+            sorted[((q.max(0.0).min(1.0)*(sorted.len() - 1) as f32).round()) as usize]
+        } else {
+            settings.threshold
+        };
+        let rewarded = match self.reward {
+            Reward::Above => smoothed > self.threshold,
+            Reward::Below => smoothed < self.threshold,
+        };
+
+        if rewarded && !self.rewarded {
+            self.episodes += 1;
+            self.episode_start = ticks;
+        }
+        if rewarded {
+            self.rewarded_frames += 1;
+            self.longest_ms = std::cmp::max(self.longest_ms, ticks - self.episode_start);
+        }
+        self.rewarded = rewarded;
+        self.value = Some(smoothed);
+        self.frames += 1;
+        self.sum += smoothed as f64;
+        self.sum_sq += smoothed as f64*smoothed as f64;
+        self.min = self.min.min(smoothed);
+        self.max = self.max.max(smoothed);
+        if self.first_threshold.is_none() {self.first_threshold = Some(self.threshold);}
+
+        writeln!(self.log, "{:.3},{},{},{},{}", (ticks - self.started_ticks) as f64/1000.0, v, smoothed, self.threshold, if rewarded {1} else {0})
+            .map_err(|e| format!("Unable to write session log {:?}: {}", self.path, e))
+    }
+
+    //the lowest and highest smoothed values in the adaptive window, with the threshold, what the bar is scaled to
+    pub fn range(&self) -> (f32, f32) {
+        let (lo, hi) = self.recent.iter().fold((self.threshold, self.threshold), |r, v| (r.0.min(v.1), r.1.max(v.1)));
+        if hi > lo {(lo, hi)} else {(lo - 1.0, hi + 1.0)}
+    }
+
+    //how far past the threshold the metric is, as a share of the range, 0 when it isn't rewarded
+    pub fn margin(&self) -> f32 {
+        match self.value {
+            Some(v) if self.rewarded => {
+                let (lo, hi) = self.range();
+                ((v - self.threshold).abs()/(hi - lo)).min(1.0)
+            },
+            _ => 0.0,
+        }
+    }
+
+    pub fn seconds(&self, ticks: u64) -> f32 {
+        ticks.saturating_sub(self.started_ticks) as f32/1000.0
+    }
+
+    pub fn summary(&self, ticks: u64) -> String {
+        if self.frames == 0 {return format!("{:.0} s, no frames yet", self.seconds(ticks));}
+        let mean = self.sum/self.frames as f64;
+        let sd = (self.sum_sq/self.frames as f64 - mean*mean).max(0.0).sqrt();
+        format!("{:.0} s, rewarded {:.0}% of {} frames in {} episodes (longest {:.1} s), {} {:.3} ± {:.3}",
+            self.seconds(ticks), 100.0*self.rewarded_frames as f32/self.frames as f32, self.frames, self.episodes,
+            self.longest_ms as f32/1000.0, self.metric_name, mean, sd)
+    }
+
+    //closes the log and writes the summary file
+    pub fn stop(mut self, ticks: u64) -> Result<String, String> {
+        self.tone = None;
+        self.log.flush().map_err(|e| format!("Unable to write session log {:?}: {}", self.path, e))?;
+        let mean = if self.frames > 0 {self.sum/self.frames as f64} else {0.0};
+        let sd = if self.frames > 0 {(self.sum_sq/self.frames as f64 - mean*mean).max(0.0).sqrt()} else {0.0};
+        let mut text = String::new();
+        text += &format!("metric: {}\n", self.metric_name);
+        text += &format!("reward: {}\n", self.reward.name());
+        text += &format!("duration_s: {:.1}\n", self.seconds(ticks));
+        text += &format!("frames: {}\n", self.frames);
+        text += &format!("rewarded_frames: {}\n", self.rewarded_frames);
+        text += &format!("rewarded_percent: {:.1}\n", if self.frames > 0 {100.0*self.rewarded_frames as f32/self.frames as f32} else {0.0});
+        text += &format!("episodes: {}\n", self.episodes);
+        text += &format!("longest_episode_s: {:.2}\n", self.longest_ms as f32/1000.0);
+        text += &format!("mean: {}\n", mean);
+        text += &format!("sd: {}\n", sd);
+        if self.frames > 0 {
+            text += &format!("min: {}\n", self.min);
+            text += &format!("max: {}\n", self.max);
+        }
+        if let Some(first) = self.first_threshold {
+            text += &format!("first_threshold: {}\n", first);
+            text += &format!("last_threshold: {}\n", self.threshold);
+        }
+        let summary_path = self.path.with_extension("summary.txt");
+        std::fs::write(&summary_path, text).map_err(|e| format!("Unable to write session summary {:?}: {}", summary_path, e))?;
+        Ok(format!("{}. Saved to {}", self.summary(ticks), self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the metric is the first band's share of two, so each frame's value is exactly what is pushed
+    fn settings(reward: Reward, adaptive: bool) -> FeedbackSettings {
+        FeedbackSettings{
+            metric: Metric::Relative(0),
+            reward: reward,
+            adaptive: adaptive,
+            threshold: 0.5,
+            smoothing_s: 0.0,
+            ..FeedbackSettings::default()
+        }
+    }
+
+    fn session(name: &str, settings: &FeedbackSettings) -> Neurofeedback {
+        let path = std::env::temp_dir().join(format!("neurofeedback_{}_{}.csv", std::process::id(), name));
+        Neurofeedback::start(settings, "alpha share", &path, 0).unwrap()
+    }
+
+    //one frame every 100 ms from 100 ms on
+    fn feed(nf: &mut Neurofeedback, values: &[f32], settings: &FeedbackSettings) -> Vec<bool> {
+        let mut history = BandPowerHistory::new(values.len());
+        let mut rewarded = Vec::new();
+        for (i, v) in values.iter().enumerate() {
+            history.push(100*(i as u64 + 1), vec![*v, 1.0 - v]);
+            nf.update(&history, &[], settings).unwrap();
+            rewarded.push(nf.rewarded);
+        }
+        rewarded
+    }
+
+    fn remove(nf: &Neurofeedback) {
+        let _ = std::fs::remove_file(&nf.path);
+        let _ = std::fs::remove_file(nf.path.with_extension("summary.txt"));
+    }
+
+    #[test]
+    fn adaptive_threshold_rewards_the_target_share() {
+        let mut state = 7u64;
+        let values: Vec<f32> = (0..1200).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            0.1 + 0.8*(state >> 40) as f32/(1u64 << 24) as f32
+        }).collect();
+        for reward in &Reward::ALL {
+            let settings = settings(*reward, true);
+            let mut nf = session("adaptive", &settings);
+            let rewarded = feed(&mut nf, &values, &settings);
+            remove(&nf);
+            //once the 30 s window has filled
+            let settled = &rewarded[300..];
+            let rate = settled.iter().filter(|r| **r).count() as f32/settled.len() as f32;
+            assert!((rate - settings.target_rate).abs() < 0.05, "{}: {}", reward.name(), rate);
+        }
+    }
+
+    #[test]
+    fn fixed_threshold_rewards_the_chosen_side() {
+        let values = [0.7, 0.3, 0.9, 0.1];
+        for &(reward, expected) in &[(Reward::Above, [true, false, true, false]), (Reward::Below, [false, true, false, true])] {
+            let settings = settings(reward, false);
+            let mut nf = session("fixed", &settings);
+            let rewarded = feed(&mut nf, &values, &settings);
+            remove(&nf);
+            assert_eq!(rewarded, expected.to_vec(), "{}", reward.name());
+            assert_eq!(nf.threshold, 0.5);
+        }
+    }
+
+    #[test]
+    fn summary_counts_frames_and_episodes() {
+        let settings = settings(Reward::Above, false);
+        let mut nf = session("summary", &settings);
+        //rewarded for one frame, then for three in a row
+        feed(&mut nf, &[0.7, 0.3, 0.3, 0.7, 0.7, 0.7, 0.3], &settings);
+        assert_eq!(nf.summary(1000), "1 s, rewarded 57% of 7 frames in 2 episodes (longest 0.2 s), alpha share 0.529 ± 0.198");
+        let path = nf.path.clone();
+        nf.stop(1000).unwrap();
+        let text = std::fs::read_to_string(path.with_extension("summary.txt")).unwrap();
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(path.with_extension("summary.txt"));
+        for line in &["frames: 7", "rewarded_frames: 4", "episodes: 2", "longest_episode_s: 0.20", "min: 0.3", "max: 0.7", "last_threshold: 0.5"] {
+            assert!(text.lines().any(|l| l == *line), "{} missing from\n{}", line, text);
+        }
+        assert_eq!(log.lines().count(), 8);
+        assert_eq!(log.lines().nth(4).unwrap(), "0.400,0.7,0.7,0.5,1");
+    }
+}
@@ -1,13 +1,17 @@
 use std;
 use regex;
 
+use channelinfo::{ChannelInfo};
+
 
 #[allow(dead_code)]
 pub struct OpenBCIFile {
     pub path: String,
     pub channels: u32,
     pub sample_rate_hz: u32,
-    pub samples: Vec<Vec<f32>>
+    pub samples: Vec<Vec<f32>>,
+    pub aux: Vec<Vec<f32>>, //the three accelerometer columns, in g
+    pub info: Vec<ChannelInfo>,
 }
 
 #[allow(dead_code)]
@@ -18,10 +22,20 @@ impl OpenBCIFile {
         let f = std::io::BufReader::new(f);
         let mut channels: i32 = -1;
         let mut samples: Vec<Vec<f32>> = Vec::<Vec<f32>>::new();
+        let mut aux: Vec<Vec<f32>> = vec![Vec::<f32>::new(); 3];
+        let mut sample_rate_hz: u32 = 0;
+        let mut units = "";
+        let sample_rate_re = regex::Regex::new(r"Sample Rate\s*=\s*([0-9.]+)").unwrap();
         for linew in f.lines() {
             let line = linew.unwrap();
             if line.len() > 0 && line.as_bytes()[0] == '%' as u8 {
 				println!("Header line: {:?}", line);
+                if let Some(caps) = sample_rate_re.captures(&line) {
+                    sample_rate_hz = caps[1].parse::<f32>().unwrap_or(0.0) as u32;
+                }
+                if line.contains("microvolts") {
+                    units = "µV";
+                }
                 continue;
             }
 
@@ -35,13 +49,22 @@ impl OpenBCIFile {
             for i in 0..channels {
                 samples[i as usize].push(fields[1 + i as usize].parse::<f32>().expect("Found non-float amplitude in file."));
             }
+            for a in 0..3 {
+                aux[a].push(fields.get(1 + channels as usize + a).and_then(|f| f.parse::<f32>().ok()).unwrap_or(0.0));
+            }
 
         }
+        if sample_rate_hz == 0 {
+            println!("No sample rate in the header, assuming 200 Hz.");
+            sample_rate_hz = 200;
+        }
         OpenBCIFile {
             path: String::from(filename),
             channels: channels as u32,
-            sample_rate_hz: 0,
-            samples: samples
+            sample_rate_hz: sample_rate_hz,
+            samples: samples,
+            aux: aux,
+            info: (0..channels as u32).map(|c| ChannelInfo::new(c, units)).collect(),
         }
     }
 
@@ -0,0 +1,85 @@
+use std;
+use conrod;
+use glium;
+
+use city2d::City2D;
+
+//Images for the settings panels, like the ERSP map and its colorbar.
+//Panels draw their pixels into a City2D, the main loop turns them into textures in conrod's image map
+//(which it owns) and the panel shows them with widget::Image using the id that comes back.
+pub struct PanelImages {
+    pending: Vec<(&'static str, City2D)>,
+    ids: Vec<(&'static str, conrod::image::Id)>,
+}
+
+impl Default for PanelImages {
+    fn default() -> PanelImages {
+        PanelImages{
+            pending: Vec::new(),
+            ids: Vec::new(),
+        }
+    }
+}
+
+impl PanelImages {
+    //replaces the image called name the next time the main loop uploads
+    pub fn set(&mut self, name: &'static str, image: City2D) {
+        self.pending.retain(|p| p.0 != name);
+        self.pending.push((name, image));
+    }
+
+    pub fn id(&self, name: &'static str) -> Option<conrod::image::Id> {
+        self.ids.iter().find(|i| i.0 == name).map(|i| i.1)
+    }
+
+    pub fn upload(&mut self, display: &glium::Display, image_map: &mut conrod::image::Map<glium::texture::Texture2d>) {
+        for (name, image) in self.pending.drain(..) {
+            let dims = (image.w, image.h);
+            //City2D never writes alpha, conrod blends images so make them opaque
+            let mut data = image.into_vec();
+            for p in data.chunks_mut(4) {p[3] = 255;}
+            let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(&data, dims);
+            let texture = match glium::texture::Texture2d::new(display, raw) {
+                Ok(texture) => texture,
+                Err(e) => {println!("Unable to create the {} texture: {:?}", name, e); continue;},
+            };
+            match self.ids.iter().find(|i| i.0 == name).map(|i| i.1) {
+                Some(id) => {image_map.replace(id, texture);},
+                None => {
+                    let id = image_map.insert(texture);
+                    self.ids.push((name, id));
+                },
+            }
+        }
+    }
+}
+
+
+//blue through white to red for values from -1 to 1, for changes either side of zero
+pub fn diverging(v: f32) -> (u8, u8, u8) {
+    let v = v.max(-1.0).min(1.0);
+    let fade = ((1.0 - v.abs())*255.0) as u8;
+    if v >= 0.0 {(255, fade, fade)} else {(fade, fade, 255)}
+}
+
+//black through blue, green and yellow to white for values from 0 to 1, for magnitudes
+pub fn sequential(v: f32) -> (u8, u8, u8) {
+    const STOPS: [(f32, f32, f32); 5] = [(0.0, 0.0, 0.0), (0.1, 0.2, 0.8), (0.1, 0.75, 0.4), (0.95, 0.9, 0.1), (1.0, 1.0, 1.0)];
+    let x = v.max(0.0).min(1.0)*(STOPS.len() - 1) as f32;
+    let i = std::cmp::min(x as usize, STOPS.len() - 2);
+    let t = x - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i+1]);
+    (((a.0 + (b.0-a.0)*t)*255.0) as u8, ((a.1 + (b.1-a.1)*t)*255.0) as u8, ((a.2 + (b.2-a.2)*t)*255.0) as u8)
+}
+
+//a vertical bar with the top of the colour map at the top
+pub fn colorbar(height: u32, colour: fn(f32) -> (u8, u8, u8), low: f32, high: f32) -> City2D {
+    let width = 8;
+    let mut image = City2D::new(width, height);
+    for y in 0..height {
+        let v = high - (high - low)*y as f32/(height - 1) as f32;
+        let (r, g, b) = colour(v);
+        image.hbar(0, y as i32, width, r, g, b);
+    }
+    image
+}
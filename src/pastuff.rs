@@ -4,6 +4,7 @@ use portaudio as pa;
 
 use appstate;
 use appstate::{AppState, StreamingData};
+use filters::{FilterChain};
 
 pub const PA_SAMPLE_RATE: f64 = 44_100.0;
 const PA_FRAMES: u32 = 1024;
@@ -19,11 +20,15 @@ pub fn pa_read_from_mic<'a>(app: &mut AppState) {
         let audio_dequeue: Vec<f32> = Vec::<f32>::new();
         let streaming_data = StreamingData {
             deque: audio_dequeue,
+            filtered: Vec::<f32>::new(),
+            filter_chain: FilterChain::new(&app.filter_data.filters, PA_SAMPLE_RATE as u32),
             channels: PA_CHANNELS as u32,
             sample_rate: PA_SAMPLE_RATE as u32,
             samples_written: 0,
             frames_lag: 0,
-            acceptable_frames_lag: 512
+            acceptable_frames_lag: 512,
+            full_scale: 1.0,
+            recorder: None,
         };
 
         let mut app_data = app.app_data.lock().unwrap();
@@ -83,13 +88,7 @@ pub fn pa_read_from_mic<'a>(app: &mut AppState) {
                 let input_samples = stream.read(in_frames).expect("PortAudio Error");
 
                 let mut app_data = closure_data.lock().unwrap();
-                app_data.streaming_data.as_mut().unwrap().samples_written += in_frames as usize;
-                let ref mut audio_dequeue = app_data.streaming_data.as_mut().unwrap().deque;
-
-            //    for i in 0..in_frames {
-                    //audio_dequeue.push( (input_samples[(2*i) as usize]+input_samples[(2*i+1) as usize]) /2.0 );
-                    audio_dequeue.extend(input_samples.into_iter());
-            //    }
+                app_data.streaming_data.as_mut().unwrap().push_samples(input_samples);
             //    println!("Read {:?} frames from the input stream.", in_frames);
             } else {
                 std::thread::sleep(std::time::Duration::from_millis(1));
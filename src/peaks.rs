@@ -0,0 +1,141 @@
+use std;
+use std::io::Write;
+use rustfft;
+
+//how many columns a track can go without a peak before it is finished
+const MAX_GAP: u32 = 3;
+//tracks that finished longer ago than this are dropped, so a long session doesn't grow forever
+const KEEP_S: f64 = 600.0;
+//tracks shorter than this are usually noise, they are kept for export but not drawn
+pub const MIN_DRAWN_POINTS: usize = 3;
+
+//a spectral peak, placed between bins by fitting a parabola to the log magnitudes either side
+#[derive(Clone, Copy)]
+pub struct Peak {
+    pub freq_hz: f32,
+    pub magnitude: f32,
+}
+
+//the k largest local maxima in the first rows bins, louder than the column's mean, loudest first
+pub fn find_peaks(spectrum: &[rustfft::num_complex::Complex<f32>], rows: usize, bin_hz: f32, k: usize) -> Vec<Peak> {
+    let rows = std::cmp::min(rows, spectrum.len());
+    if k == 0 || rows < 3 {return Vec::new();}
+    let magnitude: Vec<f32> = spectrum[..rows].iter().map(|c| c.norm()).collect();
+    let mean = magnitude.iter().sum::<f32>()/rows as f32;
+
+    let mut peaks = Vec::<Peak>::new();
+    for i in 1..rows-1 {
+        let (a, b, c) = (magnitude[i-1], magnitude[i], magnitude[i+1]);
+        if b <= mean || b < a || b <= c {continue;}
+        let (la, lb, lc) = (a.max(1e-12).ln(), b.ln(), c.max(1e-12).ln());
+        let denominator = la - 2.0*lb + lc;
+        let offset = if denominator < 0.0 {(0.5*(la - lc)/denominator).max(-0.5).min(0.5)} else {0.0};
+        peaks.push(Peak{
+            freq_hz: (i as f32 + offset)*bin_hz,
+            magnitude: (lb - 0.25*(la - lc)*offset).exp(),
+        });
+    }
+    peaks.sort_by(|p, q| q.magnitude.partial_cmp(&p.magnitude).unwrap_or(std::cmp::Ordering::Equal));
+    peaks.truncate(k);
+    peaks
+}
+
+#[derive(Clone, Copy)]
+pub struct TrackPoint {
+    pub time_s: f64,
+    pub freq_hz: f32,
+    pub magnitude: f32,
+}
+
+pub struct Track {
+    pub id: u32,
+    pub points: Vec<TrackPoint>,
+    missed: u32, //columns since the last peak, the track is finished past MAX_GAP
+}
+
+impl Track {
+    pub fn active(&self) -> bool {
+        self.missed <= MAX_GAP
+    }
+}
+
+//links the peaks of each column to the nearest peak of the column before, within max_jump_hz
+pub struct PeakTracker {
+    pub tracks: Vec<Track>,
+    next_id: u32,
+}
+
+impl PeakTracker {
+    pub fn new() -> PeakTracker {
+        PeakTracker{
+            tracks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    pub fn push(&mut self, time_s: f64, peaks: &[Peak], max_jump_hz: f32) {
+        //closest pairs first, each track and each peak used at most once
+        let mut pairs = Vec::<(f32, usize, usize)>::new();
+        for (t, track) in self.tracks.iter().enumerate().filter(|&(_, track)| track.active()) {
+            let last = track.points.last().unwrap();
+            for (p, peak) in peaks.iter().enumerate() {
+                let jump = (peak.freq_hz - last.freq_hz).abs();
+                if jump <= max_jump_hz {pairs.push((jump, t, p));}
+            }
+        }
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut track_used = vec![false; self.tracks.len()];
+        let mut peak_used = vec![false; peaks.len()];
+        for &(_, t, p) in &pairs {
+            if track_used[t] || peak_used[p] {continue;}
+            track_used[t] = true;
+            peak_used[p] = true;
+            self.tracks[t].points.push(TrackPoint{time_s: time_s, freq_hz: peaks[p].freq_hz, magnitude: peaks[p].magnitude});
+            self.tracks[t].missed = 0;
+        }
+        for (t, track) in self.tracks.iter_mut().enumerate() {
+            if !track_used[t] {track.missed += 1;}
+        }
+        for p in (0..peaks.len()).filter(|p| !peak_used[*p]) {
+            self.tracks.push(Track{
+                id: self.next_id,
+                points: vec![TrackPoint{time_s: time_s, freq_hz: peaks[p].freq_hz, magnitude: peaks[p].magnitude}],
+                missed: 0,
+            });
+            self.next_id += 1;
+        }
+        self.tracks.retain(|track| track.active() || track.points.last().map(|p| p.time_s > time_s - KEEP_S).unwrap_or(false));
+    }
+
+    //one row per point as track, time (s), frequency (Hz), magnitude, after a comment naming the drawer
+    pub fn write_csv<W: Write>(&self, f: &mut W, label: &str) -> std::io::Result<()> {
+        writeln!(f, "%{}", label)?;
+        for track in &self.tracks {
+            for point in &track.points {
+                writeln!(f, "{}, {:.4}, {:.3}, {}", track.id, point.time_s, point.freq_hz, point.magnitude)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//every drawer's tracks in one file, in the order given
+pub fn save_csv(path: &std::path::Path, trackers: &[(String, &PeakTracker)]) -> Result<usize, String> {
+    let write = || -> std::io::Result<usize> {
+        let mut f = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(f, "%track, time (s), frequency (Hz), magnitude")?;
+        let mut count = 0;
+        for &(ref label, tracker) in trackers {
+            tracker.write_csv(&mut f, label)?;
+            count += tracker.tracks.len();
+        }
+        f.flush()?;
+        Ok(count)
+    };
+    write().map_err(|e| format!("Unable to write peak tracks to {:?}: {}", path, e))
+}
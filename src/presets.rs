@@ -0,0 +1,46 @@
+use std;
+use toml;
+
+use appstate::{AppState, FilterData};
+use waveformdrawer::{WaveformDrawerSettings};
+
+pub const PRESETS_FILE: &'static str = "presets.toml";
+
+//everything needed to analyse a recording the same way on another machine
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub filter_data: FilterData,
+    pub drawers: Vec<WaveformDrawerSettings>,
+}
+
+impl Preset {
+    pub fn from_app(app: &AppState) -> Preset {
+        Preset{
+            filter_data: app.filter_data.clone(),
+            drawers: app.waveform_drawers.iter().map(|wfd| wfd.settings.clone()).collect(),
+        }
+    }
+}
+
+//all the named presets, kept together in one human readable toml file
+#[derive(Default, Serialize, Deserialize)]
+pub struct PresetFile {
+    #[serde(default)]
+    pub presets: std::collections::BTreeMap<String, Preset>,
+}
+
+impl PresetFile {
+    //a missing file is just an empty set of presets
+    pub fn load(path: &std::path::Path) -> Result<PresetFile, String> {
+        if !path.exists() {return Ok(PresetFile::default());}
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Unable to read presets from {:?}: {}", path, e))?;
+        toml::from_str(&text).map_err(|e| format!("Unable to parse presets in {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        //going through a toml::Value puts plain values before tables, which toml needs
+        let value = toml::Value::try_from(self).map_err(|e| e.to_string())?;
+        let text = toml::to_string_pretty(&value).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("Unable to write presets to {:?}: {}", path, e))
+    }
+}
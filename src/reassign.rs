@@ -0,0 +1,184 @@
+use std;
+use std::collections::VecDeque;
+use rustfft;
+
+use dftwindower::{WindowShape};
+
+//bins quieter than this fraction of the loudest are left where they are, their estimates are mostly noise
+const MIN_RELATIVE_POWER: f32 = 1e-8;
+
+//a bin's energy moved to where it really belongs
+#[derive(Clone, Copy)]
+pub struct ReassignedPoint {
+    pub bin: f32, //instantaneous frequency, in (fractional) bins of the fft
+    pub time: f32, //group delay, in samples before the newest one in the window (so 0 or negative)
+    pub power: f32,
+}
+
+//Time-frequency reassignment. Besides the plain stft with window h, each column takes two more with t*h and dh/dt.
+//For bin k, with X = stft with h:
+//  instantaneous frequency = k - fft_len/(2 pi) * Im(X_dh/X) bins
+//  group delay = centre + Re(X_th/X) samples
+//The reassigned spectrogram moves every bin's power to both. Synchrosqueezing only moves it in frequency,
+//summing the complex values, so it stays a single column and can be inverted.
+pub struct Reassigner {
+    shape: WindowShape,
+    fft_len: usize,
+    h: Vec<f32>,
+    th: Vec<f32>,
+    dh: Vec<f32>,
+}
+
+impl Reassigner {
+    pub fn new(shape: WindowShape, len: u32, fft_len: u32) -> Reassigner {
+        let h = shape.coefficients(len);
+        let n = h.len();
+        let centre = (n as f32 - 1.0)/2.0;
+        let th = h.iter().enumerate().map(|(i, v)| (i as f32 - centre)*v).collect();
+        //central differences, one sided at the ends where the window is cut off
+        let dh = (0..n).map(|i| {
+            if n < 2 {0.0}
+            else if i == 0 {h[1] - h[0]}
+            else if i == n-1 {h[n-1] - h[n-2]}
+            else {(h[i+1] - h[i-1])/2.0}
+        }).collect();
+        Reassigner{
+            shape: shape,
+            fft_len: std::cmp::max(fft_len as usize, n),
+            h: h,
+            th: th,
+            dh: dh,
+        }
+    }
+
+    pub fn matches(&self, shape: WindowShape, len: u32, fft_len: u32) -> bool {
+        self.shape == shape && self.h.len() == len as usize && self.fft_len == std::cmp::max(fft_len as usize, len as usize)
+    }
+
+    //the stft of signal with each of the three windows, a short signal is zero filled in front like the plain stft
+    fn spectra(&self, signal: &[f32]) -> (Vec<rustfft::num_complex::Complex<f32>>, Vec<rustfft::num_complex::Complex<f32>>, Vec<rustfft::num_complex::Complex<f32>>) {
+        let len = self.h.len();
+        let offset = len - std::cmp::min(len, signal.len());
+        let signal = &signal[signal.len() - (len - offset)..];
+        let mut fft_planner = rustfft::FFTplanner::new(false);
+        let fft = fft_planner.plan_fft(self.fft_len);
+        let transform = |window: &[f32]| {
+            let mut windowed = vec![rustfft::num_complex::Complex{re: 0.0, im: 0.0}; self.fft_len];
+            for (i, s) in signal.iter().enumerate() {windowed[offset+i].re = s*window[offset+i];}
+            let mut spectrum = windowed.clone();
+            fft.process(&mut windowed, &mut spectrum);
+            spectrum
+        };
+        (transform(&self.h), transform(&self.th), transform(&self.dh))
+    }
+
+    //the plain stft, for band powers and peaks, and the reassigned power of every bin below nyquist
+    pub fn reassign(&self, signal: &[f32]) -> (Vec<rustfft::num_complex::Complex<f32>>, Vec<ReassignedPoint>) {
+        let (x, x_th, x_dh) = self.spectra(signal);
+        let len = self.h.len() as f32;
+        let half = self.fft_len/2;
+        let loudest = x[..half].iter().fold(0.0f32, |m, c| m.max(c.norm_sqr()));
+        let mut points = Vec::<ReassignedPoint>::with_capacity(half);
+        for k in 0..half {
+            let power = x[k].norm_sqr();
+            if power <= loudest*MIN_RELATIVE_POWER {continue;}
+            let conj = x[k].conj();
+            let bin = k as f32 - self.fft_len as f32/(2.0*std::f32::consts::PI)*(x_dh[k]*conj).im/power;
+            let delay = (x_th[k]*conj).re/power; //samples from the window's centre
+            points.push(ReassignedPoint{
+                bin: bin,
+                time: (delay - (len - 1.0)/2.0).max(-(len - 1.0)).min(0.0),
+                power: power,
+            });
+        }
+        (x, points)
+    }
+
+    //the synchrosqueezed column: each bin's complex value added to the bin of its instantaneous frequency
+    pub fn squeeze(&self, signal: &[f32]) -> Vec<rustfft::num_complex::Complex<f32>> {
+        let (x, _, x_dh) = self.spectra(signal);
+        let centre = (self.h.len() as f32 - 1.0)/2.0;
+        let half = self.fft_len/2;
+        let loudest = x[..half].iter().fold(0.0f32, |m, c| m.max(c.norm_sqr()));
+        let mut squeezed = vec![rustfft::num_complex::Complex{re: 0.0, im: 0.0}; self.fft_len];
+        for k in 0..half {
+            let power = x[k].norm_sqr();
+            if power <= loudest*MIN_RELATIVE_POWER {continue;}
+            let bin = k as f32 - self.fft_len as f32/(2.0*std::f32::consts::PI)*(x_dh[k]*x[k].conj()).im/power;
+            let target = bin.round();
+            if target >= 0.0 && (target as usize) < half {
+                //phase measured from the window's centre rather than its start, otherwise neighbouring bins cancel out
+                let shift = 2.0*std::f32::consts::PI*k as f32*centre/self.fft_len as f32;
+                let value = x[k]*rustfft::num_complex::Complex{re: shift.cos(), im: shift.sin()};
+                squeezed[target as usize] = squeezed[target as usize] + value;
+            }
+        }
+        squeezed
+    }
+}
+
+
+//a column of the reassigned spectrogram that can still receive power
+pub struct PendingColumn {
+    pub start_ms: f64,
+    pub end_ms: f64,
+    pub pixels: u32,
+    pub power: Vec<f32>, //by fft bin
+}
+
+impl PendingColumn {
+    //as magnitudes, so it goes through the same colouring as an fft
+    pub fn spectrum(&self) -> Vec<rustfft::num_complex::Complex<f32>> {
+        self.power.iter().map(|p| rustfft::num_complex::Complex{re: p.sqrt(), im: 0.0}).collect()
+    }
+}
+
+//Power can be moved up to a window length back in time, so columns are held until no later window can reach them.
+//A scrolling display of the reassigned spectrogram therefore runs one window behind the data.
+pub struct ReassignedColumns {
+    pending: VecDeque<PendingColumn>,
+}
+
+impl ReassignedColumns {
+    pub fn new() -> ReassignedColumns {
+        ReassignedColumns{
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn push(&mut self, start_ms: f64, end_ms: f64, pixels: u32, bins: usize) {
+        self.pending.push_back(PendingColumn{
+            start_ms: start_ms,
+            end_ms: end_ms,
+            pixels: pixels,
+            power: vec![0.0; bins],
+        });
+    }
+
+    //power at time_ms goes to the column covering it, split between the two nearest bins
+    //anything older than the oldest column still pending lands in that one
+    pub fn add(&mut self, time_ms: f64, bin: f32, power: f32) {
+        let column = match self.pending.iter_mut().find(|c| time_ms <= c.end_ms) {
+            Some(column) => column,
+            None => match self.pending.back_mut() {Some(column) => column, None => return},
+        };
+        if bin < 0.0 {return;}
+        let low = bin.floor() as usize;
+        let fraction = bin - low as f32;
+        if low < column.power.len() {column.power[low] += power*(1.0 - fraction);}
+        if low+1 < column.power.len() {column.power[low+1] += power*fraction;}
+    }
+
+    //every column that ended before before_ms, oldest first
+    pub fn ready(&mut self, before_ms: f64) -> Vec<PendingColumn> {
+        let mut ready = Vec::<PendingColumn>::new();
+        while self.pending.front().map(|c| c.end_ms <= before_ms).unwrap_or(false) {
+            ready.push(self.pending.pop_front().unwrap());
+        }
+        ready
+    }
+}
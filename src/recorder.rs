@@ -0,0 +1,530 @@
+use std;
+use std::io::{Write, Seek};
+
+//Recording of live data to disk.
+//The capture thread only copies its samples into a channel, a writer thread does all the file work
+//so a slow disk can never hold up PortAudio and drop samples.
+//Every recording also gets a <name>.events.csv next to it with the start and stop times and any markers,
+//as time in seconds from the start and a label.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RecordFormat {
+    Wav,
+    OpenBCI,
+    Edf,
+}
+
+impl RecordFormat {
+    pub const ALL: [RecordFormat; 3] = [RecordFormat::Wav, RecordFormat::OpenBCI, RecordFormat::Edf];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RecordFormat::Wav => "WAV",
+            RecordFormat::OpenBCI => "OpenBCI text",
+            RecordFormat::Edf => "EDF+",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            RecordFormat::Wav => "wav",
+            RecordFormat::OpenBCI => "txt",
+            RecordFormat::Edf => "edf",
+        }
+    }
+}
+
+//what the writer needs to know about the stream
+#[derive(Clone)]
+pub struct StreamInfo {
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub labels: Vec<String>,
+    pub full_scale: f32, //largest magnitude a sample can take, integer formats are scaled to this
+}
+
+enum RecorderMessage {
+    Samples(Vec<f32>), //interleaved
+    Marker(u64, String), //frame index from the start of the recording
+    Stop(std::time::SystemTime),
+}
+
+trait RecordWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()>;
+    fn marker(&mut self, frame: u64, label: &str) -> std::io::Result<()>;
+    fn finish(&mut self, stopped: std::time::SystemTime) -> std::io::Result<()>;
+}
+
+pub struct Recorder {
+    pub format: RecordFormat,
+    pub path: std::path::PathBuf,
+    pub started: std::time::SystemTime,
+    pub frames: u64, //frames sent to the writer so far
+    sample_rate: u32,
+    channels: u32,
+    sender: Option<std::sync::mpsc::Sender<RecorderMessage>>,
+    thread: Option<std::thread::JoinHandle<Result<String, String>>>,
+}
+
+impl Recorder {
+    //creates the file straight away so a bad path is reported before any data arrives
+    pub fn start(format: RecordFormat, path: &std::path::Path, info: StreamInfo) -> Result<Recorder, String> {
+        let started = std::time::SystemTime::now();
+        let file = std::fs::File::create(path).map_err(|e| format!("Unable to create recording {:?}: {}", path, e))?;
+        let file = std::io::BufWriter::new(file);
+        let mut writer: Box<dyn RecordWriter + Send> = match format {
+            RecordFormat::Wav => Box::new(WavWriter::new(file, &info)),
+            RecordFormat::OpenBCI => Box::new(OpenBCIWriter::new(file, &info, started)),
+            RecordFormat::Edf => Box::new(EdfWriter::new(file, &info, started)),
+        };
+        let (sender, receiver) = std::sync::mpsc::channel::<RecorderMessage>();
+        let thread_path = path.to_path_buf();
+        let sample_rate = info.sample_rate;
+        let thread = std::thread::spawn(move || {
+            let mut events = Vec::<(u64, String)>::new();
+            let mut stopped = std::time::SystemTime::now();
+            let mut result = Ok(());
+            //keep draining after an error so the capture side never notices
+            for message in receiver.iter() {
+                match message {
+                    RecorderMessage::Samples(samples) => {
+                        if result.is_ok() {result = writer.write_samples(&samples);}
+                    },
+                    RecorderMessage::Marker(frame, label) => {
+                        if result.is_ok() {result = writer.marker(frame, &label);}
+                        events.push((frame, label));
+                    },
+                    RecorderMessage::Stop(time) => {stopped = time; break;},
+                }
+            }
+            result = result.and_then(|_| writer.finish(stopped));
+            result.map_err(|e| format!("Error writing recording {:?}: {}", thread_path, e))?;
+            write_events(&thread_path, sample_rate, started, stopped, &events)?;
+            Ok(format!("Saved {} markers and {:.1} s to {}", events.len(),
+                stopped.duration_since(started).map(|d| d.as_secs() as f64 + d.subsec_nanos() as f64*1e-9).unwrap_or(0.0), thread_path.display()))
+        });
+        Ok(Recorder{
+            format: format,
+            path: path.to_path_buf(),
+            started: started,
+            frames: 0,
+            sample_rate: sample_rate,
+            channels: info.channels,
+            sender: Some(sender),
+            thread: Some(thread),
+        })
+    }
+
+    //called from the capture thread, this only copies
+    pub fn push(&mut self, samples: &[f32]) {
+        if let Some(ref sender) = self.sender {
+            let _ = sender.send(RecorderMessage::Samples(samples.to_vec()));
+        }
+        self.frames += samples.len() as u64 / self.channels as u64;
+    }
+
+    //marks the latest frame that has been captured
+    pub fn mark(&mut self, label: &str) {
+        if let Some(ref sender) = self.sender {
+            let _ = sender.send(RecorderMessage::Marker(self.frames, label.to_string()));
+        }
+    }
+
+    pub fn seconds(&self) -> f32 {
+        self.frames as f32 / self.sample_rate as f32
+    }
+
+    //waits for the writer to catch up and close the file
+    pub fn stop(&mut self) -> Result<String, String> {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(RecorderMessage::Stop(std::time::SystemTime::now()));
+        }
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or(Err(String::from("The recording thread panicked."))),
+            None => Err(String::from("Not recording.")),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            match self.stop() {
+                Ok(summary) => println!("{}", summary),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+}
+
+
+fn unix_ms(time: std::time::SystemTime) -> u64 {
+    let d = time.duration_since(std::time::UNIX_EPOCH).unwrap_or(std::time::Duration::from_secs(0));
+    d.as_secs()*1000 + d.subsec_nanos() as u64/1_000_000
+}
+
+//(year, month, day, hour, minute, second) in UTC, from Howard Hinnant's days_from_civil inverse
+fn civil_time(time: std::time::SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = (unix_ms(time)/1000) as i64; //never before 1970 so plain division is fine
+    let days = secs/86400;
+    let rem = (secs % 86400) as u32;
+    let z = days + 719468;
+    let era = z/146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2)/153;
+    let day = (doy - (153*mp + 2)/5 + 1) as u32;
+    let month = (if mp < 10 {mp + 3} else {mp - 9}) as u32;
+    let year = yoe + era*400 + if month <= 2 {1} else {0};
+    (year, month, day, rem/3600, rem/60 % 60, rem % 60)
+}
+
+fn write_events(path: &std::path::Path, sample_rate: u32, started: std::time::SystemTime, stopped: std::time::SystemTime, events: &[(u64, String)]) -> Result<(), String> {
+    let events_path = path.with_extension("events.csv");
+    let mut text = format!("%Recording started = {} ms\n%Recording stopped = {} ms\n%time (s), label\n", unix_ms(started), unix_ms(stopped));
+    for &(frame, ref label) in events {
+        text.push_str(&format!("{:.4}, {}\n", frame as f64/sample_rate as f64, label));
+    }
+    std::fs::write(&events_path, text).map_err(|e| format!("Unable to write events to {:?}: {}", events_path, e))
+}
+
+fn write_u16<W: Write>(w: &mut W, v: u16) -> std::io::Result<()> {
+    w.write_all(&[v as u8, (v >> 8) as u8])
+}
+fn write_u32<W: Write>(w: &mut W, v: u32) -> std::io::Result<()> {
+    w.write_all(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+}
+
+fn to_i16(sample: f32, full_scale: f32) -> i16 {
+    ((sample/full_scale).max(-1.0).min(1.0)*32767.0).round() as i16
+}
+
+
+//16 bit PCM, the sizes in the header are filled in when the recording stops
+struct WavWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    channels: u32,
+    sample_rate: u32,
+    full_scale: f32,
+    data_bytes: u32,
+    header_written: bool,
+}
+
+impl WavWriter {
+    fn new(file: std::io::BufWriter<std::fs::File>, info: &StreamInfo) -> WavWriter {
+        WavWriter{file: file, channels: info.channels, sample_rate: info.sample_rate, full_scale: info.full_scale, data_bytes: 0, header_written: false}
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let block_align = self.channels*2;
+        self.file.write_all(b"RIFF")?;
+        write_u32(&mut self.file, 36 + self.data_bytes)?;
+        self.file.write_all(b"WAVEfmt ")?;
+        write_u32(&mut self.file, 16)?;
+        write_u16(&mut self.file, 1)?; //PCM
+        write_u16(&mut self.file, self.channels as u16)?;
+        write_u32(&mut self.file, self.sample_rate)?;
+        write_u32(&mut self.file, self.sample_rate*block_align)?;
+        write_u16(&mut self.file, block_align as u16)?;
+        write_u16(&mut self.file, 16)?;
+        self.file.write_all(b"data")?;
+        write_u32(&mut self.file, self.data_bytes)
+    }
+}
+
+impl RecordWriter for WavWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+            self.header_written = true;
+        }
+        for s in samples {
+            write_u16(&mut self.file, to_i16(*s, self.full_scale) as u16)?;
+        }
+        self.data_bytes += samples.len() as u32*2;
+        Ok(())
+    }
+    //wav has nowhere to put markers, they only go in the events file
+    fn marker(&mut self, _frame: u64, _label: &str) -> std::io::Result<()> {Ok(())}
+    fn finish(&mut self, _stopped: std::time::SystemTime) -> std::io::Result<()> {
+        if !self.header_written {return self.write_header();}
+        self.file.seek(std::io::SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+
+//the same layout OpenBCIFile reads: index, channels, three accelerometer columns and a timestamp
+struct OpenBCIWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    channels: u32,
+    sample_rate: u32,
+    started_ms: u64,
+    frame: u64,
+}
+
+impl OpenBCIWriter {
+    fn new(file: std::io::BufWriter<std::fs::File>, info: &StreamInfo, started: std::time::SystemTime) -> OpenBCIWriter {
+        let mut writer = OpenBCIWriter{file: file, channels: info.channels, sample_rate: info.sample_rate, started_ms: unix_ms(started), frame: 0};
+        //header errors turn up again on the first write
+        let _ = write!(writer.file, "%OpenBCI Raw EEG Data\n%Number of channels = {}\n%Sample Rate = {} Hz\n%Channels = {}\n%Recording started = {} ms\n",
+            info.channels, info.sample_rate, info.labels.join(", "), writer.started_ms);
+        writer
+    }
+}
+
+impl RecordWriter for OpenBCIWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for frame in samples.chunks(self.channels as usize) {
+            write!(self.file, "{}", self.frame % 256)?;
+            for s in frame {write!(self.file, ", {}", s)?;}
+            writeln!(self.file, ", 0.0, 0.0, 0.0, {}", self.started_ms + self.frame*1000/self.sample_rate as u64)?;
+            self.frame += 1;
+        }
+        Ok(())
+    }
+    //comment lines are skipped by readers so markers can go in line with the data
+    fn marker(&mut self, frame: u64, label: &str) -> std::io::Result<()> {
+        writeln!(self.file, "%Marker = {:.4} s, {}", frame as f64/self.sample_rate as f64, label)
+    }
+    fn finish(&mut self, stopped: std::time::SystemTime) -> std::io::Result<()> {
+        writeln!(self.file, "%Recording stopped = {} ms", unix_ms(stopped))?;
+        self.file.flush()
+    }
+}
+
+
+//EDF+ with one second data records and an annotation signal for the markers
+//the number of records is left as -1 while recording and filled in when it stops
+const EDF_ANNOTATION_BYTES: usize = 128;
+const EDF_RECORD_COUNT_OFFSET: u64 = 236;
+
+struct EdfWriter {
+    file: std::io::BufWriter<std::fs::File>,
+    channels: usize,
+    sample_rate: usize,
+    full_scale: f32,
+    record: Vec<Vec<i16>>, //the data record being filled, one row per channel
+    records: u64,
+    markers: std::collections::VecDeque<(u64, String)>,
+}
+
+//the longest start of s that fits in max bytes without splitting a character
+fn cut_to_bytes(s: &str, max: usize) -> &str {
+    let mut end = std::cmp::min(s.len(), max);
+    while !s.is_char_boundary(end) {end -= 1;}
+    &s[..end]
+}
+
+fn edf_field(s: &str, len: usize) -> String {
+    let mut s: String = s.chars().filter(|c| c.is_ascii() && !c.is_ascii_control()).take(len).collect();
+    while s.len() < len {s.push(' ');}
+    s
+}
+
+impl EdfWriter {
+    fn new(file: std::io::BufWriter<std::fs::File>, info: &StreamInfo, started: std::time::SystemTime) -> EdfWriter {
+        let mut writer = EdfWriter{
+            file: file,
+            channels: info.channels as usize,
+            sample_rate: info.sample_rate as usize,
+            full_scale: info.full_scale,
+            record: vec![Vec::<i16>::new(); info.channels as usize],
+            records: 0,
+            markers: std::collections::VecDeque::new(),
+        };
+        let _ = writer.write_header(info, started);
+        writer
+    }
+
+    fn write_header(&mut self, info: &StreamInfo, started: std::time::SystemTime) -> std::io::Result<()> {
+        const MONTHS: [&'static str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+        let (year, month, day, hour, minute, second) = civil_time(started);
+        let signals = self.channels + 1;
+        let full_scale = format!("{}", self.full_scale);
+        let channels = self.channels;
+        let mut h = String::new();
+        h += &edf_field("0", 8);
+        h += &edf_field("X X X X", 80);
+        h += &edf_field(&format!("Startdate {:02}-{}-{} X X X", day, MONTHS[month as usize - 1], year), 80);
+        h += &edf_field(&format!("{:02}.{:02}.{:02}", day, month, year % 100), 8);
+        h += &edf_field(&format!("{:02}.{:02}.{:02}", hour, minute, second), 8);
+        h += &edf_field(&format!("{}", 256*(signals + 1)), 8);
+        h += &edf_field("EDF+C", 44);
+        h += &edf_field("-1", 8);
+        h += &edf_field("1", 8);
+        h += &edf_field(&format!("{}", signals), 4);
+        let label = |c: usize| if c < channels {info.labels.get(c).cloned().unwrap_or(format!("Ch {}", c+1))} else {String::from("EDF Annotations")};
+        let data_or = |c: usize, data: &str, annotation: &str| if c < channels {data.to_string()} else {annotation.to_string()};
+        for c in 0..signals {h += &edf_field(&label(c), 16);}
+        for _ in 0..signals {h += &edf_field("", 80);}
+        for _ in 0..signals {h += &edf_field("", 8);}
+        for c in 0..signals {h += &edf_field(&data_or(c, &format!("-{}", full_scale), "-1"), 8);}
+        for c in 0..signals {h += &edf_field(&data_or(c, &full_scale, "1"), 8);}
+        for _ in 0..signals {h += &edf_field("-32768", 8);}
+        for _ in 0..signals {h += &edf_field("32767", 8);}
+        for _ in 0..signals {h += &edf_field("", 80);}
+        for c in 0..signals {h += &edf_field(&data_or(c, &format!("{}", self.sample_rate), &format!("{}", EDF_ANNOTATION_BYTES/2)), 8);}
+        for _ in 0..signals {h += &edf_field("", 32);}
+        self.file.write_all(h.as_bytes())
+    }
+
+    //each record's annotations start with its onset, then as many markers from inside it as fit
+    //a marker that doesn't fit waits for the next record, unless it is the first one there in which case its label is cut short
+    //so every record takes at least one marker and finish() always gets through them
+    fn annotations(&mut self) -> Vec<u8> {
+        let mut tal = format!("+{}\x14\x14\0", self.records).into_bytes();
+        let timekeeping = tal.len();
+        let record_end = (self.records + 1)*self.sample_rate as u64;
+        while let Some((frame, label)) = self.markers.pop_front() {
+            let onset = format!("+{:.4}\x14", frame as f64/self.sample_rate as f64);
+            let room = EDF_ANNOTATION_BYTES.saturating_sub(tal.len() + onset.len() + 2);
+            if frame >= record_end || (label.len() > room && tal.len() > timekeeping) {
+                self.markers.push_front((frame, label));
+                break;
+            }
+            tal.extend(onset.into_bytes());
+            tal.extend(cut_to_bytes(&label, room).as_bytes());
+            tal.extend(b"\x14\0");
+        }
+        tal.resize(EDF_ANNOTATION_BYTES, 0);
+        tal
+    }
+
+    fn write_record(&mut self) -> std::io::Result<()> {
+        for c in 0..self.channels {
+            self.record[c].resize(self.sample_rate, 0);
+            for i in 0..self.sample_rate {
+                write_u16(&mut self.file, self.record[c][i] as u16)?;
+            }
+            self.record[c].clear();
+        }
+        let annotations = self.annotations();
+        self.file.write_all(&annotations)?;
+        self.records += 1;
+        Ok(())
+    }
+}
+
+impl RecordWriter for EdfWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for frame in samples.chunks(self.channels) {
+            for (c, s) in frame.iter().enumerate() {
+                self.record[c].push(to_i16(*s, self.full_scale));
+            }
+            if self.record[0].len() == self.sample_rate {self.write_record()?;}
+        }
+        Ok(())
+    }
+    //the separators can't go in a label, its length is sorted out when its record is written
+    fn marker(&mut self, frame: u64, label: &str) -> std::io::Result<()> {
+        self.markers.push_back((frame, label.replace('\x14', " ").replace('\0', " ")));
+        Ok(())
+    }
+    //the last partial record is padded with zeros, markers that didn't fit get records of their own
+    fn finish(&mut self, _stopped: std::time::SystemTime) -> std::io::Result<()> {
+        if self.record[0].len() > 0 {self.write_record()?;}
+        while !self.markers.is_empty() {self.write_record()?;}
+        self.file.seek(std::io::SeekFrom::Start(EDF_RECORD_COUNT_OFFSET))?;
+        self.file.write_all(edf_field(&format!("{}", self.records), 8).as_bytes())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use annotations;
+
+    fn temp_file(name: &str) -> (std::path::PathBuf, std::io::BufWriter<std::fs::File>) {
+        let path = std::env::temp_dir().join(format!("recorder_{}_{}", std::process::id(), name));
+        let file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        (path, file)
+    }
+
+    fn info() -> StreamInfo {
+        StreamInfo{channels: 2, sample_rate: 4, labels: vec![String::from("Fp1"), String::from("Fp2")], full_scale: 100.0}
+    }
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {data[at] as u16 | (data[at+1] as u16) << 8}
+    fn u32_at(data: &[u8], at: usize) -> u32 {u16_at(data, at) as u32 | (u16_at(data, at+2) as u32) << 16}
+    fn text_at(data: &[u8], at: usize, len: usize) -> String {String::from_utf8_lossy(&data[at..at+len]).trim().to_string()}
+
+    #[test]
+    fn wav_header_round_trips() {
+        let (path, file) = temp_file("header.wav");
+        let mut writer = WavWriter::new(file, &info());
+        writer.write_samples(&[50.0, -50.0, 100.0, -200.0, 0.0, 25.0]).unwrap();
+        writer.finish(std::time::SystemTime::now()).unwrap();
+        drop(writer);
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(data.len(), 44 + 12);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(&data, 4), 36 + 12);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u16_at(&data, 20), 1);
+        assert_eq!(u16_at(&data, 22), 2);
+        assert_eq!(u32_at(&data, 24), 4);
+        assert_eq!(u32_at(&data, 28), 4*2*2);
+        assert_eq!(u16_at(&data, 32), 4);
+        assert_eq!(u16_at(&data, 34), 16);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32_at(&data, 40), 12);
+        let samples: Vec<i16> = (0..6).map(|i| u16_at(&data, 44 + 2*i) as i16).collect();
+        assert_eq!(samples, vec![16384, -16384, 32767, -32767, 0, 8192]);
+    }
+
+    #[test]
+    fn edf_header_round_trips() {
+        let (path, file) = temp_file("header.edf");
+        let mut writer = EdfWriter::new(file, &info(), std::time::UNIX_EPOCH);
+        writer.write_samples(&[1.0; 2*6]).unwrap();
+        writer.finish(std::time::SystemTime::now()).unwrap();
+        drop(writer);
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let signals = 3;
+        let record_bytes = 2*(4 + 4 + EDF_ANNOTATION_BYTES/2);
+        assert_eq!(data.len(), 256*(signals + 1) + 2*record_bytes);
+        assert_eq!(text_at(&data, 0, 8), "0");
+        assert_eq!(text_at(&data, 88, 80), "Startdate 01-JAN-1970 X X X");
+        assert_eq!(text_at(&data, 168, 8), "01.01.70");
+        assert_eq!(text_at(&data, 184, 8), format!("{}", 256*(signals + 1)));
+        assert_eq!(text_at(&data, 192, 44), "EDF+C");
+        assert_eq!(text_at(&data, 236, 8), "2");
+        assert_eq!(text_at(&data, 244, 8), "1");
+        assert_eq!(text_at(&data, 252, 4), "3");
+        let labels: Vec<String> = (0..signals).map(|s| text_at(&data, 256 + 16*s, 16)).collect();
+        assert_eq!(labels, vec!["Fp1", "Fp2", "EDF Annotations"]);
+        let samples: Vec<String> = (0..signals).map(|s| text_at(&data, 256 + 216*signals + 8*s, 8)).collect();
+        assert_eq!(samples, vec!["4", "4", "64"]);
+    }
+
+    #[test]
+    fn long_edf_labels_are_cut_on_a_character_boundary() {
+        let (path, file) = temp_file("labels.edf");
+        let long: String = std::iter::repeat("αβγ").take(100).collect();
+        let mut writer = EdfWriter::new(file, &info(), std::time::UNIX_EPOCH);
+        writer.write_samples(&[0.0; 2*4]).unwrap();
+        writer.marker(2, &long).unwrap();
+        writer.marker(2, "short").unwrap();
+        writer.marker(3, &long).unwrap();
+        writer.finish(std::time::SystemTime::now()).unwrap();
+        drop(writer);
+        let loaded = annotations::load_edf(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.len(), 3);
+        let labels: Vec<&str> = loaded.iter().map(|a| a.label.as_str()).collect();
+        assert!(labels.contains(&"short"));
+        for a in loaded.iter().filter(|a| a.label != "short") {
+            assert!(a.label.len() > 64 && long.starts_with(&a.label), "{:?}", a.label);
+        }
+        assert!(loaded.iter().all(|a| a.time_s == 0.5 || a.time_s == 0.75));
+    }
+}
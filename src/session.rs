@@ -0,0 +1,121 @@
+use std;
+use glium;
+use serde_json;
+
+use appstate::{AppState, DataSource, FilterData, GuiDisplay};
+use waveformdrawer::{WaveformDrawer, WaveformDrawerSettings};
+use derived::{DerivedChannel};
+use layout::{Layout};
+use annotations::{Annotation};
+
+pub const SESSION_FILE: &'static str = "session.json";
+
+//where the data came from, a device is reopened rather than replayed
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SessionSource {
+    NoSource,
+    File(std::path::PathBuf),
+    PortAudio,
+}
+
+//the plain state of the whole workspace, AppState itself holds GL textures and the data so it can't be saved directly
+//this is json rather than toml because toml can't hold enums with data like DerivedChannel
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub source: SessionSource,
+    pub position_ms: u64, //how far into a file playback had got
+    pub filter_data: FilterData,
+    pub derived: Vec<DerivedChannel>,
+    pub layout: Layout,
+    pub drawers: Vec<WaveformDrawerSettings>,
+    pub gui_display: GuiDisplay,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+impl Session {
+    pub fn from_app(app: &AppState) -> Session {
+        let app_data = app.app_data.lock().unwrap();
+        let source = match app_data.data_source {
+            DataSource::WavBuffer => match app_data.source_path {
+                Some(ref path) => SessionSource::File(path.clone()),
+                None => SessionSource::NoSource,
+            },
+            DataSource::PortAudio => SessionSource::PortAudio,
+            DataSource::NoSource => SessionSource::NoSource,
+        };
+        let position_ms = match source {
+            SessionSource::File(_) => app.waveform_drawers.first().map(|wfd| wfd.position_ms(app.ticker.ticks())).unwrap_or(0),
+            _ => 0,
+        };
+        Session{
+            source: source,
+            position_ms: position_ms,
+            filter_data: app.filter_data.clone(),
+            derived: app_data.wave_data.as_ref().map(|wd| wd.derived.clone()).unwrap_or(Vec::<DerivedChannel>::new()),
+            layout: app.layout.clone(),
+            drawers: app.waveform_drawers.iter().map(|wfd| wfd.settings.clone()).collect(),
+            gui_display: app.gui_data.gui_display,
+            annotations: app.annotations.clone(),
+        }
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Session, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Unable to read session from {:?}: {}", path, e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Unable to parse session in {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, text).map_err(|e| format!("Unable to write session to {:?}: {}", path, e))
+    }
+
+    //reopens the source and puts every drawer back where it was, a file is played on from the saved position
+    pub fn restore<'a>(&self, app: &mut AppState<'a>, display: &'a glium::Display) -> Result<(), String> {
+        app.filter_data = self.filter_data.clone();
+        app.preset = None;
+        match self.source {
+            SessionSource::File(ref path) => {
+                if !path.is_file() {return Err(format!("The session's data file {:?} no longer exists.", path));}
+                app.open_file(display, path);
+            },
+            SessionSource::PortAudio => app.open_portaudio(display),
+            SessionSource::NoSource => {},
+        }
+        app.annotations = self.annotations.clone();
+
+        let channels = {
+            let mut app_data = app.app_data.lock().unwrap();
+            if let Some(ref mut wave_data) = app_data.wave_data {
+                wave_data.set_derived(self.derived.clone());
+            }
+            app_data.channel_names().len() as u32
+        };
+        if channels == 0 {return Ok(());}
+
+        //the drawers are rebuilt from the session rather than matched up like a preset, so their number comes back too
+        app.waveform_drawers = self.drawers.iter().map(|settings| {
+            let mut settings = settings.clone();
+            if settings.channel >= channels {settings.channel = 0;}
+            WaveformDrawer::new(display, settings)
+        }).collect();
+        app.layout = self.layout.clone();
+        //a hand edited or mismatched layout is dropped rather than half applied
+        if !app.layout.fits(app.waveform_drawers.len()) {app.layout.reset(app.waveform_drawers.len());}
+        app.layout.apply(&mut app.waveform_drawers, app.gui_data.viewport.aspect());
+
+        let ticks = app.ticker.ticks();
+        for wfd in &mut app.waveform_drawers {
+            match self.source {
+                SessionSource::File(_) => wfd.seek(ticks, self.position_ms, &app.app_data, &app.filter_data),
+                _ => wfd.start(ticks),
+            }
+        }
+
+        app.gui_data.gui_display = match self.gui_display {
+            GuiDisplay::FileOpen | GuiDisplay::Nothing => GuiDisplay::FilterOptions,
+            other => other,
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use std;
+use serde_json;
+
+use linalg::{Matrix, multiply, transpose, eigen};
+
+//a new window is scored each time this much more data has arrived
+const STEP_S: f64 = 0.25;
+//directions with less variance than this share of the largest are dropped when whitening, eg the one a common average reference removes
+const RANK_TOLERANCE: f64 = 1e-9;
+
+//what the SSVEP panel is set to
+pub struct SsvepSettings {
+    pub frequencies: Vec<f32>, //Hz, the stimuli being looked for
+    pub harmonics: usize, //references at each frequency and this many multiples of it, counting the fundamental
+    pub min_window_s: f32, //a trial is first scored once it is this long
+    pub max_window_s: f32, //and given up on, with no decision, at this long
+    pub confidence: f32, //the winner is reported once the confidence reaches this
+    pub address: String, //decisions are sent here over UDP
+}
+
+impl Default for SsvepSettings {
+    fn default() -> SsvepSettings {
+        SsvepSettings{
+            frequencies: vec![8.57, 10.0, 12.0, 15.0],
+            harmonics: 3,
+            min_window_s: 1.0,
+            max_window_s: 4.0,
+            confidence: 0.3,
+            address: String::from("127.0.0.1:5005"),
+        }
+    }
+}
+
+//a comma or space separated list of frequencies in Hz
+pub fn parse_frequencies(text: &str) -> Result<Vec<f32>, String> {
+    let frequencies: Vec<f32> = text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.parse::<f32>().map_err(|_| format!("{:?} isn't a frequency.", t)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    if frequencies.is_empty() {return Err(String::from("List the stimulus frequencies."));}
+    if frequencies.iter().any(|f| *f <= 0.0) {return Err(String::from("Frequencies have to be above 0 Hz."));}
+    Ok(frequencies)
+}
+
+//sine and cosine at each harmonic of frequency_hz that is below nyquist
+fn references(frequency_hz: f32, harmonics: usize, n: usize, sample_rate: u32) -> Vec<Vec<f64>> {
+    let mut rows = Vec::<Vec<f64>>::new();
+    for h in 1..harmonics+1 {
+        let f = frequency_hz as f64*h as f64;
+        if f >= sample_rate as f64/2.0 {break;}
+        let w = 2.0*std::f64::consts::PI*f/sample_rate as f64;
+        rows.push((0..n).map(|t| (w*t as f64).sin()).collect());
+        rows.push((0..n).map(|t| (w*t as f64).cos()).collect());
+    }
+    rows
+}
+
+//the rows less their means
+fn centred(rows: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    rows.into_iter().map(|r| {
+        let mean = r.iter().sum::<f64>()/r.len().max(1) as f64;
+        r.iter().map(|x| x - mean).collect()
+    }).collect()
+}
+
+//a's rows against b's rows, summed over samples
+fn cross(a: &[Vec<f64>], b: &[Vec<f64>]) -> Matrix {
+    a.iter().map(|x| b.iter().map(|y| x.iter().zip(y.iter()).map(|(p, q)| p*q).sum()).collect()).collect()
+}
+
+//C^-1/2 restricted to the directions C has variance in, one row per kept direction
+fn whitener(c: Matrix) -> Matrix {
+    let (values, vectors) = eigen(c);
+    let largest = values.first().cloned().unwrap_or(0.0);
+    let n = values.len();
+    (0..n).filter(|k| values[*k] > largest*RANK_TOLERANCE && values[*k] > 0.0)
+        .map(|k| (0..n).map(|i| vectors[i][k]/values[k].sqrt()).collect())
+        .collect()
+}
+
+//The largest canonical correlation between the channels and the references: after whitening both sets
+//it is the largest singular value of their cross covariance.
+fn canonical_correlation(x: &[Vec<f64>], y: &[Vec<f64>]) -> f32 {
+    if x.is_empty() || y.is_empty() {return 0.0;}
+    let wx = whitener(cross(x, x));
+    let wy = whitener(cross(y, y));
+    if wx.is_empty() || wy.is_empty() {return 0.0;}
+    let t = multiply(&multiply(&wx, &cross(x, y)), &transpose(&wy));
+    let (values, _) = eigen(multiply(&t, &transpose(&t)));
+    values.first().cloned().unwrap_or(0.0).max(0.0).min(1.0).sqrt() as f32
+}
+
+//the canonical correlation at each frequency, for the same stretch of every channel
+pub fn scores(channels: &[&[f32]], sample_rate: u32, frequencies: &[f32], harmonics: usize) -> Vec<f32> {
+    let n = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    let x = centred(channels.iter().map(|c| c[c.len()-n..].iter().map(|v| *v as f64).collect()).collect());
+    frequencies.iter().map(|f| canonical_correlation(&x, &centred(references(*f, harmonics, n, sample_rate)))).collect()
+}
+
+//what is sent out when a trial ends, as one line of JSON
+#[derive(Clone, Serialize)]
+pub struct Decision {
+    pub time_s: f64, //data time the trial ended at
+    pub decided: bool, //false if the trial ran to the longest window without reaching the confidence
+    pub frequency_hz: f32, //the best scoring frequency, whether or not it was decided
+    pub confidence: f32,
+    pub latency_s: f64, //from the start of the trial to the decision
+    pub correlations: Vec<f32>, //one per frequency
+}
+
+//Trials start when the detector does, at each marker, and straight after the last trial ends. As a trial grows
+//its window is scored every STEP_S, and it ends as soon as the winner is confident enough or the window is as long as allowed.
+//Confidence is how far the winner is ahead of the runner up, as a share of the winner's correlation.
+pub struct Ssvep {
+    pub onset_s: f64, //start of the current trial
+    last_end: usize, //the sample the last window ended at
+    pub frequencies: Vec<f32>, //what the correlations are for
+    pub correlations: Vec<f32>, //the latest window's
+    pub window_s: f64,
+    pub last: Option<Decision>,
+    pub decided: usize,
+    pub undecided: usize,
+    socket: std::net::UdpSocket,
+    target: std::net::SocketAddr,
+}
+
+impl Ssvep {
+    //resolves the address straight away so a bad one is reported before the detector starts
+    pub fn start(settings: &SsvepSettings, now_s: f64) -> Result<Ssvep, String> {
+        use std::net::ToSocketAddrs;
+        let target = settings.address.trim().to_socket_addrs().ok().and_then(|mut a| a.next())
+            .ok_or(format!("{:?} isn't an address to send to, it should look like 127.0.0.1:5005.", settings.address))?;
+        let local = if target.is_ipv4() {"127.0.0.1:0"} else {"[::1]:0"};
+        let socket = std::net::UdpSocket::bind(local).map_err(|e| format!("Unable to open a socket: {}", e))?;
+        Ok(Ssvep{
+            onset_s: now_s,
+            last_end: 0,
+            frequencies: settings.frequencies.clone(),
+            correlations: Vec::new(),
+            window_s: 0.0,
+            last: None,
+            decided: 0,
+            undecided: 0,
+            socket: socket,
+            target: target,
+        })
+    }
+
+    //channels hold everything up to end, marker_s is the latest marker at or before end
+    pub fn update(&mut self, channels: &[&[f32]], end: usize, sample_rate: u32, settings: &SsvepSettings, marker_s: Option<f64>) -> Result<(), String> {
+        let rate = sample_rate as f64;
+        let end_s = end as f64/rate;
+        //playback was moved back, or a marker has started a new trial
+        if end < self.last_end || self.onset_s > end_s {
+            self.onset_s = end_s;
+            self.last_end = end;
+        }
+        if let Some(marker_s) = marker_s {
+            if marker_s > self.onset_s {self.onset_s = marker_s;}
+        }
+        if ((end - self.last_end) as f64) < STEP_S*rate {return Ok(());}
+        self.last_end = end;
+
+        let window_s = end_s - self.onset_s;
+        if window_s < settings.min_window_s as f64 || channels.is_empty() || settings.frequencies.is_empty() {return Ok(());}
+        let start = end - (window_s.min(settings.max_window_s as f64)*rate) as usize;
+        let windows: Vec<&[f32]> = channels.iter().map(|c| &c[start..end]).collect();
+        self.frequencies = settings.frequencies.clone();
+        self.correlations = scores(&windows, sample_rate, &settings.frequencies, settings.harmonics);
+        self.window_s = window_s;
+
+        let mut order: Vec<usize> = (0..self.correlations.len()).collect();
+        order.sort_by(|a, b| self.correlations[*b].partial_cmp(&self.correlations[*a]).unwrap_or(std::cmp::Ordering::Equal));
+        let best = self.correlations[order[0]];
+        let runner_up = if order.len() > 1 {self.correlations[order[1]]} else {0.0};
+        let confidence = if best > 0.0 {(best - runner_up)/best} else {0.0};
+        let decided = confidence >= settings.confidence;
+        if !decided && window_s < settings.max_window_s as f64 {return Ok(());}
+
+        let decision = Decision{
+            time_s: end_s,
+            decided: decided,
+            frequency_hz: settings.frequencies[order[0]],
+            confidence: confidence,
+            latency_s: window_s,
+            correlations: self.correlations.clone(),
+        };
+        if decided {self.decided += 1;} else {self.undecided += 1;}
+        self.onset_s = end_s;
+        let line = serde_json::to_string(&decision).map_err(|e| e.to_string())?;
+        self.last = Some(decision);
+        self.socket.send_to(line.as_bytes(), self.target).map_err(|e| format!("Unable to send to {}: {}", self.target, e))?;
+        Ok(())
+    }
+
+    pub fn target(&self) -> std::net::SocketAddr {
+        self.target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_sinusoid_scores_one_at_its_frequency_only() {
+        //two channels carrying a 12 Hz response at different phases and sizes
+        let sample_rate = 250;
+        let wave = |i: usize, phase: f32| (2.0*std::f32::consts::PI*12.0*i as f32/sample_rate as f32 + phase).sin();
+        let a: Vec<f32> = (0..2*sample_rate as usize).map(|i| 3.0*wave(i, 0.3) + 1.0).collect();
+        let b: Vec<f32> = (0..2*sample_rate as usize).map(|i| 0.5*wave(i, 1.9)).collect();
+        let frequencies = [8.57, 10.0, 12.0, 15.0];
+        let scores = scores(&[&a, &b], sample_rate, &frequencies, 3);
+        assert!((scores[2] - 1.0).abs() < 1e-3, "{:?}", scores);
+        for (k, s) in scores.iter().enumerate().filter(|&(k, _)| k != 2) {
+            assert!(*s < 0.2, "{} Hz: {:?}", frequencies[k], scores);
+        }
+    }
+
+    #[test]
+    fn frequencies_parse_from_a_list() {
+        assert_eq!(parse_frequencies("8.57, 10 12,15").unwrap(), vec![8.57, 10.0, 12.0, 15.0]);
+        assert!(parse_frequencies("").is_err());
+        assert!(parse_frequencies("10, x").is_err());
+        assert!(parse_frequencies("-3").is_err());
+    }
+}
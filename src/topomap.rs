@@ -0,0 +1,244 @@
+use std;
+
+use city2d::City2D;
+use panelimage;
+use linalg::{invert};
+
+//the map is this many pixels across, the head fills it
+pub const MAP_SIZE: u32 = 128;
+//a little room around the head so its outline shows
+pub const EXTENT: f32 = 1.05;
+//order of the spherical spline and how many Legendre terms its kernel is summed to
+const SPLINE_ORDER: i32 = 4;
+const SPLINE_TERMS: usize = 10;
+//inverse distance weights fall off as distance to this power
+const IDW_POWER: f32 = 2.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    SphericalSpline,
+    InverseDistance,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 2] = [Interpolation::SphericalSpline, Interpolation::InverseDistance];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Interpolation::SphericalSpline => "Spherical spline",
+            Interpolation::InverseDistance => "Inverse distance",
+        }
+    }
+}
+
+//what is mapped for each channel, indices are into the filter options' bands and ratios
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scalar {
+    Power(usize), //in dB
+    Relative(usize), //the band's share of the total over all bands
+    Ratio(usize),
+    PeakFrequency, //of the loudest tracked peak, needs peak tracking on
+}
+
+//what the scalp map panel is set to
+pub struct TopoSettings {
+    pub interpolation: Interpolation,
+    pub scalar: Scalar,
+}
+
+impl Default for TopoSettings {
+    fn default() -> TopoSettings {
+        TopoSettings{
+            interpolation: Interpolation::SphericalSpline,
+            scalar: Scalar::Power(0),
+        }
+    }
+}
+
+//the flattened 10-20 layout has 0.2 per 10% of arc, so radius 0.8 is the equator
+fn to_sphere(p: (f32, f32)) -> (f64, f64, f64) {
+    let r = ((p.0*p.0 + p.1*p.1) as f64).sqrt();
+    let theta = r/0.8*std::f64::consts::PI/2.0;
+    let phi = (p.1 as f64).atan2(p.0 as f64);
+    (theta.sin()*phi.cos(), theta.sin()*phi.sin(), theta.cos())
+}
+
+//Perrin's spherical spline kernel g(cos angle) = 1/4pi sum (2n+1)/(n(n+1))^m P_n(cos angle)
+fn spline_kernel(x: f64) -> f64 {
+    let (mut p0, mut p1) = (1.0, x);
+    let mut sum = 0.0;
+    for n in 1..SPLINE_TERMS+1 {
+        let n = n as f64;
+        sum += (2.0*n + 1.0)/(n*(n + 1.0)).powi(SPLINE_ORDER)*p1;
+        let p2 = ((2.0*n + 1.0)*x*p1 - n*p0)/(n + 1.0);
+        p0 = p1;
+        p1 = p2;
+    }
+    sum/(4.0*std::f64::consts::PI)
+}
+
+//Both interpolations are linear in the electrode values, so each pixel's weights are worked out once
+//for a set of electrodes and drawing a new frame is just a weighted sum per pixel.
+pub struct TopoMap {
+    interpolation: Interpolation,
+    positions: Vec<(f32, f32)>,
+    spheres: Vec<(f64, f64, f64)>,
+    spline: Option<Vec<Vec<f64>>>, //the inverted spline system, None for inverse distance
+    weights: Vec<Option<Vec<f32>>>, //per pixel row by row, None outside the head
+}
+
+fn dot(a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+    (a.0*b.0 + a.1*b.1 + a.2*b.2).max(-1.0).min(1.0)
+}
+
+impl TopoMap {
+    pub fn new(positions: &[(f32, f32)], interpolation: Interpolation) -> TopoMap {
+        let n = positions.len();
+        let spheres: Vec<(f64, f64, f64)> = positions.iter().map(|p| to_sphere(*p)).collect();
+        //[G 1; 1' 0] [c; c0] = [v; 0], so the pixel's weights are the inverse applied to its [g; 1]
+        let spline = match interpolation {
+            Interpolation::SphericalSpline => {
+                let mut a = vec![vec![0.0f64; n+1]; n+1];
+                for i in 0..n {
+                    for j in 0..n {a[i][j] = spline_kernel(dot(spheres[i], spheres[j]));}
+                    a[i][n] = 1.0;
+                    a[n][i] = 1.0;
+                }
+                invert(a)
+            },
+            Interpolation::InverseDistance => None,
+        };
+
+        let mut map = TopoMap{
+            interpolation: interpolation,
+            positions: positions.to_vec(),
+            spheres: spheres,
+            spline: spline,
+            weights: Vec::with_capacity((MAP_SIZE*MAP_SIZE) as usize),
+        };
+        for py in 0..MAP_SIZE {
+            for px in 0..MAP_SIZE {
+                let (x, y) = TopoMap::plane(px, py);
+                let w = if x*x + y*y > 1.0 || n == 0 {None} else {Some(map.weights_at(x, y))};
+                map.weights.push(w);
+            }
+        }
+        map
+    }
+
+    //how much each electrode counts towards the value at a point on the head
+    fn weights_at(&self, x: f32, y: f32) -> Vec<f32> {
+        let n = self.positions.len();
+        match self.spline {
+            Some(ref inverse) => {
+                let here = to_sphere((x, y));
+                let mut g: Vec<f64> = self.spheres.iter().map(|s| spline_kernel(dot(here, *s))).collect();
+                g.push(1.0);
+                (0..n).map(|i| (0..n+1).map(|j| inverse[j][i]*g[j]).sum::<f64>() as f32).collect()
+            },
+            //also what a spline falls back to if its electrodes are coincident
+            None => {
+                let d: Vec<f32> = self.positions.iter().map(|p| ((p.0 - x).powi(2) + (p.1 - y).powi(2)).sqrt()).collect();
+                match d.iter().position(|d| *d < 1e-4) {
+                    Some(i) => (0..n).map(|j| if i == j {1.0} else {0.0}).collect(),
+                    None => {
+                        let raw: Vec<f32> = d.iter().map(|d| d.powf(-IDW_POWER)).collect();
+                        let total: f32 = raw.iter().sum();
+                        raw.iter().map(|w| w/total).collect()
+                    },
+                }
+            },
+        }
+    }
+
+    pub fn matches(&self, positions: &[(f32, f32)], interpolation: Interpolation) -> bool {
+        self.interpolation == interpolation && self.positions == positions
+    }
+
+    //the head's coordinates at the centre of a pixel, nose up
+    fn plane(px: u32, py: u32) -> (f32, f32) {
+        let step = 2.0*EXTENT/MAP_SIZE as f32;
+        (-EXTENT + (px as f32 + 0.5)*step, EXTENT - (py as f32 + 0.5)*step)
+    }
+
+    fn pixel(x: f32, y: f32) -> (i32, i32) {
+        let step = 2.0*EXTENT/MAP_SIZE as f32;
+        (((x + EXTENT)/step) as i32, ((EXTENT - y)/step) as i32)
+    }
+
+    //the values coloured from the lowest to the highest electrode, with the head outline and a dot at each electrode
+    pub fn image(&self, values: &[f32], range: (f32, f32)) -> City2D {
+        let mut image = City2D::new(MAP_SIZE, MAP_SIZE);
+        let span = if range.1 > range.0 {range.1 - range.0} else {1.0};
+        let edge = 2.0*EXTENT/MAP_SIZE as f32;
+        for py in 0..MAP_SIZE {
+            for px in 0..MAP_SIZE {
+                let (x, y) = TopoMap::plane(px, py);
+                let r = (x*x + y*y).sqrt();
+                if (r - 1.0).abs() < edge {
+                    image.set_pixel(px, py, 160, 160, 160);
+                    continue;
+                }
+                if let Some(ref w) = self.weights[(py*MAP_SIZE + px) as usize] {
+                    let v = w.iter().zip(values.iter()).map(|(w, v)| w*v).sum::<f32>();
+                    let (red, green, blue) = panelimage::sequential((v - range.0)/span);
+                    image.set_pixel(px, py, red, green, blue);
+                }
+            }
+        }
+        for p in &self.positions {
+            let (cx, cy) = TopoMap::pixel(p.0, p.1);
+            for dy in -2..3 {
+                for dx in -2..3 {
+                    let (x, y) = (cx + dx, cy + dy);
+                    if x < 0 || y < 0 || x >= MAP_SIZE as i32 || y >= MAP_SIZE as i32 {continue;}
+                    let shade = if dx.abs() == 2 || dy.abs() == 2 {255} else {0};
+                    image.set_pixel(x as u32, y as u32, shade, shade, shade);
+                }
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Fz, Cz, Pz, C3, C4, F3 and P4 on the flattened layout
+    const POSITIONS: [(f32, f32); 7] = [(0.0, 0.4), (0.0, 0.0), (0.0, -0.4), (-0.4, 0.0), (0.4, 0.0), (-0.3, 0.4), (0.3, -0.4)];
+    const VALUES: [f32; 7] = [1.0, 4.0, 2.5, -3.0, 0.5, 2.0, -1.0];
+
+    #[test]
+    fn electrodes_keep_their_own_values() {
+        for interpolation in &Interpolation::ALL {
+            let map = TopoMap::new(&POSITIONS, *interpolation);
+            for (p, v) in POSITIONS.iter().zip(VALUES.iter()) {
+                let here: f32 = map.weights_at(p.0, p.1).iter().zip(VALUES.iter()).map(|(w, v)| w*v).sum();
+                assert!((here - v).abs() < 1e-3, "{} at {:?}: {} not {}", interpolation.name(), p, here, v);
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_is_drawn_outside_the_head() {
+        for interpolation in &Interpolation::ALL {
+            let map = TopoMap::new(&POSITIONS, *interpolation);
+            let rgb = map.image(&VALUES, (-3.0, 4.0)).rgb();
+            let edge = 2.0*EXTENT/MAP_SIZE as f32;
+            for py in 0..MAP_SIZE {
+                for px in 0..MAP_SIZE {
+                    let (x, y) = TopoMap::plane(px, py);
+                    let r = (x*x + y*y).sqrt();
+                    let i = 3*(py*MAP_SIZE + px) as usize;
+                    let weights = &map.weights[(py*MAP_SIZE + px) as usize];
+                    if r > 1.0 + edge {
+                        assert!(weights.is_none());
+                        assert!(rgb[i..i+3].iter().all(|c| *c == 0), "{:?} is drawn", (x, y));
+                    }
+                    if r < 1.0 {assert!(weights.is_some(), "{:?} is left out", (x, y));}
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,33 @@
 use std;
 use conrod;
 use glium;
-use appstate;
-use appstate::{AppState, WaveData, GuiDisplay};
-use openbci_file::{OpenBCIFile};
-use waveformdrawer::{WaveformDrawer,WaveformDrawerSettings};
-use pastuff;
+use appstate::{AppState, GuiDisplay};
+use detrend::{Detrend};
+use derived::{DerivedChannel};
+use layout::{LayoutMode};
+use presets::{Preset};
+use session::{Session};
+use recorder::{RecordFormat};
+use export;
+use export::{Export, MatrixFormat};
+use annotations;
+use ersp::{Ersp};
+use worker::{Worker};
+use panelimage;
+use waveformdrawer::{Transform};
+use multitaper::{Tapers};
+use dftwindower::{WindowShape};
+use peaks;
+use connectivity;
+use connectivity::{Connectivity, Measure};
+use topomap;
+use topomap::{TopoMap, Interpolation, Scalar};
+use artifacts;
+use artifacts::{ArtifactKind};
+use ica::{Ica, Algorithm, ComponentLabel};
+use neurofeedback::{Neurofeedback, Metric, Reward, FeedbackKind};
+use ssvep;
+use ssvep::{Ssvep};
 
 // Generate a unique const `WidgetId` for each widget.
 widget_ids!{
@@ -21,32 +43,414 @@ widget_ids!{
         sldier_amplification,
         toggle_manamp,
         drop_down_dft_window_shape,
+        slider_window_parameter,
+        window_metrics,
+        drop_down_detrend,
         freq_line,
         freq_display,
         freq_display_bg,
+        drop_down_panel,
+        bp_band_sliders[],
+        bp_legend[],
+        bp_bars[],
+        bp_traces[],
+        bp_ratio_traces[],
+        bp_ratio_text[],
+        toggle_highpass,
+        slider_highpass,
+        toggle_lowpass,
+        slider_lowpass,
+        slider_filter_order,
+        toggle_notch,
+        drop_down_mains,
+        slider_notch_harmonics,
+        mt_info,
+        mt_kind,
+        mt_channel,
+        mt_reference,
+        mt_second_reference,
+        mt_neighbours[],
+        mt_add,
+        mt_add_car_all,
+        mt_remove[],
+        mt_drawers_heading,
+        mt_drawer_channel[],
+        channel_labels[],
+        ly_mode,
+        ly_visible[],
+        ly_up[],
+        ly_down[],
+        pr_name,
+        pr_save,
+        pr_status,
+        pr_load[],
+        pr_delete[],
+        ss_path,
+        ss_save,
+        ss_load,
+        ss_status,
+        rc_format,
+        rc_path,
+        rc_toggle,
+        rc_status,
+        rc_marker_label,
+        rc_marker,
+        ex_channel,
+        ex_range,
+        ex_whole,
+        ex_path,
+        ex_format,
+        ex_image,
+        ex_matrix,
+        ex_status,
+        pk_tracks[],
+        an_lines[],
+        an_labels[],
+        an_help,
+        an_marker_label,
+        an_marker,
+        an_path,
+        an_import,
+        an_save,
+        an_clear,
+        an_status,
+        an_list,
+        er_marker,
+        er_channel,
+        er_pre,
+        er_post,
+        er_baseline,
+        er_compute,
+        er_status,
+        er_map,
+        er_colorbar,
+        er_db_high,
+        er_db_low,
+        er_axes,
+        tf_help,
+        tf_label[],
+        tf_kind[],
+        tf_first[],
+        tf_second[],
+        tf_window[],
+        tf_fft[],
+        tf_pow2[],
+        pk_help,
+        pk_label[],
+        pk_count[],
+        pk_jump[],
+        pk_path,
+        pk_export,
+        pk_status,
+        co_measure,
+        co_first,
+        co_second,
+        co_span,
+        co_band,
+        co_live,
+        co_compute,
+        co_status,
+        co_plot,
+        co_band_low,
+        co_band_high,
+        co_trace,
+        co_plot_axes,
+        co_matrix,
+        co_colorbar,
+        co_high,
+        co_low,
+        co_matrix_axes,
+        tp_interpolation,
+        tp_scalar,
+        tp_status,
+        tp_map,
+        tp_labels[],
+        tp_colorbar,
+        tp_high,
+        tp_low,
+        ar_shades[],
+        ar_help,
+        ar_kind[],
+        ar_threshold[],
+        ar_flat_s,
+        ar_exclude,
+        ar_detect,
+        ar_status,
+        ic_help,
+        ic_algorithm,
+        ic_run,
+        ic_status,
+        ic_component,
+        ic_label,
+        ic_time,
+        ic_time_trace,
+        ic_time_axes,
+        ic_spectrum,
+        ic_spectrum_trace,
+        ic_spectrum_axes,
+        ic_apply,
+        ic_show,
+        nf_wash,
+        nf_bar_frame,
+        nf_bar,
+        nf_bar_threshold,
+        nf_bar_text,
+        nf_help,
+        nf_drawer,
+        nf_metric,
+        nf_reward,
+        nf_kind,
+        nf_adaptive,
+        nf_smoothing,
+        nf_target,
+        nf_window,
+        nf_threshold,
+        nf_path,
+        nf_toggle,
+        nf_status,
+        sv_help,
+        sv_frequencies,
+        sv_harmonics,
+        sv_confidence,
+        sv_windows,
+        sv_address,
+        sv_toggle,
+        sv_status,
+        sv_bars[],
+        sv_bar_labels[],
+        sv_decision,
     }
 }
-pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &Ids, display: &'b glium::Display, app: &mut AppState<'b>){
+
+//colours used for each band in the band power panel, cycled if there are more bands
+const BAND_COLOURS: [(f32,f32,f32); 5] = [
+    (0.35, 0.45, 1.0),
+    (0.2, 0.85, 0.85),
+    (0.3, 0.9, 0.3),
+    (1.0, 0.85, 0.2),
+    (1.0, 0.35, 0.35),
+];
+fn band_colour(band: usize) -> conrod::Color {
+    let c = BAND_COLOURS[band % BAND_COLOURS.len()];
+    conrod::color::rgb(c.0, c.1, c.2)
+}
+
+pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &mut Ids, display: &'b glium::Display, app: &mut AppState<'b>){
     #![allow(unused_imports)]
     #![allow(non_snake_case)]
 
     use conrod::{color, widget, Colorable, Labelable, Positionable, Scalar, Sizeable, Widget};
     let path = std::path::Path::new("data/");
 
-    //So the window width and framebuffer width are different!!!!
-    //They may or may not correspond to the actual window width
-    //This is all a bit confusing so there are some helper functions for this
-    let win_w = ui.win_w;
-    let win_h = ui.win_h;
-    let X = |x: f64| x*win_w/100.0;
-    let Y = |x: f64| x*win_h/100.0;
-    let iX = |x: f32| x/win_w as f32*100.0;
-    let iY = |x: f32| x/win_h as f32*100.0;
+    //everything is laid out in percentages of the window, the viewport converts these to conrod's logical points
+    //and is shared with the texture drawing so the two always agree, see viewport.rs
+    let viewport = app.gui_data.viewport;
+    let X = |x: f64| viewport.x(x);
+    let Y = |x: f64| viewport.y(x);
+
+
+    //label each spectrogram with the channel it is showing
+    if app.gui_data.gui_display != GuiDisplay::FileOpen {
+        let names = app.app_data.lock().unwrap().channel_names();
+        ids.channel_labels.resize(app.waveform_drawers.len(), &mut ui.widget_id_generator());
+        for (d, wfd) in app.waveform_drawers.iter().enumerate() {
+            if !wfd.settings.visible {continue;}
+            let name = match names.get(wfd.settings.channel as usize) {Some(name) => name.clone(), None => continue};
+            let t = widget::Text::new(&name).font_size(14).color(conrod::color::WHITE);
+            let w = t.get_w(ui).unwrap();
+            let left = X((wfd.settings.x - wfd.settings.width/2.0) as f64);
+            let top = Y((wfd.settings.y + wfd.settings.height/2.0) as f64);
+            t.x_y(left + w/2.0 + 6.0, top - 12.0).set(ids.channel_labels[d], ui);
+        }
+    }
+
+    //peak tracks are lines over the spectrogram, placed in time the same way as the annotations
+    if app.gui_data.gui_display != GuiDisplay::FileOpen {
+        let ticks = app.ticker.ticks();
+        let sample_rate = app.app_data.lock().unwrap().get_sample_rate().unwrap_or(0);
+        let mut paths = Vec::<(Vec<[f64; 2]>, u32)>::new(); //points, track id
+        for wfd in app.waveform_drawers.iter().filter(|wfd| wfd.settings.visible && wfd.settings.peaks > 0) {
+            let ref s = wfd.settings;
+            let now_s = wfd.position_ms(ticks) as f64/1000.0;
+            let span_s = s.time_pixels as f64*s.milliseconds_per_pixel as f64/1000.0;
+            let top_hz = s.dtft_display_samples as f32*s.bin_hz(sample_rate);
+            for track in wfd.peak_tracker.tracks.iter().filter(|t| t.points.len() >= peaks::MIN_DRAWN_POINTS) {
+                let points: Vec<[f64; 2]> = track.points.iter()
+                    .filter(|p| p.time_s <= now_s && p.time_s >= now_s - span_s && p.freq_hz < top_hz)
+                    .map(|p| [
+                        X((s.x + s.width/2.0) as f64 - (now_s - p.time_s)/span_s*s.width as f64),
+                        Y((s.y - s.height/2.0 + p.freq_hz/top_hz*s.height) as f64),
+                    ]).collect();
+                if points.len() >= 2 {paths.push((points, track.id));}
+            }
+        }
+        ids.pk_tracks.resize(paths.len(), &mut ui.widget_id_generator());
+        for (i, (points, id)) in paths.into_iter().enumerate() {
+            widget::PointPath::abs(points)
+                .color(band_colour(id as usize))
+                .thickness(2.0)
+                .set(ids.pk_tracks[i], ui);
+        }
+    }
+
+    //flagged artifacts shade the part of each drawer they cover, over its whole height
+    if app.gui_data.gui_display != GuiDisplay::FileOpen {
+        let ticks = app.ticker.ticks();
+        let mut shades = Vec::<(f64, f64, f64, f64, ArtifactKind)>::new(); //left, right, top, bottom
+        {
+            let app_data = app.app_data.lock().unwrap();
+            if let Some(ref wave_data) = app_data.wave_data {
+                for wfd in app.waveform_drawers.iter().filter(|wfd| wfd.settings.visible) {
+                    let ref s = wfd.settings;
+                    let now_s = wfd.position_ms(ticks) as f64/1000.0;
+                    let span_s = s.time_pixels as f64*s.milliseconds_per_pixel as f64/1000.0;
+                    let right = (s.x + s.width/2.0) as f64;
+                    for a in wave_data.artifacts_for(s.channel as usize).iter().filter(|a| a.overlaps(now_s - span_s, now_s)) {
+                        let x = |t: f64| right - (now_s - t.max(now_s - span_s).min(now_s))/span_s*s.width as f64;
+                        shades.push((X(x(a.start_s)), X(x(a.end_s)), Y((s.y + s.height/2.0) as f64), Y((s.y - s.height/2.0) as f64), a.kind));
+                    }
+                }
+            }
+        }
+        ids.ar_shades.resize(shades.len(), &mut ui.widget_id_generator());
+        for (i, &(left, right, top, bottom, kind)) in shades.iter().enumerate() {
+            let c = kind.colour();
+            widget::Rectangle::fill([(right - left).max(1.0), top - bottom])
+                .x_y((left + right)/2.0, (top + bottom)/2.0)
+                .color(conrod::color::rgba(c.0, c.1, c.2, 0.25))
+                .set(ids.ar_shades[i], ui);
+        }
+    }
+
+    //annotations are vertical lines across every visible drawer, placed by how long ago they happened so they scroll with it
+    if app.gui_data.gui_display != GuiDisplay::FileOpen {
+        let ticks = app.ticker.ticks();
+        let mut lines = Vec::<(f64, f64, f64, &str)>::new(); //x, top, bottom, label
+        for wfd in app.waveform_drawers.iter().filter(|wfd| wfd.settings.visible) {
+            let ref s = wfd.settings;
+            let now_s = wfd.position_ms(ticks) as f64/1000.0;
+            let span_s = s.time_pixels as f64*s.milliseconds_per_pixel as f64/1000.0;
+            for a in app.annotations.iter().filter(|a| a.time_s <= now_s && a.time_s >= now_s - span_s) {
+                let x = (s.x + s.width/2.0) as f64 - (now_s - a.time_s)/span_s*s.width as f64;
+                lines.push((X(x), Y((s.y + s.height/2.0) as f64), Y((s.y - s.height/2.0) as f64), &a.label));
+            }
+        }
+        ids.an_lines.resize(lines.len(), &mut ui.widget_id_generator());
+        ids.an_labels.resize(lines.len(), &mut ui.widget_id_generator());
+        for (i, &(x, top, bottom, label)) in lines.iter().enumerate() {
+            widget::Line::abs([x, top], [x, bottom])
+                .color(conrod::color::YELLOW)
+                .thickness(1.5)
+                .set(ids.an_lines[i], ui);
+            widget::Text::new(label)
+                .font_size(11)
+                .color(conrod::color::YELLOW)
+                .x_y(x + 4.0, top - 28.0)
+                .set(ids.an_labels[i], ui);
+        }
+    }
+
+    //a running neurofeedback session takes in the drawer's new band powers every frame, whichever panel is open,
+    //the wash covers the spectrograms and the bar stands at their right hand edge
+    if app.gui_data.gui_display != GuiDisplay::FileOpen && app.gui_data.neurofeedback.is_some() {
+        let result = {
+            let nf = app.gui_data.neurofeedback.as_mut().unwrap();
+            match app.waveform_drawers.get(nf.drawer) {
+                Some(wfd) => nf.update(&wfd.band_history, &app.filter_data.band_ratios, &app.gui_data.feedback_settings),
+                None => Err(String::from("The drawer the session was reading from has gone.")),
+            }
+        };
+        if let Err(e) = result {
+            let nf = app.gui_data.neurofeedback.take().unwrap();
+            app.gui_data.feedback_status = match nf.stop(app.ticker.ticks()) {
+                Ok(summary) => format!("Stopped: {} {}", e, summary),
+                Err(stop_error) => format!("Stopped: {} {}", e, stop_error),
+            };
+        }
+    }
+    if let Some(ref nf) = app.gui_data.neurofeedback {
+        match app.gui_data.feedback_settings.kind {
+            FeedbackKind::Wash => {
+                if nf.rewarded {
+                    widget::Rectangle::fill([X(75.0), Y(100.0)])
+                        .x_y(X(-12.5), Y(0.0))
+                        .color(conrod::color::rgba(0.2, 0.9, 0.3, 0.1 + 0.4*nf.margin()))
+                        .set(ids.nf_wash, ui);
+                }
+            },
+            FeedbackKind::Bar => {
+                let (lo, hi) = nf.range();
+                let (h, bottom) = (Y(80.0), Y(-40.0));
+                let fraction = |v: f32| ((v - lo)/(hi - lo)).max(0.0).min(1.0) as f64;
+                widget::Rectangle::outline([X(3.0), h])
+                    .x_y(X(22.5), Y(0.0))
+                    .color(conrod::color::GREY)
+                    .set(ids.nf_bar_frame, ui);
+                if let Some(v) = nf.value {
+                    let filled = (h*fraction(v)).max(1.0);
+                    widget::Rectangle::fill([X(2.6), filled])
+                        .x_y(X(22.5), bottom + filled/2.0)
+                        .color(if nf.rewarded {conrod::color::rgb(0.2, 0.9, 0.3)} else {conrod::color::rgb(0.4, 0.4, 0.45)})
+                        .set(ids.nf_bar, ui);
+                }
+                let threshold_y = bottom + h*fraction(nf.threshold);
+                widget::Line::abs([X(21.0), threshold_y], [X(24.0), threshold_y])
+                    .color(conrod::color::YELLOW)
+                    .thickness(2.0)
+                    .set(ids.nf_bar_threshold, ui);
+                widget::Text::new(&nf.value.map(|v| format!("{:.2}", v)).unwrap_or(String::new()))
+                    .font_size(12)
+                    .color(conrod::color::WHITE)
+                    .x_y(X(22.5), bottom - 12.0)
+                    .set(ids.nf_bar_text, ui);
+            },
+            FeedbackKind::Tone => (),
+        }
+    }
 
-    /* let (fb_w,fb_h) = display.get_framebuffer_dimensions();
-    let fbX = |x: f64| x*fb_w as f64/100.0;
-    let fbY = |x: f64| x*fb_h as f64/100.0; */
+    //a running SSVEP detector scores the channels the drawers show, up to where the first drawer is, whichever panel is open
+    if app.gui_data.gui_display != GuiDisplay::FileOpen && app.gui_data.ssvep.is_some() {
+        let ticks = app.ticker.ticks();
+        let mut channels = Vec::<usize>::new();
+        for wfd in app.waveform_drawers.iter().filter(|wfd| wfd.settings.visible) {
+            if !channels.contains(&(wfd.settings.channel as usize)) {channels.push(wfd.settings.channel as usize);}
+        }
+        let end_ms = app.waveform_drawers.first().map(|wfd| wfd.position_ms(ticks)).unwrap_or(0);
+        let result = {
+            let app_data = app.app_data.lock().unwrap();
+            match app_data.get_sample_rate() {
+                Ok(sample_rate) => {
+                    let n_channels = app_data.channel_names().len();
+                    let samples: Vec<&[f32]> = channels.iter().filter(|c| **c < n_channels).map(|c| app_data.samples(*c)).collect();
+                    let shortest = samples.iter().map(|s| s.len()).min().unwrap_or(0);
+                    let end = std::cmp::min((end_ms*sample_rate as u64/1000) as usize, shortest);
+                    let end_s = end as f64/sample_rate as f64;
+                    let marker_s = app.annotations.iter().rev().map(|a| a.time_s).find(|t| *t <= end_s);
+                    app.gui_data.ssvep.as_mut().unwrap().update(&samples, end, sample_rate, &app.gui_data.ssvep_settings, marker_s)
+                },
+                Err(e) => Err(e.to_string()),
+            }
+        };
+        if let Err(e) = result {app.gui_data.ssvep_status = e;}
+    }
+
+    //every panel apart from the file chooser shares the right hand canvas and a drop down to switch between panels
+    if app.gui_data.gui_display != GuiDisplay::FileOpen {
+        widget::Canvas::new()
+            .color(conrod::color::DARK_CHARCOAL)
+            .x_y(X(37.5),Y(0.0))
+            .w_h(X(25.0),Y(100.0))
+            .set(ids.settings_canvas, ui);
 
+        let panel_items: Vec<String> = GuiDisplay::PANELS.iter().map(|p| p.name().to_string()).collect();
+        let panel_index = GuiDisplay::PANELS.iter().position(|p| *p == app.gui_data.gui_display);
+        for drop in widget::DropDownList::new(&panel_items, panel_index)
+            .top_left_with_margins_on(ids.settings_canvas, Y(0.5), X(0.5))
+            .w_h(X(7.0), Y(2.0))
+            .label_font_size(12)
+            .set(ids.drop_down_panel, ui)
+            {app.gui_data.gui_display = GuiDisplay::PANELS[drop];}
+    }
 
     match app.gui_data.gui_display {
         GuiDisplay::FileOpen =>
@@ -68,41 +472,17 @@ pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &Ids, display: &'b glium::Dis
                     println!("{:?}", app.gui_data.file_selection);
 
                     if app.gui_data.file_selection.is_some() {
-                        // ## load OPENBCI file
-                        println!("Reading OpenBCI data file.");
-                        let openbci_file=OpenBCIFile::new(app.gui_data.file_selection.take().unwrap().to_str().unwrap());
-                        let wave_data = WaveData{
-                            buffer: openbci_file.samples.clone(),
-                            channels: openbci_file.channels,
-                            sample_rate: 200,
-                            buffer_length: openbci_file.samples[0].len()
-                        };
-                        let app_data_arc=app.app_data.clone();
-                        let mut app_data = app_data_arc.lock().unwrap();
-                        app_data.wave_data = Some(wave_data);
-                        app_data.data_source = appstate::DataSource::WavBuffer;
-
-                        println!("Initialising waveform drawer.");
-                        app.waveform_drawers.clear();
-                        for i in 0..openbci_file.channels{
-                        app.waveform_drawers.push( WaveformDrawer::new( display,
-                            WaveformDrawerSettings{
-                                    x: -12.5,
-                                    y: 37.5 - 25.0 *i as f32,
-                                    width: 75.0,
-                                    height: 20.0,
-                                    milliseconds_per_pixel: 8.0,
-                                    time_pixels: 1000,
-                                    dtft_samples: 800,
-                                    dtft_display_samples: 200,
-                                    channel: i}))
-                        }
-
-                        let ticks=app.ticker.ticks();
-                        for wfd in &mut app.waveform_drawers{
-                            wfd.start(ticks);
+                        let file_path = app.gui_data.file_selection.take().unwrap();
+                        //a saved session can be opened straight from the file navigator
+                        if file_path.extension().map(|e| e == "json").unwrap_or(false) {
+                            match Session::load(&file_path).and_then(|session| session.restore(app, display)) {
+                                Ok(()) => app.gui_data.session_path = file_path.to_string_lossy().into_owned(),
+                                Err(e) => println!("{}", e),
+                            }
+                        } else {
+                            app.open_file(display, &file_path);
+                            app.gui_data.gui_display=GuiDisplay::FilterOptions;
                         }
-                        app.gui_data.gui_display=GuiDisplay::FilterOptions;
                     }
 
                 }
@@ -114,28 +494,8 @@ pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &Ids, display: &'b glium::Dis
                 .w_h(X(20.0), Y(5.0))
                 .set(ids.btn_useportaudio, ui)
                 {
-
-                    pastuff::pa_read_from_mic(app);
-
-                    println!("Initialising waveform drawer.");
-                    app.waveform_drawers.clear();
-                    app.waveform_drawers.push( WaveformDrawer::new( display,
-                        WaveformDrawerSettings{
-                                x: -12.5,
-                                y: 0.0,
-                                width: 75.0,
-                                height: 100.0,
-                                milliseconds_per_pixel: 5.0,
-                                time_pixels: 1600,
-                                dtft_samples: 1800,
-                                dtft_display_samples: 300,
-                                channel: 0}));
-
-                    let ticks=app.ticker.ticks();
-                    for wfd in &mut app.waveform_drawers{
-                        wfd.start(ticks);
+                    app.open_portaudio(display);
                     app.gui_data.gui_display=GuiDisplay::FilterOptions;
-                    }
                 }
 
             // Navigate the conrod directory only showing `.rs` and `.toml` files.
@@ -158,11 +518,7 @@ pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &Ids, display: &'b glium::Dis
         }
         GuiDisplay::FilterOptions =>
         {
-            widget::Canvas::new()
-                .color(conrod::color::DARK_CHARCOAL)
-                .x_y(X(37.5),Y(0.0))
-                .w_h(X(25.0),Y(100.0))
-                .set(ids.settings_canvas, ui);
+            let nyquist = app.app_data.lock().unwrap().get_sample_rate().unwrap_or(200) as f32 / 2.0;
             let ref mut fd = app.filter_data;
 
             for (x, y) in widget::XYPad::new(fd.green.0, fd.min_green.0, fd.max_green.0,
@@ -213,36 +569,174 @@ pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &Ids, display: &'b glium::Dis
                     {fd.amp=value;}
             }
 
-            let list_items = [
-                "Rectangular Window".to_string(),
-                "Hann Window".to_string(),
-                "Hamming Window".to_string(),
-                "Nuttall Window".to_string(),
-                "Sine Window".to_string(),
-                "Kaiser Window".to_string(),
-            ];
-
-            for drop in widget::DropDownList::new(&list_items,Some(fd.window_shape as usize))
+            let list_items: Vec<String> = WindowShape::ALL.iter().map(|w| w.name().to_string()).collect();
+            let shape_index = WindowShape::ALL.iter().position(|w| w.same_kind(&fd.window_shape));
+            for drop in widget::DropDownList::new(&list_items, shape_index)
                 .align_middle_x_of(ids.settings_canvas)
                 .w_h(X(20.0),X(3.0))
                 .down(Y(5.0))
                 .set(ids.drop_down_dft_window_shape, ui)
-                {fd.window_shape = drop as i32;}
+                {fd.window_shape = WindowShape::ALL[drop];}
 
+            if let Some((label, value, min, max)) = fd.window_shape.parameter() {
+                for v in widget::Slider::new(value, min, max)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.5))
+                    .label(&format!("{} {:.2}", label, value))
+                    .label_font_size(12)
+                    .set(ids.slider_window_parameter, ui)
+                    {fd.window_shape = fd.window_shape.with_parameter(v);}
+            }
+
+            let stale = match app.gui_data.window_metrics {
+                Some((shape, _)) => shape != fd.window_shape,
+                None => true,
+            };
+            if stale {app.gui_data.window_metrics = Some((fd.window_shape, fd.window_shape.metrics()));}
+            if let Some((_, metrics)) = app.gui_data.window_metrics {
+                widget::Text::new(&format!("Coherent gain {:.3}, ENBW {:.2} bins\nScalloping loss {:.2} dB, sidelobes {:.1} dB",
+                        metrics.coherent_gain, metrics.enbw_bins, metrics.scalloping_loss_db, metrics.sidelobe_db))
+                    .font_size(12)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down(Y(0.5))
+                    .set(ids.window_metrics, ui);
+            }
+
+            let detrend_items: Vec<String> = Detrend::ALL.iter().map(|d| d.name().to_string()).collect();
+            let detrend_index = Detrend::ALL.iter().position(|d| *d == fd.detrend);
+            for drop in widget::DropDownList::new(&detrend_items, detrend_index)
+                .align_middle_x_of(ids.settings_canvas)
+                .w_h(X(20.0),X(3.0))
+                .down(Y(1.0))
+                .set(ids.drop_down_detrend, ui)
+                {fd.detrend = Detrend::ALL[drop];}
+
+            ids.bp_band_sliders.resize(fd.bands.len(), &mut ui.widget_id_generator());
+            let mut bands_changed = false;
+            for b in 0..fd.bands.len() {
+                let label = format!("{} {:.1}-{:.1} Hz", fd.bands[b].name, fd.bands[b].low_hz, fd.bands[b].high_hz);
+                let slider = widget::RangeSlider::new(fd.bands[b].low_hz, fd.bands[b].high_hz, 0.5, 60.0)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .label(&label)
+                    .label_font_size(12)
+                    .color(band_colour(b));
+                let slider = if b == 0 {slider.down_from(ids.drop_down_detrend, Y(3.0))} else {slider.down(Y(0.5))};
+                for (edge, value) in slider.set(ids.bp_band_sliders[b], ui) {
+                    match edge {
+                        widget::range_slider::Edge::Start => fd.bands[b].low_hz = value,
+                        widget::range_slider::Edge::End => fd.bands[b].high_hz = value,
+                    }
+                    bands_changed = true;
+                }
+            }
+            if bands_changed {
+                for wfd in &mut app.waveform_drawers {wfd.band_history.clear();}
+            }
+
+            //filter chain, applied to the data before it reaches the stft
+            let mut filters_changed = false;
+            {
+                let ref mut filters = fd.filters;
+                for v in widget::Toggle::new(filters.highpass)
+                    .label("High-pass")
+                    .label_color(if filters.highpass { conrod::color::WHITE } else { conrod::color::LIGHT_CHARCOAL })
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(2.0))
+                    .set(ids.toggle_highpass, ui)
+                    {filters.highpass = v; filters_changed = true;}
+                let label = format!("High-pass {:.2} Hz", filters.highpass_hz);
+                for v in widget::Slider::new(filters.highpass_hz, 0.1, nyquist.min(10.0))
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.0))
+                    .label(&label)
+                    .label_font_size(12)
+                    .set(ids.slider_highpass, ui)
+                    {filters.highpass_hz = v; filters_changed = true;}
+                for v in widget::Toggle::new(filters.lowpass)
+                    .label("Low-pass")
+                    .label_color(if filters.lowpass { conrod::color::WHITE } else { conrod::color::LIGHT_CHARCOAL })
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.5))
+                    .set(ids.toggle_lowpass, ui)
+                    {filters.lowpass = v; filters_changed = true;}
+                let label = format!("Low-pass {:.1} Hz", filters.lowpass_hz);
+                for v in widget::Slider::new(filters.lowpass_hz.min(nyquist*0.95), 1.0, nyquist*0.95)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.0))
+                    .label(&label)
+                    .label_font_size(12)
+                    .set(ids.slider_lowpass, ui)
+                    {filters.lowpass_hz = v; filters_changed = true;}
+                let label = format!("Butterworth order {}", filters.order);
+                for v in widget::Slider::new(filters.order as f32, 2.0, 8.0)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.5))
+                    .label(&label)
+                    .label_font_size(12)
+                    .set(ids.slider_filter_order, ui)
+                    {
+                        let order = 2*(v/2.0).round() as u32;
+                        if order != filters.order {filters.order = order; filters_changed = true;}
+                    }
+                for v in widget::Toggle::new(filters.notch)
+                    .label("Mains notch")
+                    .label_color(if filters.notch { conrod::color::WHITE } else { conrod::color::LIGHT_CHARCOAL })
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.5))
+                    .set(ids.toggle_notch, ui)
+                    {filters.notch = v; filters_changed = true;}
+                let mains_items = ["50 Hz mains".to_string(), "60 Hz mains".to_string()];
+                for drop in widget::DropDownList::new(&mains_items, Some(if filters.mains_hz == 60.0 {1} else {0}))
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.0))
+                    .label_font_size(12)
+                    .set(ids.drop_down_mains, ui)
+                    {filters.mains_hz = if drop == 1 {60.0} else {50.0}; filters_changed = true;}
+                let label = format!("Notch harmonics {}", filters.notch_harmonics);
+                for v in widget::Slider::new(filters.notch_harmonics as f32, 1.0, 5.0)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .w_h(X(20.0),X(1.5))
+                    .down(Y(0.0))
+                    .label(&label)
+                    .label_font_size(12)
+                    .set(ids.slider_notch_harmonics, ui)
+                    {
+                        let harmonics = v.round() as u32;
+                        if harmonics != filters.notch_harmonics {filters.notch_harmonics = harmonics; filters_changed = true;}
+                    }
+            }
+            //refiltering a whole file is too slow to do on every step of a drag, so changes wait for the mouse to be let go
+            if filters_changed {app.gui_data.filters_pending = true;}
+            if app.gui_data.filters_pending && ui.global_input().current.mouse.buttons.left().is_up() {
+                app.gui_data.filters_pending = false;
+                app.app_data.lock().unwrap().set_filters(&fd.filters);
+                app.gui_data.clear_ica();
+            }
+
+            let sample_rate = app.app_data.lock().unwrap().get_sample_rate().unwrap_or(0);
             for wfd in &app.waveform_drawers {
-                let x = iX(app.gui_data.cursor_xy.0);
-                let y = iY(app.gui_data.cursor_xy.1);
-                if  (x - wfd.settings.x - 50.0).abs() < wfd.settings.width/2.0 &&
-                    (y - wfd.settings.y - 50.0).abs() < wfd.settings.height/2.0 {
+                if !wfd.settings.visible {continue;}
+                let (x, y) = viewport.cursor_to_percent(app.gui_data.cursor_xy);
+                if  (x - wfd.settings.x).abs() < wfd.settings.width/2.0 &&
+                    (y - wfd.settings.y).abs() < wfd.settings.height/2.0 {
                         //hf is how far above the bottom of the spectrum is the mouse as a proportion of the height of the spectrum.
-                        let hf:f32 = 0.5 - (y - wfd.settings.y - 50.0)/ wfd.settings.height;
-                        let freq:f32 = hf * wfd.settings.dtft_display_samples as f32 * pastuff::PA_SAMPLE_RATE as f32 / wfd.settings.dtft_samples as f32;
+                        let hf:f32 = 0.5 + (y - wfd.settings.y)/ wfd.settings.height;
+                        let freq:f32 = hf * wfd.settings.dtft_display_samples as f32 * wfd.settings.bin_hz(sample_rate);
                         let freqs = format!("{:.2}", freq) + " Hz";
                         widget::Line::centred([0.0,0.0], [X(wfd.settings.width as f64),0.0])
-                        .x_y(X(wfd.settings.x as f64),Y(50.0 - y as f64))
+                        .x_y(X(wfd.settings.x as f64),Y(y as f64))
                         .set(ids.freq_line, ui);
                         let t = widget::Text::new(&freqs)
-                        .y(Y(50.0 - y as f64))
+                        .y(Y(y as f64))
                         .align_left_of(ids.freq_line)
                         .align_middle_y_of(ids.freq_line);
                         widget::Rectangle::fill([t.get_w(ui).unwrap()*1.05, t.get_h(ui).unwrap()*1.1]).align_left_of(ids.freq_line).y_relative_to(ids.freq_line,-2.0).set(ids.freq_display_bg, ui);
@@ -252,6 +746,1977 @@ pub fn gui<'b,'a>(ref mut ui: conrod::UiCell, ids: &Ids, display: &'b glium::Dis
             }
 
         }
+        GuiDisplay::BandPower =>
+        {
+            let ref fd = app.filter_data;
+
+            ids.bp_legend.resize(fd.bands.len(), &mut ui.widget_id_generator());
+            for b in 0..fd.bands.len() {
+                let t = widget::Text::new(&fd.bands[b].name)
+                    .color(band_colour(b))
+                    .font_size(12);
+                let t = if b == 0 {t.right_from(ids.drop_down_panel, X(0.5))} else {t.right_from(ids.bp_legend[b-1], X(0.5))};
+                t.set(ids.bp_legend[b], ui);
+            }
+
+            let n_bands = fd.bands.len();
+            let n_ratios = fd.band_ratios.len();
+            let n_drawers = app.waveform_drawers.len();
+            ids.bp_bars.resize(n_drawers*n_bands, &mut ui.widget_id_generator());
+            ids.bp_traces.resize(n_drawers*n_bands, &mut ui.widget_id_generator());
+            ids.bp_ratio_traces.resize(n_drawers*n_ratios, &mut ui.widget_id_generator());
+            ids.bp_ratio_text.resize(n_drawers*n_ratios, &mut ui.widget_id_generator());
+
+            //each channel gets a row lined up with its spectrogram: gauges on the left, traces on the right
+            let bars_left = 25.5;
+            let bar_w = 7.0 / n_bands as f64;
+            let traces_left = 33.5;
+            let traces_w = 16.0;
+            for (d, wfd) in app.waveform_drawers.iter().enumerate() {
+                if !wfd.settings.visible {continue;}
+                let row_h = wfd.settings.height as f64 * 0.9;
+                let row_bottom = wfd.settings.y as f64 - row_h/2.0;
+
+                if let Some(powers) = wfd.band_history.latest() {
+                    let total: f32 = powers.iter().sum();
+                    for b in 0..n_bands {
+                        let frac = if total > 0.0 {(powers[b]/total) as f64} else {0.0};
+                        let h = (row_h * 0.85 * frac).max(0.1);
+                        widget::Rectangle::fill([X(bar_w*0.8), Y(h)])
+                            .x_y(X(bars_left + bar_w*(b as f64 + 0.5) - 50.0), Y(row_bottom + h/2.0))
+                            .color(band_colour(b))
+                            .set(ids.bp_bars[d*n_bands + b], ui);
+                    }
+                    for r in 0..n_ratios {
+                        let ratio = &fd.band_ratios[r];
+                        let label = format!("{} {:.2}", ratio.name(&fd.bands), ratio.value(powers));
+                        widget::Text::new(&label)
+                            .font_size(12)
+                            .x_y(X(bars_left + 3.5 - 50.0), Y(row_bottom + row_h - 1.5*(r as f64 + 1.0)))
+                            .set(ids.bp_ratio_text[d*n_ratios + r], ui);
+                    }
+                }
+
+                if wfd.band_history.frames.len() < 2 {continue;}
+                //band powers are drawn on a shared log scale so the bands can be compared against each other
+                let traces: Vec<Vec<f32>> = (0..n_bands).map(|b| wfd.band_history.trace(b).iter().map(|p| (p + 1e-12).log10()).collect()).collect();
+                let lo = traces.iter().flat_map(|t| t.iter()).cloned().fold(std::f32::INFINITY, f32::min);
+                let hi = traces.iter().flat_map(|t| t.iter()).cloned().fold(std::f32::NEG_INFINITY, f32::max);
+                let range = if hi - lo > 0.0 {hi - lo} else {1.0};
+                let to_points = |trace: &[f32], lo: f32, range: f32| -> Vec<[f64; 2]> {
+                    let n = trace.len();
+                    trace.iter().enumerate().map(|(i, v)| [
+                        X(traces_left + traces_w * i as f64 / (n - 1) as f64 - 50.0),
+                        Y(row_bottom + row_h * ((v - lo)/range) as f64)
+                    ]).collect()
+                };
+                for b in 0..n_bands {
+                    widget::PointPath::abs(to_points(&traces[b][..], lo, range))
+                        .color(band_colour(b))
+                        .set(ids.bp_traces[d*n_bands + b], ui);
+                }
+                //ratios get their own scale
+                for r in 0..n_ratios {
+                    let trace: Vec<f32> = wfd.band_history.ratio_trace(&fd.band_ratios[r]).iter().map(|v| (v + 1e-12).log10()).collect();
+                    let lo = trace.iter().cloned().fold(std::f32::INFINITY, f32::min);
+                    let hi = trace.iter().cloned().fold(std::f32::NEG_INFINITY, f32::max);
+                    let range = if hi - lo > 0.0 {hi - lo} else {1.0};
+                    widget::PointPath::abs(to_points(&trace[..], lo, range))
+                        .color(conrod::color::WHITE)
+                        .thickness(0.5)
+                        .set(ids.bp_ratio_traces[d*n_ratios + r], ui);
+                }
+            }
+        }
+        GuiDisplay::Montage =>
+        {
+            let (raw_names, names, mut derived) = {
+                let app_data = app.app_data.lock().unwrap();
+                match app_data.wave_data {
+                    Some(ref wave_data) => (wave_data.raw_channel_names(), wave_data.channel_names(), wave_data.derived.clone()),
+                    None => (Vec::<String>::new(), Vec::<String>::new(), Vec::<DerivedChannel>::new()),
+                }
+            };
+            if raw_names.is_empty() {
+                widget::Text::new("Derived channels need a multichannel file to be open.")
+                    .font_size(14)
+                    .w(X(20.0))
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down_from(ids.drop_down_panel, Y(2.0))
+                    .set(ids.mt_info, ui);
+                return;
+            }
+            let n_raw = raw_names.len() as u32;
+            let mut derived_changed = false;
+
+            {
+                let ref mut editor = app.gui_data.derived_editor;
+                editor.neighbours.resize(n_raw as usize, false);
+                if editor.channel >= n_raw {editor.channel = 0;}
+                if editor.reference >= n_raw {editor.reference = 0;}
+                if editor.second_reference >= n_raw {editor.second_reference = 0;}
+
+                let kind_items: Vec<String> = DerivedChannel::KIND_NAMES.iter().map(|k| k.to_string()).collect();
+                for drop in widget::DropDownList::new(&kind_items, Some(editor.kind))
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down_from(ids.drop_down_panel, Y(2.0))
+                    .w_h(X(20.0),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.mt_kind, ui)
+                    {editor.kind = drop;}
+
+                for drop in widget::DropDownList::new(&raw_names, Some(editor.channel as usize))
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down(Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.mt_channel, ui)
+                    {editor.channel = drop as u32;}
+
+                if editor.kind == 1 || editor.kind == 2 || editor.kind == 3 {
+                    for drop in widget::DropDownList::new(&raw_names, Some(editor.reference as usize))
+                        .align_middle_x_of(ids.settings_canvas)
+                        .down(Y(0.5))
+                        .w_h(X(20.0),X(1.5))
+                        .label_font_size(12)
+                        .set(ids.mt_reference, ui)
+                        {editor.reference = drop as u32;}
+                }
+                if editor.kind == 2 {
+                    for drop in widget::DropDownList::new(&raw_names, Some(editor.second_reference as usize))
+                        .align_middle_x_of(ids.settings_canvas)
+                        .down(Y(0.5))
+                        .w_h(X(20.0),X(1.5))
+                        .label_font_size(12)
+                        .set(ids.mt_second_reference, ui)
+                        {editor.second_reference = drop as u32;}
+                }
+                if editor.kind == 4 {
+                    ids.mt_neighbours.resize(n_raw as usize, &mut ui.widget_id_generator());
+                    for c in 0..n_raw as usize {
+                        let on = editor.neighbours[c];
+                        let label = format!("Neighbour {}", raw_names[c]);
+                        for v in widget::Toggle::new(on)
+                            .label(&label)
+                            .label_font_size(12)
+                            .label_color(if on { conrod::color::WHITE } else { conrod::color::LIGHT_CHARCOAL })
+                            .align_middle_x_of(ids.settings_canvas)
+                            .down(Y(0.3))
+                            .w_h(X(20.0),X(1.2))
+                            .set(ids.mt_neighbours[c], ui)
+                            {editor.neighbours[c] = v;}
+                    }
+                }
+
+                for _press in widget::Button::new()
+                    .label("Add derived channel")
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down(Y(1.0))
+                    .w_h(X(20.0),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.mt_add, ui)
+                    {
+                        let channel = editor.build();
+                        if !derived.contains(&channel) {derived.push(channel); derived_changed = true;}
+                    }
+
+                for _press in widget::Button::new()
+                    .label("Add common average for every channel")
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down(Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.mt_add_car_all, ui)
+                    {
+                        for c in 0..n_raw {
+                            let channel = DerivedChannel::CommonAverage(c);
+                            if !derived.contains(&channel) {derived.push(channel); derived_changed = true;}
+                        }
+                    }
+            }
+
+            //existing derived channels, any drawer looking at a removed channel goes back to its primary raw channel
+            ids.mt_remove.resize(derived.len(), &mut ui.widget_id_generator());
+            let mut remove: Option<usize> = None;
+            for k in 0..derived.len() {
+                let label = format!("Remove {}", names[n_raw as usize + k]);
+                for _press in widget::Button::new()
+                    .label(&label)
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down(Y(if k == 0 {1.5} else {0.3}))
+                    .w_h(X(20.0),X(1.2))
+                    .label_font_size(11)
+                    .set(ids.mt_remove[k], ui)
+                    {remove = Some(k);}
+            }
+            if let Some(k) = remove {
+                let removed = n_raw + k as u32;
+                for wfd in &mut app.waveform_drawers {
+                    if wfd.settings.channel == removed {
+                        wfd.settings.channel = derived[k].primary();
+                        wfd.band_history.clear();
+                    } else if wfd.settings.channel > removed {
+                        wfd.settings.channel -= 1;
+                    }
+                }
+                derived.remove(k);
+                derived_changed = true;
+            }
+
+            if derived_changed {
+                let mut app_data = app.app_data.lock().unwrap();
+                if let Some(ref mut wave_data) = app_data.wave_data {
+                    wave_data.set_derived(derived);
+                }
+                return;
+            }
+
+            widget::Text::new("Drawer channels")
+                .font_size(14)
+                .align_middle_x_of(ids.settings_canvas)
+                .down(Y(2.0))
+                .set(ids.mt_drawers_heading, ui);
+            ids.mt_drawer_channel.resize(app.waveform_drawers.len(), &mut ui.widget_id_generator());
+            for (d, wfd) in app.waveform_drawers.iter_mut().enumerate() {
+                for drop in widget::DropDownList::new(&names, Some(wfd.settings.channel as usize))
+                    .align_middle_x_of(ids.settings_canvas)
+                    .down(Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .max_visible_items(8)
+                    .label_font_size(12)
+                    .set(ids.mt_drawer_channel[d], ui)
+                    {
+                        wfd.settings.channel = drop as u32;
+                        wfd.band_history.clear();
+                    }
+            }
+        }
+        GuiDisplay::Layout =>
+        {
+            let mut layout_changed = false;
+            let mode_items: Vec<String> = LayoutMode::ALL.iter().map(|m| m.name().to_string()).collect();
+            let mode_index = LayoutMode::ALL.iter().position(|m| *m == app.layout.mode);
+            for drop in widget::DropDownList::new(&mode_items, mode_index)
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .w_h(X(20.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.ly_mode, ui)
+                {app.layout.mode = LayoutMode::ALL[drop]; layout_changed = true;}
+
+            //one row per drawer in display order: show/hide toggle then move up and down buttons
+            let names = app.app_data.lock().unwrap().channel_names();
+            let n = app.layout.order.len();
+            ids.ly_visible.resize(n, &mut ui.widget_id_generator());
+            ids.ly_up.resize(n, &mut ui.widget_id_generator());
+            ids.ly_down.resize(n, &mut ui.widget_id_generator());
+            let mut move_up: Option<usize> = None;
+            let mut move_down: Option<usize> = None;
+            for position in 0..n {
+                let d = app.layout.order[position];
+                let shown = !app.layout.hidden[d];
+                let label = match app.waveform_drawers.get(d).and_then(|wfd| names.get(wfd.settings.channel as usize)) {
+                    Some(name) => name.clone(),
+                    None => format!("Drawer {}", d+1),
+                };
+                let toggle = widget::Toggle::new(shown)
+                    .label(&label)
+                    .label_font_size(12)
+                    .label_color(if shown { conrod::color::WHITE } else { conrod::color::LIGHT_CHARCOAL })
+                    .w_h(X(14.0),X(1.5));
+                let toggle = if position == 0 {toggle.down_from(ids.ly_mode, Y(2.0))} else {toggle.down_from(ids.ly_visible[position-1], Y(0.3))};
+                for v in toggle.align_left_of(ids.ly_mode).set(ids.ly_visible[position], ui)
+                    {app.layout.hidden[d] = !v; layout_changed = true;}
+                for _press in widget::Button::new()
+                    .label("Up")
+                    .label_font_size(12)
+                    .right_from(ids.ly_visible[position], X(0.5))
+                    .w_h(X(2.5),X(1.5))
+                    .set(ids.ly_up[position], ui)
+                    {move_up = Some(position);}
+                for _press in widget::Button::new()
+                    .label("Down")
+                    .label_font_size(12)
+                    .right_from(ids.ly_up[position], X(0.5))
+                    .w_h(X(2.5),X(1.5))
+                    .set(ids.ly_down[position], ui)
+                    {move_down = Some(position);}
+            }
+            if let Some(position) = move_up {app.layout.move_up(position); layout_changed = true;}
+            if let Some(position) = move_down {app.layout.move_down(position); layout_changed = true;}
+
+            if layout_changed {
+                app.layout.apply(&mut app.waveform_drawers, viewport.aspect());
+            }
+        }
+        GuiDisplay::Presets =>
+        {
+            let path = app.gui_data.preset_path.clone();
+            let mut save = false;
+            for event in widget::TextBox::new(&app.gui_data.preset_name)
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .w_h(X(20.0),X(1.5))
+                .font_size(12)
+                .set(ids.pr_name, ui)
+                {
+                    match event {
+                        widget::text_box::Event::Update(name) => app.gui_data.preset_name = name,
+                        widget::text_box::Event::Enter => save = true,
+                    }
+                }
+            for _press in widget::Button::new()
+                .label("Save preset")
+                .align_middle_x_of(ids.settings_canvas)
+                .down(Y(0.5))
+                .w_h(X(20.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.pr_save, ui)
+                {save = true;}
+
+            let name = app.gui_data.preset_name.trim().to_string();
+            if save && name.len() > 0 {
+                let preset = Preset::from_app(app);
+                app.gui_data.preset_file.presets.insert(name.clone(), preset);
+                match app.gui_data.preset_file.save(&path) {
+                    Ok(()) => println!("Saved preset {:?} to {:?}.", name, path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+
+            widget::Text::new(&format!("Presets in {}", path.display()))
+                .font_size(12)
+                .align_middle_x_of(ids.settings_canvas)
+                .down(Y(2.0))
+                .set(ids.pr_status, ui);
+
+            //one row per saved preset: load on the left, delete on the right
+            let names: Vec<String> = app.gui_data.preset_file.presets.keys().cloned().collect();
+            ids.pr_load.resize(names.len(), &mut ui.widget_id_generator());
+            ids.pr_delete.resize(names.len(), &mut ui.widget_id_generator());
+            let mut load: Option<String> = None;
+            let mut delete: Option<String> = None;
+            for (i, name) in names.iter().enumerate() {
+                for _press in widget::Button::new()
+                    .label(name)
+                    .label_font_size(12)
+                    .align_left_of(ids.pr_save)
+                    .down_from(if i == 0 {ids.pr_status} else {ids.pr_load[i-1]}, Y(0.5))
+                    .w_h(X(15.0),X(1.5))
+                    .set(ids.pr_load[i], ui)
+                    {load = Some(name.clone());}
+                for _press in widget::Button::new()
+                    .label("Delete")
+                    .label_font_size(12)
+                    .right_from(ids.pr_load[i], X(0.5))
+                    .w_h(X(4.5),X(1.5))
+                    .set(ids.pr_delete[i], ui)
+                    {delete = Some(name.clone());}
+            }
+            if let Some(name) = load {
+                let preset = app.gui_data.preset_file.presets[&name].clone();
+                app.apply_preset(&preset);
+                app.gui_data.preset_name = name;
+            }
+            if let Some(name) = delete {
+                app.gui_data.preset_file.presets.remove(&name);
+                if let Err(e) = app.gui_data.preset_file.save(&path) {println!("{}", e);}
+            }
+        }
+        GuiDisplay::Session =>
+        {
+            for event in widget::TextBox::new(&app.gui_data.session_path)
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .w_h(X(20.0),X(1.5))
+                .font_size(12)
+                .set(ids.ss_path, ui)
+                {
+                    match event {
+                        widget::text_box::Event::Update(path) => app.gui_data.session_path = path,
+                        widget::text_box::Event::Enter => (),
+                    }
+                }
+            let path = std::path::PathBuf::from(app.gui_data.session_path.trim());
+            for _press in widget::Button::new()
+                .label("Save session")
+                .align_left_of(ids.ss_path)
+                .down(Y(0.5))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ss_save, ui)
+                {
+                    app.gui_data.session_status = match Session::from_app(app).save(&path) {
+                        Ok(()) => format!("Saved session to {}", path.display()),
+                        Err(e) => e,
+                    };
+                }
+            for _press in widget::Button::new()
+                .label("Load session")
+                .right_from(ids.ss_save, X(0.5))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ss_load, ui)
+                {
+                    app.gui_data.session_status = match Session::load(&path).and_then(|session| session.restore(app, display)) {
+                        Ok(()) => format!("Loaded session from {}", path.display()),
+                        Err(e) => e,
+                    };
+                }
+            widget::Text::new(&app.gui_data.session_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.ss_save)
+                .down(Y(2.0))
+                .set(ids.ss_status, ui);
+        }
+        GuiDisplay::Record =>
+        {
+            let format_items: Vec<String> = RecordFormat::ALL.iter().map(|f| f.name().to_string()).collect();
+            let format_index = RecordFormat::ALL.iter().position(|f| *f == app.gui_data.record_format);
+            for selected in widget::DropDownList::new(&format_items, format_index)
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .w_h(X(20.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.rc_format, ui)
+                {
+                    let format = RecordFormat::ALL[selected];
+                    app.gui_data.record_format = format;
+                    let path = std::path::PathBuf::from(app.gui_data.record_path.trim()).with_extension(format.extension());
+                    app.gui_data.record_path = path.to_string_lossy().into_owned();
+                }
+            for event in widget::TextBox::new(&app.gui_data.record_path)
+                .align_middle_x_of(ids.settings_canvas)
+                .down(Y(0.5))
+                .w_h(X(20.0),X(1.5))
+                .font_size(12)
+                .set(ids.rc_path, ui)
+                {
+                    if let widget::text_box::Event::Update(path) = event {app.gui_data.record_path = path;}
+                }
+
+            let (live, recording) = {
+                let app_data = app.app_data.lock().unwrap();
+                match app_data.streaming_data {
+                    Some(ref sd) => (true, sd.recorder.as_ref().map(|r| (r.seconds(), r.path.clone()))),
+                    None => (false, None),
+                }
+            };
+            for _press in widget::Button::new()
+                .label(if recording.is_some() {"Stop recording"} else {"Record"})
+                .color(if recording.is_some() {conrod::color::LIGHT_RED} else {conrod::color::LIGHT_GREY})
+                .align_middle_x_of(ids.settings_canvas)
+                .down(Y(0.5))
+                .w_h(X(20.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.rc_toggle, ui)
+                {
+                    if !live {continue;}
+                    if recording.is_some() {
+                        let recorder = app.app_data.lock().unwrap().streaming_data.as_mut().and_then(|sd| sd.take_recorder());
+                        if let Some(mut recorder) = recorder {
+                            app.gui_data.record_status = match recorder.stop() {Ok(summary) => summary, Err(e) => e};
+                        }
+                    } else {
+                        let path = std::path::PathBuf::from(app.gui_data.record_path.trim());
+                        let mut app_data = app.app_data.lock().unwrap();
+                        let labels = app_data.channel_names();
+                        let result = app_data.streaming_data.as_mut().unwrap().start_recording(app.gui_data.record_format, &path, labels);
+                        if let Err(e) = result {app.gui_data.record_status = e;}
+                    }
+                }
+
+            let status = match recording {
+                _ if !live => String::from("Recording needs a live source."),
+                Some((seconds, ref path)) => format!("Recording {:.1} s to {}", seconds, path.display()),
+                None => app.gui_data.record_status.clone(),
+            };
+            widget::Text::new(&status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.rc_toggle)
+                .down(Y(1.0))
+                .set(ids.rc_status, ui);
+
+            //markers go in the recording and its events file at the latest captured sample
+            for event in widget::TextBox::new(&app.gui_data.marker_label)
+                .align_left_of(ids.rc_toggle)
+                .down(Y(2.0))
+                .w_h(X(14.5),X(1.5))
+                .font_size(12)
+                .set(ids.rc_marker_label, ui)
+                {
+                    if let widget::text_box::Event::Update(label) = event {app.gui_data.marker_label = label;}
+                }
+            for _press in widget::Button::new()
+                .label("Add marker")
+                .right_from(ids.rc_marker_label, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.rc_marker, ui)
+                {
+                    let label = app.gui_data.marker_label.trim().to_string();
+                    app.add_marker(&label);
+                }
+        }
+        GuiDisplay::Export =>
+        {
+            let (names, length_s) = {
+                let app_data = app.app_data.lock().unwrap();
+                let sample_rate = app_data.get_sample_rate().unwrap_or(1);
+                (app_data.channel_names(), app_data.buffer_length().unwrap_or(0) as f32/sample_rate as f32)
+            };
+            let mut channel_items = vec![String::from("All channels")];
+            channel_items.extend(names.iter().cloned());
+            let channel_index = match app.gui_data.export_channel {Some(c) if c < names.len() => c+1, _ => 0};
+            for selected in widget::DropDownList::new(&channel_items, Some(channel_index))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .w_h(X(20.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.ex_channel, ui)
+                {app.gui_data.export_channel = if selected == 0 {None} else {Some(selected-1)};}
+
+            //the time range, the whole file unless it has been narrowed down
+            let (start, end) = app.gui_data.export_range.unwrap_or((0.0, length_s));
+            let label = format!("{:.1} - {:.1} s", start, end);
+            for (edge, value) in widget::RangeSlider::new(start.min(length_s), end.min(length_s), 0.0, length_s.max(0.001))
+                .align_left_of(ids.ex_channel)
+                .down(Y(1.0))
+                .w_h(X(14.5),X(1.5))
+                .label(&label)
+                .label_font_size(12)
+                .set(ids.ex_range, ui)
+                {
+                    let (start, end) = app.gui_data.export_range.unwrap_or((0.0, length_s));
+                    app.gui_data.export_range = Some(match edge {
+                        widget::range_slider::Edge::Start => (value, end),
+                        widget::range_slider::Edge::End => (start, value),
+                    });
+                }
+            for _press in widget::Button::new()
+                .label("Whole file")
+                .right_from(ids.ex_range, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.ex_whole, ui)
+                {app.gui_data.export_range = None;}
+
+            for event in widget::TextBox::new(&app.gui_data.export_path)
+                .align_left_of(ids.ex_channel)
+                .down_from(ids.ex_range, Y(1.0))
+                .w_h(X(20.0),X(1.5))
+                .font_size(12)
+                .set(ids.ex_path, ui)
+                {
+                    if let widget::text_box::Event::Update(path) = event {app.gui_data.export_path = path;}
+                }
+
+            let mut export = None;
+            for _press in widget::Button::new()
+                .label("Export image")
+                .align_left_of(ids.ex_channel)
+                .down(Y(1.0))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ex_image, ui)
+                {export = Some(Export::Image);}
+            let format_items: Vec<String> = MatrixFormat::ALL.iter().map(|f| f.name().to_string()).collect();
+            let format_index = MatrixFormat::ALL.iter().position(|f| *f == app.gui_data.matrix_format);
+            for selected in widget::DropDownList::new(&format_items, format_index)
+                .right_from(ids.ex_image, X(0.5))
+                .w_h(X(3.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.ex_format, ui)
+                {app.gui_data.matrix_format = MatrixFormat::ALL[selected];}
+            for _press in widget::Button::new()
+                .label("Export matrix")
+                .right_from(ids.ex_format, X(0.5))
+                .w_h(X(6.25),X(1.5))
+                .label_font_size(12)
+                .set(ids.ex_matrix, ui)
+                {export = Some(Export::Matrix(app.gui_data.matrix_format));}
+
+            if let Some(export) = export {
+                let channels: Vec<usize> = match app.gui_data.export_channel {
+                    Some(c) => vec![c],
+                    None => (0..names.len()).collect(),
+                };
+                let base = app.gui_data.export_path.trim().to_string();
+                app.gui_data.export_status = match export::export_channels(app, &channels, app.gui_data.export_range, &base, export) {
+                    Ok(summary) => summary,
+                    Err(e) => e,
+                };
+            }
+            widget::Text::new(&app.gui_data.export_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.ex_channel)
+                .down_from(ids.ex_image, Y(1.0))
+                .set(ids.ex_status, ui);
+        }
+        GuiDisplay::Annotations =>
+        {
+            widget::Text::new("Press M to add a marker with this label, or 1-9 for numbered markers.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.an_help, ui);
+            for event in widget::TextBox::new(&app.gui_data.marker_label)
+                .align_left_of(ids.an_help)
+                .down(Y(1.0))
+                .w_h(X(14.5),X(1.5))
+                .font_size(12)
+                .set(ids.an_marker_label, ui)
+                {
+                    if let widget::text_box::Event::Update(label) = event {app.gui_data.marker_label = label;}
+                }
+            for _press in widget::Button::new()
+                .label("Add marker")
+                .right_from(ids.an_marker_label, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.an_marker, ui)
+                {
+                    let label = app.gui_data.marker_label.trim().to_string();
+                    app.add_marker(&label);
+                }
+
+            //import from an EDF+ file or a csv of time (s), label, or save the current ones as csv
+            for event in widget::TextBox::new(&app.gui_data.annotation_path)
+                .align_left_of(ids.an_help)
+                .down_from(ids.an_marker_label, Y(2.0))
+                .w_h(X(20.0),X(1.5))
+                .font_size(12)
+                .set(ids.an_path, ui)
+                {
+                    if let widget::text_box::Event::Update(path) = event {app.gui_data.annotation_path = path;}
+                }
+            let path = std::path::PathBuf::from(app.gui_data.annotation_path.trim());
+            for _press in widget::Button::new()
+                .label("Import")
+                .align_left_of(ids.an_help)
+                .down(Y(0.5))
+                .w_h(X(6.33),X(1.5))
+                .label_font_size(12)
+                .set(ids.an_import, ui)
+                {
+                    app.gui_data.annotation_status = match annotations::load(&path) {
+                        Ok(loaded) => {
+                            let n = loaded.len();
+                            for a in loaded {annotations::insert(&mut app.annotations, a);}
+                            format!("Imported {} annotations from {}", n, path.display())
+                        },
+                        Err(e) => e,
+                    };
+                }
+            for _press in widget::Button::new()
+                .label("Save")
+                .right_from(ids.an_import, X(0.5))
+                .w_h(X(6.33),X(1.5))
+                .label_font_size(12)
+                .set(ids.an_save, ui)
+                {
+                    app.gui_data.annotation_status = match annotations::save_csv(&path, &app.annotations) {
+                        Ok(()) => format!("Saved {} annotations to {}", app.annotations.len(), path.display()),
+                        Err(e) => e,
+                    };
+                }
+            for _press in widget::Button::new()
+                .label("Clear")
+                .right_from(ids.an_save, X(0.5))
+                .w_h(X(6.33),X(1.5))
+                .label_font_size(12)
+                .set(ids.an_clear, ui)
+                {app.annotations.clear();}
+            widget::Text::new(&app.gui_data.annotation_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.an_help)
+                .down_from(ids.an_import, Y(1.0))
+                .set(ids.an_status, ui);
+
+            //the most recent annotations, newest last
+            let recent: Vec<String> = app.annotations.iter().rev().take(20).collect::<Vec<_>>().iter().rev()
+                .map(|a| format!("{:9.3} s  {}", a.time_s, a.label)).collect();
+            widget::Text::new(&format!("{} annotations\n{}", app.annotations.len(), recent.join("\n")))
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.an_help)
+                .down_from(ids.an_status, Y(2.0))
+                .set(ids.an_list, ui);
+        }
+        GuiDisplay::Ersp =>
+        {
+            let mut labels: Vec<String> = app.annotations.iter().map(|a| a.label.clone()).collect();
+            labels.sort();
+            labels.dedup();
+            let names = app.app_data.lock().unwrap().channel_names();
+            {
+                let ref mut settings = app.gui_data.ersp_settings;
+                let label_index = settings.label.as_ref().and_then(|l| labels.iter().position(|x| x == l));
+                for selected in widget::DropDownList::new(&labels, label_index)
+                    .label("Marker")
+                    .align_left_of(ids.drop_down_panel)
+                    .down_from(ids.drop_down_panel, Y(2.0))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.er_marker, ui)
+                    {settings.label = Some(labels[selected].clone());}
+                for selected in widget::DropDownList::new(&names, if settings.channel < names.len() {Some(settings.channel)} else {None})
+                    .right_from(ids.er_marker, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.er_channel, ui)
+                    {settings.channel = selected;}
+
+                for value in widget::Slider::new(settings.pre_s, 0.1, 5.0)
+                    .label(&format!("{:.2} s before", settings.pre_s))
+                    .label_font_size(12)
+                    .align_left_of(ids.er_marker)
+                    .down(Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.er_pre, ui)
+                    {settings.pre_s = value;}
+                for value in widget::Slider::new(settings.post_s, 0.1, 10.0)
+                    .label(&format!("{:.2} s after", settings.post_s))
+                    .label_font_size(12)
+                    .right_from(ids.er_pre, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.er_post, ui)
+                    {settings.post_s = value;}
+                let baseline = (settings.baseline.0.max(-settings.pre_s), settings.baseline.1.min(settings.post_s));
+                for (edge, value) in widget::RangeSlider::new(baseline.0, baseline.1, -settings.pre_s, settings.post_s)
+                    .label(&format!("baseline {:.2} to {:.2} s", baseline.0, baseline.1))
+                    .label_font_size(12)
+                    .align_left_of(ids.er_marker)
+                    .down(Y(1.0))
+                    .w_h(X(20.0),X(1.5))
+                    .set(ids.er_baseline, ui)
+                    {
+                        match edge {
+                            widget::range_slider::Edge::Start => settings.baseline.0 = value,
+                            widget::range_slider::Edge::End => settings.baseline.1 = value,
+                        }
+                    }
+            }
+
+            //the epochs are transformed on a worker with copies of the channel and settings, the panel keeps drawing meanwhile
+            let computing = app.gui_data.ersp_worker.is_some();
+            for _press in widget::Button::new()
+                .label(if computing {"Computing..."} else {"Compute ERSP"})
+                .align_left_of(ids.er_marker)
+                .down(Y(1.0))
+                .w_h(X(20.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.er_compute, ui)
+                {
+                    if computing {continue;}
+                    let settings = app.gui_data.ersp_settings.clone();
+                    let events: Vec<annotations::Annotation> = app.annotations.iter().filter(|a| Some(&a.label) == settings.label.as_ref()).cloned().collect();
+                    let drawer = app.waveform_drawers.iter().map(|wfd| &wfd.settings).find(|s| s.channel as usize == settings.channel)
+                        .or(app.waveform_drawers.first().map(|wfd| &wfd.settings)).cloned();
+                    match drawer {
+                        _ if events.is_empty() => app.gui_data.ersp_status = String::from("Pick a marker type that has markers."),
+                        Some(drawer) => {
+                            let (samples, sample_rate, artifacts) = {
+                                let app_data = app.app_data.lock().unwrap();
+                                let artifacts = match app_data.wave_data {
+                                    Some(ref w) if app.filter_data.exclude_artifacts => w.artifacts_for(settings.channel),
+                                    _ => Vec::new(),
+                                };
+                                (app_data.samples(settings.channel).to_vec(), app_data.get_sample_rate().unwrap_or(1), artifacts)
+                            };
+                            let fd = app.filter_data.clone();
+                            app.gui_data.ersp_worker = Some(Worker::start(move || {
+                                let events: Vec<&annotations::Annotation> = events.iter().collect();
+                                Ersp::compute(&samples, sample_rate, &events, &settings, &drawer, &fd, &artifacts)
+                            }));
+                        },
+                        None => app.gui_data.ersp_status = String::from("Nothing is open."),
+                    }
+                }
+            let result = app.gui_data.ersp_worker.as_mut().and_then(|w| w.poll());
+            match result {
+                Some(Ok(ersp)) => {
+                    app.gui_data.ersp_status = format!("{} trials averaged, {} did not fit in the data or had artifacts", ersp.trials, ersp.rejected);
+                    if ersp.empty_rows > 0 {
+                        app.gui_data.ersp_status += &format!(", {} frequencies had no baseline power and are left at 0 dB", ersp.empty_rows);
+                    }
+                    app.gui_data.panel_images.set("ersp", ersp.image());
+                    app.gui_data.panel_images.set("ersp colorbar", panelimage::colorbar(128, panelimage::diverging, -1.0, 1.0));
+                    app.gui_data.ersp = Some(ersp);
+                    app.gui_data.ersp_worker = None;
+                },
+                Some(Err(e)) => {
+                    app.gui_data.ersp_status = e;
+                    app.gui_data.ersp_worker = None;
+                },
+                None => if let Some(ref worker) = app.gui_data.ersp_worker {
+                    app.gui_data.ersp_status = format!("Computing the ERSP, {:.0} s so far.", worker.seconds());
+                },
+            }
+            widget::Text::new(&app.gui_data.ersp_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.er_marker)
+                .down(Y(1.0))
+                .set(ids.er_status, ui);
+
+            //the map with its colorbar to the right, the colorbar runs from minus to plus the largest change
+            if let (Some(ref ersp), Some(map), Some(colorbar)) = (app.gui_data.ersp.as_ref(), app.gui_data.panel_images.id("ersp"), app.gui_data.panel_images.id("ersp colorbar")) {
+                widget::Image::new(map)
+                    .w_h(X(18.0),Y(30.0))
+                    .align_left_of(ids.er_marker)
+                    .down_from(ids.er_status, Y(2.0))
+                    .set(ids.er_map, ui);
+                widget::Image::new(colorbar)
+                    .w_h(X(0.5),Y(30.0))
+                    .right_from(ids.er_map, X(0.5))
+                    .set(ids.er_colorbar, ui);
+                let range = ersp.range_db();
+                widget::Text::new(&format!("+{:.1} dB", range))
+                    .font_size(11)
+                    .right_from(ids.er_colorbar, X(0.2))
+                    .align_top_of(ids.er_colorbar)
+                    .set(ids.er_db_high, ui);
+                widget::Text::new(&format!("-{:.1} dB", range))
+                    .font_size(11)
+                    .right_from(ids.er_colorbar, X(0.2))
+                    .align_bottom_of(ids.er_colorbar)
+                    .set(ids.er_db_low, ui);
+                widget::Text::new(&format!("{:.2} to {:.2} s from the marker, 0 to {:.1} Hz",
+                        ersp.times.first().cloned().unwrap_or(0.0), ersp.times.last().cloned().unwrap_or(0.0), ersp.freqs.last().cloned().unwrap_or(0.0)))
+                    .font_size(12)
+                    .align_left_of(ids.er_map)
+                    .down_from(ids.er_map, Y(1.0))
+                    .set(ids.er_axes, ui);
+            }
+        }
+        GuiDisplay::Transform =>
+        {
+            widget::Text::new("How each drawer turns its signal into columns. Changes apply from the next column.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.tf_help, ui);
+
+            //per drawer: its channel, the transform, then the sliders that transform has
+            let (names, sample_rate) = {
+                let app_data = app.app_data.lock().unwrap();
+                (app_data.channel_names(), app_data.get_sample_rate().unwrap_or(0))
+            };
+            let kind_items: Vec<String> = Transform::ALL.iter().map(|t| t.name().to_string()).collect();
+            let n = app.waveform_drawers.len();
+            ids.tf_label.resize(n, &mut ui.widget_id_generator());
+            ids.tf_kind.resize(n, &mut ui.widget_id_generator());
+            ids.tf_first.resize(n, &mut ui.widget_id_generator());
+            ids.tf_second.resize(n, &mut ui.widget_id_generator());
+            ids.tf_window.resize(n, &mut ui.widget_id_generator());
+            ids.tf_fft.resize(n, &mut ui.widget_id_generator());
+            ids.tf_pow2.resize(n, &mut ui.widget_id_generator());
+            let mut last = ids.tf_help;
+            for (d, wfd) in app.waveform_drawers.iter_mut().enumerate() {
+                let label = match names.get(wfd.settings.channel as usize) {
+                    Some(name) => name.clone(),
+                    None => format!("Drawer {}", d+1),
+                };
+                widget::Text::new(&label)
+                    .font_size(12)
+                    .align_left_of(ids.tf_help)
+                    .down_from(last, Y(if d == 0 {2.0} else {1.5}))
+                    .set(ids.tf_label[d], ui);
+
+                let kind_index = Transform::ALL.iter().position(|t| *t == wfd.settings.transform);
+                for drop in widget::DropDownList::new(&kind_items, kind_index)
+                    .align_left_of(ids.tf_help)
+                    .down_from(ids.tf_label[d], Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.tf_kind[d], ui)
+                    {wfd.settings.transform = Transform::ALL[drop];}
+                last = ids.tf_kind[d];
+
+                match wfd.settings.transform {
+                    Transform::Stft | Transform::Reassigned | Transform::Synchrosqueezed => {},
+                    Transform::Morlet => {
+                        for v in widget::Slider::new(wfd.settings.morlet_cycles, 3.0, 15.0)
+                            .align_left_of(ids.tf_help)
+                            .down_from(last, Y(0.5))
+                            .w_h(X(20.0),X(1.5))
+                            .label(&format!("{:.1} cycles per wavelet", wfd.settings.morlet_cycles))
+                            .label_font_size(12)
+                            .set(ids.tf_first[d], ui)
+                            {wfd.settings.morlet_cycles = (v*2.0).round()/2.0;}
+                        last = ids.tf_first[d];
+                    },
+                    Transform::Multitaper => {
+                        let bin_hz = wfd.settings.bin_hz(sample_rate);
+                        for v in widget::Slider::new(wfd.settings.multitaper_nw, 1.5, 8.0)
+                            .align_left_of(ids.tf_help)
+                            .down_from(last, Y(0.5))
+                            .w_h(X(20.0),X(1.5))
+                            .label(&format!("NW {:.1}, resolution {:.2} Hz", wfd.settings.multitaper_nw, 2.0*wfd.settings.multitaper_nw*bin_hz))
+                            .label_font_size(12)
+                            .set(ids.tf_first[d], ui)
+                            {
+                                wfd.settings.multitaper_nw = (v*2.0).round()/2.0;
+                                wfd.settings.multitaper_tapers = std::cmp::min(wfd.settings.multitaper_tapers, Tapers::max_tapers(wfd.settings.multitaper_nw));
+                            }
+                        let max_tapers = Tapers::max_tapers(wfd.settings.multitaper_nw);
+                        for v in widget::Slider::new(wfd.settings.multitaper_tapers as f32, 1.0, max_tapers as f32)
+                            .align_left_of(ids.tf_help)
+                            .down_from(ids.tf_first[d], Y(0.5))
+                            .w_h(X(20.0),X(1.5))
+                            .label(&format!("{} of at most {} tapers", wfd.settings.multitaper_tapers, max_tapers))
+                            .label_font_size(12)
+                            .set(ids.tf_second[d], ui)
+                            {wfd.settings.multitaper_tapers = v.round() as u32;}
+                        last = ids.tf_second[d];
+                    },
+                }
+
+                //window length and fft size, the rows shown are rescaled so the top frequency stays put
+                let mut sizes = wfd.settings.clone();
+                let max_window = std::cmp::max(64, std::cmp::min(16384, sample_rate*8));
+                for v in widget::Slider::new(sizes.dtft_samples as f32, 16.0, max_window as f32)
+                    .align_left_of(ids.tf_help)
+                    .down_from(last, Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label(&format!("Window {} samples, {:.2} s", sizes.dtft_samples, sizes.dtft_samples as f32/sample_rate.max(1) as f32))
+                    .label_font_size(12)
+                    .set(ids.tf_window[d], ui)
+                    {sizes.dtft_samples = v.round() as u32;}
+                let fft_len = sizes.fft_len();
+                for v in widget::Slider::new(fft_len as f32, sizes.dtft_samples as f32, (sizes.dtft_samples*8) as f32)
+                    .align_left_of(ids.tf_help)
+                    .down_from(ids.tf_window[d], Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label(&format!("FFT {} ({:.2}x padding), {:.3} Hz bins", fft_len, fft_len as f32/sizes.dtft_samples as f32, sizes.bin_hz(sample_rate)))
+                    .label_font_size(12)
+                    .set(ids.tf_fft[d], ui)
+                    {sizes.fft_samples = v.round() as u32;}
+                for v in widget::Toggle::new(sizes.fft_pow2)
+                    .label("Round the FFT up to a power of two")
+                    .label_font_size(12)
+                    .label_color(if sizes.fft_pow2 { conrod::color::WHITE } else { conrod::color::LIGHT_CHARCOAL })
+                    .align_left_of(ids.tf_help)
+                    .down_from(ids.tf_fft[d], Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .set(ids.tf_pow2[d], ui)
+                    {sizes.fft_pow2 = v;}
+                last = ids.tf_pow2[d];
+
+                if sizes.dtft_samples != wfd.settings.dtft_samples || sizes.fft_samples != wfd.settings.fft_samples || sizes.fft_pow2 != wfd.settings.fft_pow2 {
+                    let rows = wfd.settings.dtft_display_samples as f32*sizes.fft_len() as f32/wfd.settings.fft_len() as f32;
+                    //the rows stay on the same frequencies, and never go past nyquist
+                    sizes.dtft_display_samples = std::cmp::max(1, std::cmp::min(rows.round() as u32, sizes.fft_len()/2));
+                    wfd.set_settings(sizes);
+                }
+            }
+        }
+        GuiDisplay::Peaks =>
+        {
+            widget::Text::new("Tracks the loudest spectral peaks in each column and draws them over the spectrogram.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.pk_help, ui);
+
+            //per drawer: its channel with the current loudest peak, how many peaks to track and how far they can jump
+            let names = app.app_data.lock().unwrap().channel_names();
+            let n = app.waveform_drawers.len();
+            ids.pk_label.resize(n, &mut ui.widget_id_generator());
+            ids.pk_count.resize(n, &mut ui.widget_id_generator());
+            ids.pk_jump.resize(n, &mut ui.widget_id_generator());
+            for (d, wfd) in app.waveform_drawers.iter_mut().enumerate() {
+                let name = match names.get(wfd.settings.channel as usize) {
+                    Some(name) => name.clone(),
+                    None => format!("Drawer {}", d+1),
+                };
+                //the loudest peak among the tracks still running
+                let loudest = wfd.peak_tracker.tracks.iter()
+                    .filter(|t| t.active())
+                    .filter_map(|t| t.points.last())
+                    .fold(None, |best: Option<peaks::TrackPoint>, p| match best {
+                        Some(b) if b.magnitude >= p.magnitude => Some(b),
+                        _ => Some(*p),
+                    });
+                let label = match loudest {
+                    Some(p) if wfd.settings.peaks > 0 => format!("{}: {:.2} Hz", name, p.freq_hz),
+                    _ => name,
+                };
+                widget::Text::new(&label)
+                    .font_size(12)
+                    .align_left_of(ids.pk_help)
+                    .down_from(if d == 0 {ids.pk_help} else {ids.pk_jump[d-1]}, Y(if d == 0 {2.0} else {1.5}))
+                    .set(ids.pk_label[d], ui);
+                for v in widget::Slider::new(wfd.settings.peaks as f32, 0.0, 5.0)
+                    .align_left_of(ids.pk_help)
+                    .down_from(ids.pk_label[d], Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label(&if wfd.settings.peaks == 0 {String::from("Peak tracking off")} else {format!("{} peaks per column", wfd.settings.peaks)})
+                    .label_font_size(12)
+                    .set(ids.pk_count[d], ui)
+                    {
+                        let count = v.round() as u32;
+                        if count != wfd.settings.peaks {wfd.settings.peaks = count; wfd.peak_tracker.clear();}
+                    }
+                for v in widget::Slider::new(wfd.settings.peak_jump_hz, 0.1, 20.0)
+                    .align_left_of(ids.pk_help)
+                    .down_from(ids.pk_count[d], Y(0.5))
+                    .w_h(X(20.0),X(1.5))
+                    .label(&format!("Tracks move at most {:.1} Hz per column", wfd.settings.peak_jump_hz))
+                    .label_font_size(12)
+                    .set(ids.pk_jump[d], ui)
+                    {wfd.settings.peak_jump_hz = v;}
+            }
+
+            //every drawer's tracks go in one csv
+            let path_widget = widget::TextBox::new(&app.gui_data.peaks_path)
+                .align_left_of(ids.pk_help)
+                .w_h(X(14.5),X(1.5))
+                .font_size(12);
+            let path_widget = if n == 0 {path_widget.down_from(ids.pk_help, Y(2.0))} else {path_widget.down_from(ids.pk_jump[n-1], Y(2.0))};
+            for event in path_widget.set(ids.pk_path, ui)
+                {
+                    if let widget::text_box::Event::Update(path) = event {app.gui_data.peaks_path = path;}
+                }
+            for _press in widget::Button::new()
+                .label("Export")
+                .right_from(ids.pk_path, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.pk_export, ui)
+                {
+                    let path = std::path::PathBuf::from(app.gui_data.peaks_path.trim());
+                    let trackers: Vec<(String, &peaks::PeakTracker)> = app.waveform_drawers.iter().enumerate()
+                        .filter(|&(_, wfd)| wfd.settings.peaks > 0)
+                        .map(|(d, wfd)| (names.get(wfd.settings.channel as usize).cloned().unwrap_or(format!("Drawer {}", d+1)), &wfd.peak_tracker))
+                        .collect();
+                    app.gui_data.peaks_status = if trackers.is_empty() {
+                        String::from("No drawer is tracking peaks.")
+                    } else {
+                        match peaks::save_csv(&path, &trackers) {
+                            Ok(count) => format!("Wrote {} tracks to {}", count, path.display()),
+                            Err(e) => e,
+                        }
+                    };
+                }
+            widget::Text::new(&app.gui_data.peaks_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.pk_help)
+                .down_from(ids.pk_path, Y(1.0))
+                .set(ids.pk_status, ui);
+        }
+        GuiDisplay::Connectivity =>
+        {
+            let (names, sample_rate) = {
+                let app_data = app.app_data.lock().unwrap();
+                (app_data.channel_names(), app_data.get_sample_rate().unwrap_or(0))
+            };
+            let measure_items: Vec<String> = Measure::ALL.iter().map(|m| m.name().to_string()).collect();
+            {
+                let ref mut settings = app.gui_data.connectivity_settings;
+                let measure_index = Measure::ALL.iter().position(|m| *m == settings.measure);
+                for selected in widget::DropDownList::new(&measure_items, measure_index)
+                    .align_left_of(ids.drop_down_panel)
+                    .down_from(ids.drop_down_panel, Y(2.0))
+                    .w_h(X(20.0),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.co_measure, ui)
+                    {settings.measure = Measure::ALL[selected];}
+                for selected in widget::DropDownList::new(&names, if settings.pair.0 < names.len() {Some(settings.pair.0)} else {None})
+                    .align_left_of(ids.co_measure)
+                    .down(Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.co_first, ui)
+                    {settings.pair.0 = selected;}
+                for selected in widget::DropDownList::new(&names, if settings.pair.1 < names.len() {Some(settings.pair.1)} else {None})
+                    .right_from(ids.co_first, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.co_second, ui)
+                    {settings.pair.1 = selected;}
+                for value in widget::Slider::new(settings.span_s, 1.0, 60.0)
+                    .label(&format!("Last {:.1} s", settings.span_s))
+                    .label_font_size(12)
+                    .align_left_of(ids.co_measure)
+                    .down(Y(1.0))
+                    .w_h(X(20.0),X(1.5))
+                    .set(ids.co_span, ui)
+                    {settings.span_s = value;}
+                let nyquist = (sample_rate as f32/2.0).max(1.0);
+                let band = (settings.band.0.min(nyquist), settings.band.1.min(nyquist));
+                for (edge, value) in widget::RangeSlider::new(band.0, band.1, 0.0, nyquist)
+                    .label(&format!("Matrix band {:.1} to {:.1} Hz", band.0, band.1))
+                    .label_font_size(12)
+                    .align_left_of(ids.co_measure)
+                    .down(Y(1.0))
+                    .w_h(X(20.0),X(1.5))
+                    .set(ids.co_band, ui)
+                    {
+                        match edge {
+                            widget::range_slider::Edge::Start => settings.band.0 = value,
+                            widget::range_slider::Edge::End => settings.band.1 = value,
+                        }
+                    }
+                for live in widget::Toggle::new(settings.live)
+                    .label("Live")
+                    .label_font_size(12)
+                    .align_left_of(ids.co_measure)
+                    .down(Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.co_live, ui)
+                    {settings.live = live;}
+            }
+
+            //the data up to where the first drawer is, live results are redone about once a second
+            //only the span that goes in is copied out, the transforms are done on a worker so the lock is held just for the copy
+            let ticks = app.ticker.ticks();
+            let computing = app.gui_data.connectivity_worker.is_some();
+            let mut recompute = app.gui_data.connectivity_settings.live && !computing &&
+                app.gui_data.connectivity.as_ref().map(|c| ticks >= c.computed_ms + 1000).unwrap_or(true);
+            for _press in widget::Button::new()
+                .label(if computing {"Computing..."} else {"Compute"})
+                .right_from(ids.co_live, X(0.5))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.co_compute, ui)
+                {recompute = !computing;}
+            if recompute {
+                match app.waveform_drawers.first() {
+                    Some(wfd) => {
+                        let end_ms = wfd.position_ms(ticks);
+                        let (channels, first_sample, artifacts) = {
+                            let app_data = app.app_data.lock().unwrap();
+                            let end = (end_ms*sample_rate as u64/1000) as usize;
+                            let first_sample = end.saturating_sub(connectivity::span_samples(&app.gui_data.connectivity_settings, sample_rate));
+                            let channels: Vec<Vec<f32>> = (0..names.len()).map(|c| {
+                                let samples = app_data.samples(c);
+                                samples[std::cmp::min(first_sample, samples.len())..std::cmp::min(end, samples.len())].to_vec()
+                            }).collect();
+                            let artifacts = match app_data.wave_data {
+                                Some(ref w) if app.filter_data.exclude_artifacts => w.artifacts.clone(),
+                                _ => Vec::new(),
+                            };
+                            (channels, first_sample, artifacts)
+                        };
+                        let (settings, drawer, fd) = (app.gui_data.connectivity_settings.clone(), wfd.settings.clone(), app.filter_data.clone());
+                        app.gui_data.connectivity_worker = Some(Worker::start(move || {
+                            let channels: Vec<&[f32]> = channels.iter().map(|c| c.as_slice()).collect();
+                            let mut connectivity = Connectivity::compute(&channels, first_sample, sample_rate, end_ms, &settings, &drawer, &fd, &artifacts)?;
+                            connectivity.computed_ms = ticks;
+                            Ok(connectivity)
+                        }));
+                    },
+                    None => app.gui_data.connectivity_status = String::from("Nothing is open."),
+                }
+            }
+            let result = app.gui_data.connectivity_worker.as_mut().and_then(|w| w.poll());
+            if result.is_some() {app.gui_data.connectivity_worker = None;}
+            if let Some(result) = result {
+                match result {
+                    Ok(connectivity) => {
+                        app.gui_data.connectivity_status = format!("{} windows up to {:.1} s, {} left out for artifacts", connectivity.frames, connectivity.end_s, connectivity.rejected);
+                        app.gui_data.panel_images.set("connectivity", connectivity.matrix_image());
+                        app.gui_data.panel_images.set("connectivity colorbar", connectivity.colorbar());
+                        app.gui_data.connectivity = Some(connectivity);
+                    },
+                    Err(e) => {
+                        app.gui_data.connectivity_status = e;
+                        //stop a live update from retrying every frame
+                        if let Some(ref mut connectivity) = app.gui_data.connectivity {connectivity.computed_ms = ticks;}
+                    },
+                }
+            }
+            widget::Text::new(&app.gui_data.connectivity_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.co_measure)
+                .down_from(ids.co_live, Y(1.0))
+                .set(ids.co_status, ui);
+
+            if let Some(ref connectivity) = app.gui_data.connectivity {
+                //the pair against frequency, 0 to 1 up the plot or -1 to 1 when the measure is signed
+                let (w, h) = (X(20.0), Y(15.0));
+                widget::Rectangle::outline([w, h])
+                    .align_left_of(ids.co_measure)
+                    .down_from(ids.co_status, Y(2.0))
+                    .color(color::GREY)
+                    .set(ids.co_plot, ui);
+                let (cx, cy) = ui.xy_of(ids.co_plot).map(|p| (p[0], p[1])).unwrap_or((0.0, 0.0));
+                let (left, bottom) = (cx - w/2.0, cy - h/2.0);
+                let top_hz = connectivity.freqs.last().cloned().unwrap_or(0.0).max(1e-6);
+                let to_x = |hz: f32| left + w*(hz/top_hz).max(0.0).min(1.0) as f64;
+                let to_y = |v: f32| bottom + h*(if connectivity.measure.signed() {(v + 1.0)/2.0} else {v}).max(0.0).min(1.0) as f64;
+                widget::Line::abs([to_x(connectivity.band.0), bottom], [to_x(connectivity.band.0), bottom + h])
+                    .color(color::DARK_GREY)
+                    .set(ids.co_band_low, ui);
+                widget::Line::abs([to_x(connectivity.band.1), bottom], [to_x(connectivity.band.1), bottom + h])
+                    .color(color::DARK_GREY)
+                    .set(ids.co_band_high, ui);
+                let points: Vec<[f64; 2]> = connectivity.freqs.iter().zip(connectivity.spectrum.iter()).map(|(f, v)| [to_x(*f), to_y(*v)]).collect();
+                if points.len() > 1 {
+                    widget::PointPath::abs(points)
+                        .color(band_colour(0))
+                        .set(ids.co_trace, ui);
+                }
+                let pair = &app.gui_data.connectivity_settings.pair;
+                widget::Text::new(&format!("{} against {}, 0 to {:.1} Hz, {} up the side",
+                        names.get(pair.0).map(|n| n.as_str()).unwrap_or(""), names.get(pair.1).map(|n| n.as_str()).unwrap_or(""), top_hz,
+                        if connectivity.measure.signed() {"-1 to 1"} else {"0 to 1"}))
+                    .font_size(12)
+                    .w(X(20.0))
+                    .align_left_of(ids.co_plot)
+                    .down_from(ids.co_plot, Y(0.5))
+                    .set(ids.co_plot_axes, ui);
+
+                //every pair over the band, channel i against channel j in row i column j
+                if let (Some(matrix), Some(colorbar)) = (app.gui_data.panel_images.id("connectivity"), app.gui_data.panel_images.id("connectivity colorbar")) {
+                    widget::Image::new(matrix)
+                        .w_h(X(15.0),X(15.0))
+                        .align_left_of(ids.co_plot)
+                        .down_from(ids.co_plot_axes, Y(2.0))
+                        .set(ids.co_matrix, ui);
+                    widget::Image::new(colorbar)
+                        .w_h(X(0.5),X(15.0))
+                        .right_from(ids.co_matrix, X(0.5))
+                        .set(ids.co_colorbar, ui);
+                    widget::Text::new("1")
+                        .font_size(11)
+                        .right_from(ids.co_colorbar, X(0.2))
+                        .align_top_of(ids.co_colorbar)
+                        .set(ids.co_high, ui);
+                    widget::Text::new(if connectivity.measure.signed() {"-1"} else {"0"})
+                        .font_size(11)
+                        .right_from(ids.co_colorbar, X(0.2))
+                        .align_bottom_of(ids.co_colorbar)
+                        .set(ids.co_low, ui);
+                    widget::Text::new(&format!("{:.1} to {:.1} Hz, {} top to bottom and left to right",
+                            connectivity.band.0, connectivity.band.1, names.join(", ")))
+                        .font_size(12)
+                        .w(X(20.0))
+                        .align_left_of(ids.co_matrix)
+                        .down_from(ids.co_matrix, Y(1.0))
+                        .set(ids.co_matrix_axes, ui);
+                }
+            }
+        }
+        GuiDisplay::ScalpMap =>
+        {
+            //everything that can be mapped: each band's power and share, each ratio, then the peak frequency
+            let ref fd = app.filter_data;
+            let mut scalars = Vec::<(Scalar, String)>::new();
+            for (b, band) in fd.bands.iter().enumerate() {scalars.push((Scalar::Power(b), format!("{} power (dB)", band.name)));}
+            for (b, band) in fd.bands.iter().enumerate() {scalars.push((Scalar::Relative(b), format!("{} relative power", band.name)));}
+            for (r, ratio) in fd.band_ratios.iter().enumerate() {scalars.push((Scalar::Ratio(r), ratio.name(&fd.bands)));}
+            scalars.push((Scalar::PeakFrequency, String::from("Loudest peak (Hz)")));
+            let scalar_items: Vec<String> = scalars.iter().map(|s| s.1.clone()).collect();
+            let interpolation_items: Vec<String> = Interpolation::ALL.iter().map(|i| i.name().to_string()).collect();
+            {
+                let ref mut settings = app.gui_data.topo_settings;
+                let interpolation_index = Interpolation::ALL.iter().position(|i| *i == settings.interpolation);
+                for selected in widget::DropDownList::new(&interpolation_items, interpolation_index)
+                    .align_left_of(ids.drop_down_panel)
+                    .down_from(ids.drop_down_panel, Y(2.0))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.tp_interpolation, ui)
+                    {settings.interpolation = Interpolation::ALL[selected];}
+                for selected in widget::DropDownList::new(&scalar_items, scalars.iter().position(|s| s.0 == settings.scalar))
+                    .right_from(ids.tp_interpolation, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.tp_scalar, ui)
+                    {settings.scalar = scalars[selected].0;}
+            }
+
+            //one electrode per positioned channel that a drawer is showing, with the drawer's latest frame
+            let info = {
+                let app_data = app.app_data.lock().unwrap();
+                app_data.wave_data.as_ref().map(|w| w.info.clone()).unwrap_or(Vec::new())
+            };
+            let mut positions = Vec::<(f32, f32)>::new();
+            let mut shown = Vec::<(String, f32)>::new();
+            for wfd in &app.waveform_drawers {
+                let channel = wfd.settings.channel as usize;
+                let xy = match info.get(channel).and_then(|ci| ci.xy()) {Some(xy) => xy, None => continue};
+                if positions.contains(&xy) {continue;}
+                let powers = wfd.band_history.latest();
+                let value = match (app.gui_data.topo_settings.scalar, powers) {
+                    (Scalar::Power(b), Some(p)) if b < p.len() => Some(10.0*p[b].max(1e-30).log10()),
+                    (Scalar::Relative(b), Some(p)) if b < p.len() => {
+                        let total: f32 = p.iter().sum();
+                        Some(if total > 0.0 {p[b]/total} else {0.0})
+                    },
+                    (Scalar::Ratio(r), Some(p)) if r < fd.band_ratios.len() => Some(fd.band_ratios[r].value(p)),
+                    (Scalar::PeakFrequency, _) => wfd.peak_tracker.tracks.iter()
+                        .filter(|t| t.active())
+                        .filter_map(|t| t.points.last())
+                        .fold(None, |best: Option<peaks::TrackPoint>, p| match best {
+                            Some(b) if b.magnitude >= p.magnitude => Some(b),
+                            _ => Some(*p),
+                        })
+                        .map(|p| p.freq_hz),
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    positions.push(xy);
+                    shown.push((info[channel].label.clone(), value));
+                }
+            }
+
+            let range = shown.iter().fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |r, s| (r.0.min(s.1), r.1.max(s.1)));
+            let status = if shown.is_empty() {
+                if info.iter().any(|ci| ci.xy().is_some()) {String::from("Show channels with 10-20 positions in the drawers to map them. The loudest peak needs peak tracking on.")}
+                else {String::from("No channel has a 10-20 position. Name them in a montage file.")}
+            } else {
+                format!("{} electrodes, {:.3} to {:.3}", shown.len(), range.0, range.1)
+            };
+            widget::Text::new(&status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.tp_interpolation)
+                .down(Y(1.0))
+                .set(ids.tp_status, ui);
+            if shown.is_empty() {return;}
+
+            //the weights only change with the electrodes, the image whenever a drawer adds a frame
+            let interpolation = app.gui_data.topo_settings.interpolation;
+            if !app.gui_data.topo_map.as_ref().map(|m| m.matches(&positions, interpolation)).unwrap_or(false) {
+                app.gui_data.topo_map = Some(TopoMap::new(&positions, interpolation));
+                app.gui_data.topo_shown.clear();
+            }
+            if shown != app.gui_data.topo_shown {
+                let values: Vec<f32> = shown.iter().map(|s| s.1).collect();
+                if let Some(ref map) = app.gui_data.topo_map {
+                    app.gui_data.panel_images.set("scalp map", map.image(&values, range));
+                    app.gui_data.panel_images.set("scalp map colorbar", panelimage::colorbar(128, panelimage::sequential, 0.0, 1.0));
+                }
+                app.gui_data.topo_shown = shown;
+            }
+
+            if let (Some(map), Some(colorbar)) = (app.gui_data.panel_images.id("scalp map"), app.gui_data.panel_images.id("scalp map colorbar")) {
+                let size = X(18.0);
+                widget::Image::new(map)
+                    .w_h(size, size)
+                    .align_left_of(ids.tp_interpolation)
+                    .down_from(ids.tp_status, Y(2.0))
+                    .set(ids.tp_map, ui);
+                widget::Image::new(colorbar)
+                    .w_h(X(0.5), size)
+                    .right_from(ids.tp_map, X(0.5))
+                    .set(ids.tp_colorbar, ui);
+                widget::Text::new(&format!("{:.2}", range.1))
+                    .font_size(11)
+                    .right_from(ids.tp_colorbar, X(0.2))
+                    .align_top_of(ids.tp_colorbar)
+                    .set(ids.tp_high, ui);
+                widget::Text::new(&format!("{:.2}", range.0))
+                    .font_size(11)
+                    .right_from(ids.tp_colorbar, X(0.2))
+                    .align_bottom_of(ids.tp_colorbar)
+                    .set(ids.tp_low, ui);
+                //electrode names just above their dots, nose at the top
+                let (cx, cy) = ui.xy_of(ids.tp_map).map(|p| (p[0], p[1])).unwrap_or((0.0, 0.0));
+                let scale = size/2.0/topomap::EXTENT as f64;
+                ids.tp_labels.resize(positions.len(), &mut ui.widget_id_generator());
+                for (i, (xy, electrode)) in positions.iter().zip(app.gui_data.topo_shown.iter()).enumerate() {
+                    widget::Text::new(&electrode.0)
+                        .font_size(10)
+                        .color(color::WHITE)
+                        .x_y(cx + xy.0 as f64*scale, cy + xy.1 as f64*scale + 10.0)
+                        .set(ids.tp_labels[i], ui);
+                }
+            }
+        }
+        GuiDisplay::Artifacts =>
+        {
+            widget::Text::new("Flags bad stretches of a file. They are shaded over the spectrograms and can be left out of band powers, the ERSP and connectivity. Live data is not checked.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.ar_help, ui);
+
+            //each detector with its threshold beside it
+            let n = ArtifactKind::ALL.len();
+            ids.ar_kind.resize(n, &mut ui.widget_id_generator());
+            ids.ar_threshold.resize(n, &mut ui.widget_id_generator());
+            {
+                let ref mut settings = app.gui_data.artifact_settings;
+                for (k, kind) in ArtifactKind::ALL.iter().enumerate() {
+                    let c = kind.colour();
+                    for on in widget::Toggle::new(settings.enabled[k])
+                        .label(kind.name())
+                        .label_font_size(12)
+                        .color(conrod::color::rgb(c.0*0.6, c.1*0.6, c.2*0.6))
+                        .align_left_of(ids.ar_help)
+                        .down_from(if k == 0 {ids.ar_help} else {ids.ar_kind[k-1]}, Y(if k == 0 {2.0} else {1.0}))
+                        .w_h(X(9.75),X(1.5))
+                        .set(ids.ar_kind[k], ui)
+                        {settings.enabled[k] = on;}
+                    let (value, low, high, label) = match *kind {
+                        ArtifactKind::Clipping => (settings.clip_run as f32, 2.0, 20.0, format!("{} samples on the rail", settings.clip_run)),
+                        ArtifactKind::Flatline => (settings.flat_uv, 0.1, 10.0, format!("Under {:.1} µV peak to peak", settings.flat_uv)),
+                        ArtifactKind::LineNoise => (settings.line_fraction, 0.05, 0.9, format!("Over {:.0}% at mains", settings.line_fraction*100.0)),
+                        ArtifactKind::Blink => (settings.blink_uv, 20.0, 500.0, format!("Over {:.0} µV frontal", settings.blink_uv)),
+                        ArtifactKind::Motion => (settings.motion_g, 0.01, 1.0, format!("Over {:.2} g", settings.motion_g)),
+                    };
+                    for v in widget::Slider::new(value, low, high)
+                        .label(&label)
+                        .label_font_size(12)
+                        .right_from(ids.ar_kind[k], X(0.5))
+                        .w_h(X(9.75),X(1.5))
+                        .set(ids.ar_threshold[k], ui)
+                        {
+                            match *kind {
+                                ArtifactKind::Clipping => settings.clip_run = v.round() as u32,
+                                ArtifactKind::Flatline => settings.flat_uv = v,
+                                ArtifactKind::LineNoise => settings.line_fraction = v,
+                                ArtifactKind::Blink => settings.blink_uv = v,
+                                ArtifactKind::Motion => settings.motion_g = v,
+                            }
+                        }
+                }
+                for v in widget::Slider::new(settings.flat_s, 0.1, 5.0)
+                    .label(&format!("Flat for at least {:.1} s", settings.flat_s))
+                    .label_font_size(12)
+                    .align_left_of(ids.ar_help)
+                    .down_from(ids.ar_kind[n-1], Y(1.0))
+                    .w_h(X(20.0),X(1.5))
+                    .set(ids.ar_flat_s, ui)
+                    {settings.flat_s = v;}
+            }
+
+            for exclude in widget::Toggle::new(app.filter_data.exclude_artifacts)
+                .label("Leave out flagged spans")
+                .label_font_size(12)
+                .align_left_of(ids.ar_help)
+                .down_from(ids.ar_flat_s, Y(1.0))
+                .w_h(X(14.5),X(1.5))
+                .set(ids.ar_exclude, ui)
+                {app.filter_data.exclude_artifacts = exclude;}
+            for _press in widget::Button::new()
+                .label("Detect")
+                .right_from(ids.ar_exclude, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.ar_detect, ui)
+                {
+                    let mut app_data = app.app_data.lock().unwrap();
+                    if let Some(ref mut wave_data) = app_data.wave_data {
+                        wave_data.detect_artifacts(&app.gui_data.artifact_settings, app.filter_data.filters.mains_hz);
+                    }
+                }
+            let status = match app.app_data.lock().unwrap().wave_data {
+                Some(ref wave_data) => artifacts::summary(&wave_data.artifacts),
+                None => String::from("Artifact detection needs a file to be open, live data is not checked."),
+            };
+            widget::Text::new(&status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.ar_help)
+                .down_from(ids.ar_exclude, Y(1.0))
+                .set(ids.ar_status, ui);
+        }
+        GuiDisplay::Ica =>
+        {
+            widget::Text::new("Splits the channels of a file into independent components. Mark the eye and muscle ones, then rebuild the channels without them.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.ic_help, ui);
+
+            let algorithm_items: Vec<String> = Algorithm::ALL.iter().map(|a| a.name().to_string()).collect();
+            for selected in widget::DropDownList::new(&algorithm_items, Algorithm::ALL.iter().position(|a| *a == app.gui_data.ica_algorithm))
+                .align_left_of(ids.ic_help)
+                .down_from(ids.ic_help, Y(2.0))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ic_algorithm, ui)
+                {app.gui_data.ica_algorithm = Algorithm::ALL[selected];}
+            let running = app.gui_data.ica_worker.is_some();
+            for _press in widget::Button::new()
+                .label(if running {"Running..."} else {"Run ICA"})
+                .right_from(ids.ic_algorithm, X(0.5))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ic_run, ui)
+                {
+                    if running {continue;}
+                    //on the filtered raw channels, the derived ones are mixtures of them and add nothing
+                    //they are copied out so the worker doesn't hold the data lock while it iterates
+                    let copied = {
+                        let app_data = app.app_data.lock().unwrap();
+                        app_data.wave_data.as_ref().map(|w| (w.buffer[..w.channels as usize].to_vec(), w.sample_rate))
+                    };
+                    match copied {
+                        Some((channels, sample_rate)) => {
+                            let algorithm = app.gui_data.ica_algorithm;
+                            app.gui_data.ica_worker = Some(Worker::start(move || Ica::compute(&channels, sample_rate, algorithm, 500)));
+                        },
+                        None => app.gui_data.ica_status = String::from("ICA needs a file to be open."),
+                    }
+                }
+            let result = app.gui_data.ica_worker.as_mut().and_then(|w| w.poll());
+            match result {
+                Some(Ok(ica)) => {
+                    app.gui_data.ica_status = format!("{} components, {} after {} iterations", ica.components(),
+                        if ica.converged {"converged"} else {"not converged"}, ica.iterations);
+                    app.gui_data.ica_component = 0;
+                    app.gui_data.ica = Some(ica);
+                    app.gui_data.ica_worker = None;
+                },
+                Some(Err(e)) => {
+                    app.gui_data.ica_status = e;
+                    app.gui_data.ica_worker = None;
+                },
+                None => if let Some(ref worker) = app.gui_data.ica_worker {
+                    app.gui_data.ica_status = format!("Running ICA, {:.0} s so far.", worker.seconds());
+                },
+            }
+            widget::Text::new(&app.gui_data.ica_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.ic_help)
+                .down_from(ids.ic_algorithm, Y(1.0))
+                .set(ids.ic_status, ui);
+            if app.gui_data.ica.is_none() {return;}
+
+            //pick a component and say what it is
+            {
+                let ica = app.gui_data.ica.as_mut().unwrap();
+                let component = std::cmp::min(app.gui_data.ica_component, ica.components() - 1);
+                let component_items: Vec<String> = (0..ica.components()).map(|k| format!("IC {}: {:.1}%{}", k+1, ica.variance[k]*100.0,
+                    if ica.labels[k] == ComponentLabel::Keep {""} else {" (removed)"})).collect();
+                for selected in widget::DropDownList::new(&component_items, Some(component))
+                    .align_left_of(ids.ic_help)
+                    .down_from(ids.ic_status, Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .max_visible_items(8)
+                    .label_font_size(12)
+                    .set(ids.ic_component, ui)
+                    {app.gui_data.ica_component = selected;}
+                let label_items: Vec<String> = ComponentLabel::ALL.iter().map(|l| l.name().to_string()).collect();
+                for selected in widget::DropDownList::new(&label_items, ComponentLabel::ALL.iter().position(|l| *l == ica.labels[component]))
+                    .right_from(ids.ic_component, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.ic_label, ui)
+                    {ica.labels[component] = ComponentLabel::ALL[selected];}
+            }
+
+            //the component's time course over what the first drawer shows, as a min/max envelope, then its spectrum
+            let ticks = app.ticker.ticks();
+            let (w, h) = (X(20.0), Y(10.0));
+            if let Some(ref ica) = app.gui_data.ica {
+                let component = std::cmp::min(app.gui_data.ica_component, ica.components() - 1);
+                let source = &ica.sources[component];
+                let sample_rate = app.app_data.lock().unwrap().get_sample_rate().unwrap_or(1) as f64;
+                let (start, end) = match app.waveform_drawers.first() {
+                    Some(wfd) => {
+                        let end = std::cmp::min((wfd.position_ms(ticks) as f64/1000.0*sample_rate) as usize, source.len());
+                        let span = (wfd.settings.time_pixels as f64*wfd.settings.milliseconds_per_pixel as f64/1000.0*sample_rate) as usize;
+                        (end.saturating_sub(span), end)
+                    },
+                    None => (0, source.len()),
+                };
+                widget::Rectangle::outline([w, h])
+                    .align_left_of(ids.ic_help)
+                    .down_from(ids.ic_component, Y(2.0))
+                    .color(color::GREY)
+                    .set(ids.ic_time, ui);
+                let (cx, cy) = ui.xy_of(ids.ic_time).map(|p| (p[0], p[1])).unwrap_or((0.0, 0.0));
+                let shown = &source[start..end];
+                let peak = shown.iter().fold(1e-12f32, |m, x| m.max(x.abs())) as f64;
+                let columns = std::cmp::max(1, std::cmp::min(200, shown.len()));
+                let mut points = Vec::<[f64; 2]>::with_capacity(columns*2);
+                for c in 0..columns {
+                    let chunk = &shown[c*shown.len()/columns..(c+1)*shown.len()/columns];
+                    if chunk.is_empty() {continue;}
+                    let x = cx - w/2.0 + w*(c as f64 + 0.5)/columns as f64;
+                    let (low, high) = chunk.iter().fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |r, v| (r.0.min(*v), r.1.max(*v)));
+                    points.push([x, cy + h/2.0*0.95*low as f64/peak]);
+                    points.push([x, cy + h/2.0*0.95*high as f64/peak]);
+                }
+                if points.len() > 1 {
+                    widget::PointPath::abs(points)
+                        .color(band_colour(1))
+                        .set(ids.ic_time_trace, ui);
+                }
+                widget::Text::new(&format!("{:.1} to {:.1} s", start as f64/sample_rate, end as f64/sample_rate))
+                    .font_size(12)
+                    .align_left_of(ids.ic_time)
+                    .down_from(ids.ic_time, Y(0.5))
+                    .set(ids.ic_time_axes, ui);
+
+                widget::Rectangle::outline([w, h])
+                    .align_left_of(ids.ic_help)
+                    .down_from(ids.ic_time_axes, Y(1.5))
+                    .color(color::GREY)
+                    .set(ids.ic_spectrum, ui);
+                let (cx, cy) = ui.xy_of(ids.ic_spectrum).map(|p| (p[0], p[1])).unwrap_or((0.0, 0.0));
+                let spectrum = &ica.spectra[component];
+                let (low, high) = spectrum.iter().skip(1).fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |r, v| (r.0.min(*v), r.1.max(*v)));
+                let range = if high > low {(high - low) as f64} else {1.0};
+                let top_hz = ica.spectrum_hz.last().cloned().unwrap_or(1.0).max(1e-6) as f64;
+                let points: Vec<[f64; 2]> = ica.spectrum_hz.iter().zip(spectrum.iter()).skip(1)
+                    .map(|(f, v)| [cx - w/2.0 + w*(*f as f64/top_hz), cy - h/2.0 + h*((v - low) as f64/range)]).collect();
+                if points.len() > 1 {
+                    widget::PointPath::abs(points)
+                        .color(band_colour(3))
+                        .set(ids.ic_spectrum_trace, ui);
+                }
+                widget::Text::new(&format!("0 to {:.0} Hz, {:.0} to {:.0} dB", top_hz, low, high))
+                    .font_size(12)
+                    .align_left_of(ids.ic_spectrum)
+                    .down_from(ids.ic_spectrum, Y(0.5))
+                    .set(ids.ic_spectrum_axes, ui);
+            }
+
+            //cleaned channels are derived channels, added after the others or replacing the last cleaned ones
+            for _press in widget::Button::new()
+                .label("Rebuild cleaned channels")
+                .align_left_of(ids.ic_help)
+                .down_from(ids.ic_spectrum_axes, Y(1.5))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ic_apply, ui)
+                {
+                    let cleaned = app.gui_data.ica.as_ref().unwrap().cleaned();
+                    let mut app_data = app.app_data.lock().unwrap();
+                    app.gui_data.ica_status = match app_data.wave_data {
+                        Some(ref mut wave_data) if wave_data.channels as usize == cleaned.len() => {
+                            let mut derived = wave_data.derived.clone();
+                            let existing: Vec<usize> = (0..derived.len()).filter(|k| if let DerivedChannel::Cleaned(..) = derived[*k] {true} else {false}).collect();
+                            if existing.len() == cleaned.len() {
+                                for (k, d) in existing.iter().zip(cleaned.into_iter()) {derived[*k] = d;}
+                            } else {
+                                derived.extend(cleaned);
+                            }
+                            wave_data.set_derived(derived);
+                            String::from("Cleaned channels rebuilt, pick them in Montage or with the button beside this one.")
+                        },
+                        _ => String::from("The file has changed since ICA was run, run it again."),
+                    };
+                }
+            for _press in widget::Button::new()
+                .label("Show cleaned in drawers")
+                .right_from(ids.ic_apply, X(0.5))
+                .w_h(X(9.75),X(1.5))
+                .label_font_size(12)
+                .set(ids.ic_show, ui)
+                {
+                    //each drawer on a raw channel moves to that channel's cleaned copy
+                    let app_data = app.app_data.lock().unwrap();
+                    if let Some(ref wave_data) = app_data.wave_data {
+                        let n_raw = wave_data.channels;
+                        for wfd in &mut app.waveform_drawers {
+                            let cleaned = wave_data.derived.iter().position(|d| if let DerivedChannel::Cleaned(c, _, _) = *d {c == wfd.settings.channel} else {false});
+                            if let (true, Some(k)) = (wfd.settings.channel < n_raw, cleaned) {
+                                wfd.settings.channel = n_raw + k as u32;
+                                wfd.band_history.clear();
+                            }
+                        }
+                    }
+                }
+        }
+        GuiDisplay::Neurofeedback =>
+        {
+            widget::Text::new("Trains a band power or ratio from one drawer. The metric is smoothed and compared with a fixed threshold or one that adapts to keep the reward rate steady.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.nf_help, ui);
+
+            //everything that can be trained: each band's power and share, then each ratio
+            let ref fd = app.filter_data;
+            let mut metrics = Vec::<(Metric, String)>::new();
+            for (b, band) in fd.bands.iter().enumerate() {metrics.push((Metric::Power(b), format!("{} power (dB)", band.name)));}
+            for (b, band) in fd.bands.iter().enumerate() {metrics.push((Metric::Relative(b), format!("{} relative power", band.name)));}
+            for (r, ratio) in fd.band_ratios.iter().enumerate() {metrics.push((Metric::Ratio(r), ratio.name(&fd.bands)));}
+            let metric_items: Vec<String> = metrics.iter().map(|m| m.1.clone()).collect();
+            let names = app.app_data.lock().unwrap().channel_names();
+            let drawer_items: Vec<String> = app.waveform_drawers.iter().enumerate()
+                .map(|(d, wfd)| format!("Drawer {}: {}", d+1, names.get(wfd.settings.channel as usize).cloned().unwrap_or(String::new()))).collect();
+            let running = app.gui_data.neurofeedback.is_some();
+            {
+                //the drawer, metric and direction belong to a session once it starts
+                let ref mut settings = app.gui_data.feedback_settings;
+                for selected in widget::DropDownList::new(&drawer_items, if settings.drawer < drawer_items.len() {Some(settings.drawer)} else {None})
+                    .align_left_of(ids.nf_help)
+                    .down_from(ids.nf_help, Y(2.0))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.nf_drawer, ui)
+                    {if !running {settings.drawer = selected;}}
+                for selected in widget::DropDownList::new(&metric_items, metrics.iter().position(|m| m.0 == settings.metric))
+                    .right_from(ids.nf_drawer, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .max_visible_items(8)
+                    .label_font_size(12)
+                    .set(ids.nf_metric, ui)
+                    {if !running {settings.metric = metrics[selected].0;}}
+                let reward_items: Vec<String> = Reward::ALL.iter().map(|r| r.name().to_string()).collect();
+                for selected in widget::DropDownList::new(&reward_items, Reward::ALL.iter().position(|r| *r == settings.reward))
+                    .align_left_of(ids.nf_help)
+                    .down_from(ids.nf_drawer, Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.nf_reward, ui)
+                    {if !running {settings.reward = Reward::ALL[selected];}}
+                let kind_items: Vec<String> = FeedbackKind::ALL.iter().map(|k| k.name().to_string()).collect();
+                for selected in widget::DropDownList::new(&kind_items, FeedbackKind::ALL.iter().position(|k| *k == settings.kind))
+                    .right_from(ids.nf_reward, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .label_font_size(12)
+                    .set(ids.nf_kind, ui)
+                    {settings.kind = FeedbackKind::ALL[selected];}
+
+                for adaptive in widget::Toggle::new(settings.adaptive)
+                    .label("Adaptive threshold")
+                    .label_font_size(12)
+                    .align_left_of(ids.nf_help)
+                    .down_from(ids.nf_reward, Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.nf_adaptive, ui)
+                    {settings.adaptive = adaptive;}
+                for v in widget::Slider::new(settings.smoothing_s, 0.1, 5.0)
+                    .label(&format!("Smoothed over {:.1} s", settings.smoothing_s))
+                    .label_font_size(12)
+                    .right_from(ids.nf_adaptive, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.nf_smoothing, ui)
+                    {settings.smoothing_s = v;}
+                if settings.adaptive {
+                    for v in widget::Slider::new(settings.target_rate, 0.1, 0.9)
+                        .label(&format!("Rewarded {:.0}% of the time", settings.target_rate*100.0))
+                        .label_font_size(12)
+                        .align_left_of(ids.nf_help)
+                        .down_from(ids.nf_adaptive, Y(1.0))
+                        .w_h(X(9.75),X(1.5))
+                        .set(ids.nf_target, ui)
+                        {settings.target_rate = v;}
+                    for v in widget::Slider::new(settings.adaptive_s, 5.0, 120.0)
+                        .label(&format!("Over the last {:.0} s", settings.adaptive_s))
+                        .label_font_size(12)
+                        .right_from(ids.nf_target, X(0.5))
+                        .w_h(X(9.75),X(1.5))
+                        .set(ids.nf_window, ui)
+                        {settings.adaptive_s = v;}
+                } else {
+                    //in the metric's own units, which depend on the source's scaling, so it is typed
+                    for event in widget::TextBox::new(&app.gui_data.feedback_threshold)
+                        .align_left_of(ids.nf_help)
+                        .down_from(ids.nf_adaptive, Y(1.0))
+                        .w_h(X(9.75),X(1.5))
+                        .font_size(12)
+                        .set(ids.nf_threshold, ui)
+                        {
+                            if let widget::text_box::Event::Update(text) = event {
+                                if let Ok(threshold) = text.trim().parse::<f32>() {settings.threshold = threshold;}
+                                app.gui_data.feedback_threshold = text;
+                            }
+                        }
+                }
+            }
+
+            for event in widget::TextBox::new(&app.gui_data.feedback_path)
+                .align_left_of(ids.nf_help)
+                .down_from(ids.nf_adaptive, Y(3.5))
+                .w_h(X(14.5),X(1.5))
+                .font_size(12)
+                .set(ids.nf_path, ui)
+                {
+                    if let widget::text_box::Event::Update(path) = event {app.gui_data.feedback_path = path;}
+                }
+            for _press in widget::Button::new()
+                .label(if running {"Stop"} else {"Start"})
+                .color(if running {conrod::color::LIGHT_RED} else {conrod::color::LIGHT_GREY})
+                .right_from(ids.nf_path, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.nf_toggle, ui)
+                {
+                    let ticks = app.ticker.ticks();
+                    match app.gui_data.neurofeedback.take() {
+                        Some(nf) => app.gui_data.feedback_status = match nf.stop(ticks) {Ok(summary) => summary, Err(e) => e},
+                        None => {
+                            let ref settings = app.gui_data.feedback_settings;
+                            let name = metrics.iter().find(|m| m.0 == settings.metric).map(|m| m.1.clone());
+                            let result = match name {
+                                _ if settings.drawer >= app.waveform_drawers.len() => Err(String::from("Pick a drawer to read from.")),
+                                None => Err(String::from("Pick a metric to train.")),
+                                Some(name) => Neurofeedback::start(settings, &name, std::path::Path::new(app.gui_data.feedback_path.trim()), ticks),
+                            };
+                            match result {
+                                Ok(nf) => {
+                                    app.gui_data.neurofeedback = Some(nf);
+                                    app.gui_data.feedback_status = String::new();
+                                },
+                                Err(e) => app.gui_data.feedback_status = e,
+                            }
+                        },
+                    }
+                }
+
+            let status = match app.gui_data.neurofeedback {
+                Some(ref nf) => format!("{}\nNow {}, threshold {:.3}{}", nf.summary(app.ticker.ticks()),
+                    nf.value.map(|v| format!("{:.3}", v)).unwrap_or(String::from("waiting")), nf.threshold, if nf.rewarded {", rewarded"} else {""}),
+                None => app.gui_data.feedback_status.clone(),
+            };
+            widget::Text::new(&status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.nf_help)
+                .down_from(ids.nf_path, Y(1.0))
+                .set(ids.nf_status, ui);
+        }
+        GuiDisplay::Ssvep =>
+        {
+            widget::Text::new("Scores each stimulus frequency by canonical correlation between the drawers' channels and sines and cosines at it and its harmonics. A trial starts at each marker and after each decision.")
+                .font_size(12)
+                .w(X(20.0))
+                .align_middle_x_of(ids.settings_canvas)
+                .down_from(ids.drop_down_panel, Y(2.0))
+                .set(ids.sv_help, ui);
+
+            {
+                let ref mut settings = app.gui_data.ssvep_settings;
+                for event in widget::TextBox::new(&app.gui_data.ssvep_frequencies)
+                    .align_left_of(ids.sv_help)
+                    .down_from(ids.sv_help, Y(2.0))
+                    .w_h(X(20.0),X(1.5))
+                    .font_size(12)
+                    .set(ids.sv_frequencies, ui)
+                    {
+                        if let widget::text_box::Event::Update(text) = event {
+                            match ssvep::parse_frequencies(&text) {
+                                Ok(frequencies) => settings.frequencies = frequencies,
+                                Err(e) => app.gui_data.ssvep_status = e,
+                            }
+                            app.gui_data.ssvep_frequencies = text;
+                        }
+                    }
+                for v in widget::Slider::new(settings.harmonics as f32, 1.0, 5.0)
+                    .label(&format!("{} harmonic{}", settings.harmonics, if settings.harmonics == 1 {""} else {"s"}))
+                    .label_font_size(12)
+                    .align_left_of(ids.sv_help)
+                    .down_from(ids.sv_frequencies, Y(1.0))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.sv_harmonics, ui)
+                    {settings.harmonics = v.round() as usize;}
+                for v in widget::Slider::new(settings.confidence, 0.05, 0.9)
+                    .label(&format!("Decide at {:.2} confidence", settings.confidence))
+                    .label_font_size(12)
+                    .right_from(ids.sv_harmonics, X(0.5))
+                    .w_h(X(9.75),X(1.5))
+                    .set(ids.sv_confidence, ui)
+                    {settings.confidence = v;}
+                for (edge, value) in widget::RangeSlider::new(settings.min_window_s, settings.max_window_s, 0.25, 10.0)
+                    .label(&format!("Windows from {:.2} to {:.2} s", settings.min_window_s, settings.max_window_s))
+                    .label_font_size(12)
+                    .align_left_of(ids.sv_help)
+                    .down_from(ids.sv_harmonics, Y(1.0))
+                    .w_h(X(20.0),X(1.5))
+                    .set(ids.sv_windows, ui)
+                    {
+                        match edge {
+                            widget::range_slider::Edge::Start => settings.min_window_s = value,
+                            widget::range_slider::Edge::End => settings.max_window_s = value,
+                        }
+                    }
+                for event in widget::TextBox::new(&settings.address)
+                    .align_left_of(ids.sv_help)
+                    .down_from(ids.sv_windows, Y(1.0))
+                    .w_h(X(14.5),X(1.5))
+                    .font_size(12)
+                    .set(ids.sv_address, ui)
+                    {
+                        if let widget::text_box::Event::Update(address) = event {settings.address = address;}
+                    }
+            }
+            let running = app.gui_data.ssvep.is_some();
+            for _press in widget::Button::new()
+                .label(if running {"Stop"} else {"Start"})
+                .color(if running {conrod::color::LIGHT_RED} else {conrod::color::LIGHT_GREY})
+                .right_from(ids.sv_address, X(0.5))
+                .w_h(X(5.0),X(1.5))
+                .label_font_size(12)
+                .set(ids.sv_toggle, ui)
+                {
+                    if running {
+                        let detector = app.gui_data.ssvep.take().unwrap();
+                        app.gui_data.ssvep_status = format!("Stopped after {} decisions and {} trials without one.", detector.decided, detector.undecided);
+                    } else {
+                        let now_s = app.waveform_drawers.first().map(|wfd| wfd.position_ms(app.ticker.ticks()) as f64/1000.0).unwrap_or(0.0);
+                        match Ssvep::start(&app.gui_data.ssvep_settings, now_s) {
+                            Ok(detector) => {
+                                app.gui_data.ssvep_status = format!("Sending decisions to {}", detector.target());
+                                app.gui_data.ssvep = Some(detector);
+                            },
+                            Err(e) => app.gui_data.ssvep_status = e,
+                        }
+                    }
+                }
+            widget::Text::new(&app.gui_data.ssvep_status)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.sv_help)
+                .down_from(ids.sv_address, Y(1.0))
+                .set(ids.sv_status, ui);
+            if app.gui_data.ssvep.is_none() {return;}
+
+            //the latest window's correlation for each frequency, the leader in green
+            let detector = app.gui_data.ssvep.as_ref().unwrap();
+            let n = detector.correlations.len();
+            ids.sv_bars.resize(n, &mut ui.widget_id_generator());
+            ids.sv_bar_labels.resize(n, &mut ui.widget_id_generator());
+            let best = (0..n).fold(None, |best: Option<usize>, k| match best {
+                Some(b) if detector.correlations[b] >= detector.correlations[k] => Some(b),
+                _ => Some(k),
+            });
+            let full = X(14.0);
+            for k in 0..n {
+                let w = (full*detector.correlations[k] as f64).max(1.0);
+                let label = widget::Text::new(&format!("{:.2} Hz", detector.frequencies[k]))
+                    .font_size(12)
+                    .w(X(5.0))
+                    .align_left_of(ids.sv_help);
+                let label = if k == 0 {label.down_from(ids.sv_status, Y(2.0))} else {label.down_from(ids.sv_bar_labels[k-1], Y(1.0))};
+                label.set(ids.sv_bar_labels[k], ui);
+                let y = ui.xy_of(ids.sv_bar_labels[k]).map(|p| p[1]).unwrap_or(0.0);
+                let left = ui.xy_of(ids.sv_help).map(|p| p[0]).unwrap_or(0.0) - X(10.0) + X(6.0);
+                widget::Rectangle::fill([w, X(1.0)])
+                    .x_y(left + w/2.0, y)
+                    .color(if Some(k) == best {conrod::color::rgb(0.2, 0.9, 0.3)} else {conrod::color::rgb(0.4, 0.4, 0.45)})
+                    .set(ids.sv_bars[k], ui);
+            }
+
+            let decision = match detector.last {
+                Some(ref d) => format!("{} {:.2} Hz, confidence {:.2}, after {:.2} s at {:.1} s\n{} decisions, {} trials without one, this trial {:.2} s",
+                    if d.decided {"Decided"} else {"No decision, best"}, d.frequency_hz, d.confidence, d.latency_s, d.time_s,
+                    detector.decided, detector.undecided, detector.window_s),
+                None => format!("No trials finished yet, this one {:.2} s", detector.window_s),
+            };
+            let below = if n > 0 {ids.sv_bar_labels[n-1]} else {ids.sv_status};
+            widget::Text::new(&decision)
+                .font_size(12)
+                .w(X(20.0))
+                .align_left_of(ids.sv_help)
+                .down_from(below, Y(2.0))
+                .set(ids.sv_decision, ui);
+        }
         _=>()
     }
 
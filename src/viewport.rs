@@ -0,0 +1,55 @@
+//The one coordinate system everything is laid out in.
+//Layout is done in percentages of the window with (0,0) in the centre and y going up,
+//the same as WaveformDrawerSettings. Conrod works in logical points, glutin reports the cursor
+//in logical points from the top left, and textures are blitted in physical framebuffer pixels.
+//On HiDPI screens logical and physical differ by the hidpi factor, so every conversion goes through here.
+#[derive(Clone, Copy)]
+pub struct Viewport {
+    pub width: f64, //logical points
+    pub height: f64,
+    pub hidpi_factor: f64,
+}
+
+impl Viewport {
+    pub fn new(width: f64, height: f64, hidpi_factor: f64) -> Viewport {
+        Viewport{
+            width: width,
+            height: height,
+            hidpi_factor: hidpi_factor,
+        }
+    }
+
+    pub fn resize(&mut self, width: f64, height: f64) {
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn aspect(&self) -> f32 {
+        (self.width/self.height) as f32
+    }
+
+    //percentage lengths or centred positions to conrod's logical points
+    pub fn x(&self, percent: f64) -> f64 {
+        percent*self.width/100.0
+    }
+    pub fn y(&self, percent: f64) -> f64 {
+        percent*self.height/100.0
+    }
+
+    //a logical cursor position from glutin to centred percentages
+    pub fn cursor_to_percent(&self, cursor: (f32, f32)) -> (f32, f32) {
+        (cursor.0/self.width as f32*100.0 - 50.0, 50.0 - cursor.1/self.height as f32*100.0)
+    }
+
+    pub fn physical_size(&self) -> (u32, u32) {
+        ((self.width*self.hidpi_factor).round() as u32, (self.height*self.hidpi_factor).round() as u32)
+    }
+
+    //a centred percentage rectangle to physical pixels as (left, bottom, width, height) for blitting
+    pub fn pixel_rect(&self, x: f32, y: f32, width: f32, height: f32) -> (u32, u32, i32, i32) {
+        let (fb_w, fb_h) = self.physical_size();
+        let left = fb_w as f32*(x - width/2.0 + 50.0)/100.0;
+        let bottom = fb_h as f32*(y - height/2.0 + 50.0)/100.0;
+        (left.max(0.0) as u32, bottom.max(0.0) as u32, (width*fb_w as f32/100.0) as i32, (height*fb_h as f32/100.0) as i32)
+    }
+}
@@ -1,12 +1,20 @@
 use std;
 use appstate::{AppData, FilterData};
 use rustfft;
-use dftwindower::{DFTWindower};
+use dftwindower::{DFTWindower, WindowShape};
+use bandpower;
+use bandpower::{BandPowerHistory};
+use peaks;
+use peaks::{PeakTracker};
 
 use glium;
 use glium::{Surface};
 
 use city2d::City2D;
+use morlet::{MorletBank};
+use multitaper::{Tapers};
+use reassign::{Reassigner, ReassignedPoint, ReassignedColumns};
+use viewport::{Viewport};
 
 #[allow(dead_code)]
 struct VStrip{
@@ -32,7 +40,201 @@ impl VStrip{
     }
 }
 
+//detrends, windows and transforms one column of samples
+//the display and the exports both go through here so they always agree
+//a signal shorter than the window (near the start) is zero filled in front of it, then the windowed signal is zero padded
+//up to fft_len, so the bins are the same for every column
+pub fn spectrum_of(signal: &mut Vec<rustfft::num_complex::Complex<f32>>, fd: &FilterData, dft_windower: &DFTWindower, fft_len: u32) -> Vec<rustfft::num_complex::Complex<f32>>{
+    fd.detrend.apply(signal);
+
+    let missing = dft_windower.len().saturating_sub(signal.len());
+    if missing > 0 {
+        let mut padded = vec![rustfft::num_complex::Complex{re: 0.0, im: 0.0}; missing];
+        padded.extend_from_slice(signal);
+        *signal = padded;
+    }
+    dft_windower.apply(signal);
+    let dtft_len = std::cmp::max(fft_len as usize, signal.len()) as u32;
+    signal.resize(dtft_len as usize, rustfft::num_complex::Complex{re: 0.0, im: 0.0});
+
+    let mut spectrum = signal.clone();
+    let mut fft_planner = rustfft::FFTplanner::new(false);
+    let fft = fft_planner.plan_fft(dtft_len as usize);
+    fft.process(signal, &mut spectrum);
+    spectrum
+}
+
+//how each column of a drawer is worked out
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Transform {
+    Stft,
+    Morlet,
+    Multitaper,
+    Reassigned,
+    Synchrosqueezed,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 5] = [Transform::Stft, Transform::Morlet, Transform::Multitaper, Transform::Reassigned, Transform::Synchrosqueezed];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Transform::Stft => "STFT",
+            Transform::Morlet => "Morlet wavelets",
+            Transform::Multitaper => "Multitaper (DPSS)",
+            Transform::Reassigned => "Reassigned STFT",
+            Transform::Synchrosqueezed => "Synchrosqueezed STFT",
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::Stft
+    }
+}
+
+fn default_morlet_cycles() -> f32 {7.0}
+
+fn default_multitaper_nw() -> f32 {3.0}
+fn default_multitaper_tapers() -> u32 {5}
+fn default_peak_jump_hz() -> f32 {2.0}
+
+//everything besides the samples that goes into a column, the wavelets and tapers are only built once a drawer uses them
+//and rebuilt when a setting they depend on changes
+pub struct ColumnAnalyser {
+    dft_windower: DFTWindower,
+    morlet: Option<MorletBank>,
+    tapers: Option<Tapers>,
+    reassigner: Option<Reassigner>,
+}
+
+impl ColumnAnalyser {
+    pub fn new(dtft_samples: u32) -> ColumnAnalyser {
+        ColumnAnalyser{
+            dft_windower: DFTWindower::new(WindowShape::Rectangular, dtft_samples),
+            morlet: None,
+            tapers: None,
+            reassigner: None,
+        }
+    }
+
+    pub fn prepare(&mut self, settings: &WaveformDrawerSettings, fd: &FilterData, sample_rate: u32) {
+        match settings.transform {
+            Transform::Stft => {
+                if !self.dft_windower.matches(fd.window_shape, settings.dtft_samples) {
+                    self.dft_windower = DFTWindower::new(fd.window_shape, settings.dtft_samples);
+                }
+            },
+            Transform::Morlet => {
+                let rows = std::cmp::min(settings.dtft_display_samples, settings.fft_len()/2);
+                if !self.morlet.as_ref().map(|m| m.matches(sample_rate, settings.fft_len(), settings.dtft_samples, rows, settings.morlet_cycles)).unwrap_or(false) {
+                    self.morlet = Some(MorletBank::new(sample_rate, settings.fft_len(), settings.dtft_samples, rows, settings.morlet_cycles));
+                }
+            },
+            Transform::Multitaper => {
+                let len = settings.dtft_samples as usize;
+                if !self.tapers.as_ref().map(|t| t.matches(len, settings.multitaper_nw, settings.multitaper_tapers)).unwrap_or(false) {
+                    self.tapers = Some(Tapers::new(len, settings.multitaper_nw, settings.multitaper_tapers));
+                }
+            },
+            Transform::Reassigned | Transform::Synchrosqueezed => {
+                //the plain window is kept too, its energy scales the band powers
+                if !self.dft_windower.matches(fd.window_shape, settings.dtft_samples) {
+                    self.dft_windower = DFTWindower::new(fd.window_shape, settings.dtft_samples);
+                }
+                if !self.reassigner.as_ref().map(|r| r.matches(fd.window_shape, settings.dtft_samples, settings.fft_len())).unwrap_or(false) {
+                    self.reassigner = Some(Reassigner::new(fd.window_shape, settings.dtft_samples, settings.fft_len()));
+                }
+            },
+        }
+    }
+
+    //how many samples up to the newest one a column looks at
+    pub fn window_length(&self, settings: &WaveformDrawerSettings) -> usize {
+        match (settings.transform, self.morlet.as_ref()) {
+            (Transform::Morlet, Some(bank)) => bank.longest(),
+            _ => settings.dtft_samples as usize,
+        }
+    }
+
+    //the sum of the squared window coefficients behind a column, for band_powers
+    //the tapers are each scaled to a rectangular window's energy and the wavelets to a rectangular stft's amplitude
+    pub fn window_energy(&self, settings: &WaveformDrawerSettings) -> f32 {
+        match settings.transform {
+            Transform::Morlet | Transform::Multitaper => settings.dtft_samples as f32,
+            _ => self.dft_windower.energy(),
+        }
+    }
+
+    //one column's spectrum, laid out like an fft of fft_len whatever the transform
+    //the morlet wavelets and the tapers are their own windows so the window shape only applies to the stft
+    //the reassigned spectrogram spreads over several columns, so here it is the plain stft, see reassign()
+    pub fn spectrum(&self, signal: &mut Vec<rustfft::num_complex::Complex<f32>>, settings: &WaveformDrawerSettings, fd: &FilterData) -> Vec<rustfft::num_complex::Complex<f32>> {
+        match (settings.transform, self.morlet.as_ref(), self.tapers.as_ref(), self.reassigner.as_ref()) {
+            (Transform::Morlet, Some(bank), _, _) => {
+                let samples: Vec<f32> = signal.iter().map(|c| c.re).collect();
+                bank.transform(&samples)
+            },
+            (Transform::Multitaper, _, Some(tapers), _) => {
+                fd.detrend.apply(signal);
+                let samples: Vec<f32> = signal.iter().map(|c| c.re).collect();
+                tapers.spectrum(&samples, settings.fft_len())
+            },
+            (Transform::Synchrosqueezed, _, _, Some(reassigner)) => {
+                fd.detrend.apply(signal);
+                let samples: Vec<f32> = signal.iter().map(|c| c.re).collect();
+                reassigner.squeeze(&samples)
+            },
+            (Transform::Reassigned, _, _, Some(reassigner)) => {
+                fd.detrend.apply(signal);
+                let samples: Vec<f32> = signal.iter().map(|c| c.re).collect();
+                reassigner.reassign(&samples).0
+            },
+            _ => spectrum_of(signal, fd, &self.dft_windower, settings.fft_len()),
+        }
+    }
+
+    //the plain stft and where its power belongs, for drawers showing the reassigned spectrogram
+    pub fn reassign(&self, signal: &mut Vec<rustfft::num_complex::Complex<f32>>, settings: &WaveformDrawerSettings, fd: &FilterData) -> Option<(Vec<rustfft::num_complex::Complex<f32>>, Vec<ReassignedPoint>)> {
+        match (settings.transform, self.reassigner.as_ref()) {
+            (Transform::Reassigned, Some(reassigner)) => {
+                fd.detrend.apply(signal);
+                let samples: Vec<f32> = signal.iter().map(|c| c.re).collect();
+                Some(reassigner.reassign(&samples))
+            },
+            _ => None,
+        }
+    }
+}
+
+//the colour of each of the first dtft_display_len bins of a spectrum, lowest frequency first
+pub fn column_colours(spectrum: &[rustfft::num_complex::Complex<f32>], dtft_display_len: u32, fd: &FilterData) -> Vec<(u8,u8,u8)>{
+    let mut mean_norm : f32 = 0.0;
+    for i in 0..dtft_display_len {
+        let norm=spectrum[i as usize].norm();
+        mean_norm += norm;
+    }
+    if mean_norm == 0.0 {mean_norm=1.0;}
+    mean_norm /= (dtft_display_len/2) as f32;
+
+    let mut colours = Vec::<(u8,u8,u8)>::with_capacity(dtft_display_len as usize);
+    for i in 0..dtft_display_len {
+        let norm_spec_val = if fd.amp_manual {spectrum[i as usize]*fd.amp.exp()} else {spectrum[i as usize]/mean_norm};
+        //let norm_spec_val=spectrum[i as usize]/mean_norm;
+
+        let ired=std::cmp::min(   ((norm_spec_val*fd.red.0).norm().atan()*fd.red.1)   as u64,255);
+        //let igre=std::cmp::min(   ((((norm_spec_val.norm()*fd.green.0)+2.718).ln()-1.0)*fd.green.1)   as u64,255);
+        let igre=std::cmp::min(   (norm_spec_val.norm()*fd.green.0.exp() + (1.0+norm_spec_val.norm()).ln() * fd.green.1.exp() )   as u64,255);
+        let iblu=std::cmp::min(   (mean_norm*fd.blue.1.exp())   as u64,fd.blue.0 as u64);
+
+        colours.push((ired as u8, igre as u8, iblu as u8));
+    }
+    colours
+}
+
 #[allow(dead_code)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct WaveformDrawerSettings {
     pub x: f32, //x coord of the display
     pub y: f32, //y coord of display
@@ -43,6 +245,61 @@ pub struct WaveformDrawerSettings {
     pub dtft_samples: u32, //how many samples to take for the ftft window
     pub dtft_display_samples: u32, //how many of the above samples to display (cuts off high frequency samples)
     pub channel: u32, //which chanel to read from
+    pub visible: bool, //hidden drawers keep analysing but are not drawn
+    #[serde(default)]
+    pub transform: Transform,
+    #[serde(default = "default_morlet_cycles")]
+    pub morlet_cycles: f32, //how many cycles each wavelet spans, more gives finer frequency and coarser time
+    #[serde(default = "default_multitaper_nw")]
+    pub multitaper_nw: f32, //time-half-bandwidth product, the tapers smooth over +-nw bins
+    #[serde(default = "default_multitaper_tapers")]
+    pub multitaper_tapers: u32, //at most 2nw-1
+    #[serde(default)]
+    pub fft_samples: u32, //fft size, the window is zero padded up to it, anything up to dtft_samples means no padding
+    #[serde(default)]
+    pub fft_pow2: bool, //round the fft size up to a power of two
+    #[serde(default)]
+    pub peaks: u32, //how many spectral peaks to track per column, 0 for none
+    #[serde(default = "default_peak_jump_hz")]
+    pub peak_jump_hz: f32, //the furthest a track can move between columns
+}
+
+//one drawer filling the spectrogram region, new drawers start from this and change what they need
+impl Default for WaveformDrawerSettings {
+    fn default() -> WaveformDrawerSettings {
+        WaveformDrawerSettings{
+            x: -12.5,
+            y: 0.0,
+            width: 75.0,
+            height: 100.0,
+            milliseconds_per_pixel: 8.0,
+            time_pixels: 1000,
+            dtft_samples: 800,
+            dtft_display_samples: 200,
+            channel: 0,
+            visible: true,
+            transform: Transform::Stft,
+            morlet_cycles: default_morlet_cycles(),
+            multitaper_nw: default_multitaper_nw(),
+            multitaper_tapers: default_multitaper_tapers(),
+            fft_samples: 0,
+            fft_pow2: false,
+            peaks: 0,
+            peak_jump_hz: default_peak_jump_hz(),
+        }
+    }
+}
+
+impl WaveformDrawerSettings {
+    //how long the fft is, dtft_samples is only the window
+    pub fn fft_len(&self) -> u32 {
+        let len = std::cmp::max(self.dtft_samples, self.fft_samples);
+        if self.fft_pow2 {len.next_power_of_two()} else {len}
+    }
+
+    pub fn bin_hz(&self, sample_rate: u32) -> f32 {
+        sample_rate as f32/self.fft_len() as f32
+    }
 }
 
 #[allow(dead_code)]
@@ -57,7 +314,11 @@ pub struct WaveformDrawer<'a> {
     display: &'a glium::Display,
     running: bool,
     start_ticks: u64,
-    dft_windower: DFTWindower,
+    offset_ms: u64, //where in the source we were when start_ticks was taken
+    analyser: ColumnAnalyser,
+    pub band_history: BandPowerHistory,
+    pub peak_tracker: PeakTracker,
+    reassigned: ReassignedColumns, //columns still waiting for power from later windows
 }
 
 impl<'a> WaveformDrawer<'a> {
@@ -78,32 +339,86 @@ impl<'a> WaveformDrawer<'a> {
             display: display,
             running: false,
             start_ticks: 0,
-            dft_windower: DFTWindower::new(dtft_samples),
+            offset_ms: 0,
+            analyser: ColumnAnalyser::new(dtft_samples),
+            band_history: BandPowerHistory::new(500),
+            peak_tracker: PeakTracker::new(),
+            reassigned: ReassignedColumns::new(),
         }
     }
 
 
+    //swaps in new settings, rebuilding the texture and windower if their sizes have changed
+    pub fn set_settings(&mut self, settings: WaveformDrawerSettings){
+        if settings.time_pixels != self.texture_w || settings.dtft_display_samples != self.texture_h {
+            self.texture_w = settings.time_pixels;
+            self.texture_h = settings.dtft_display_samples;
+            self.texture = glium::texture::Texture2d::empty(self.display, self.texture_w, self.texture_h).expect("WaveformDrawer unable to create texture.");
+            self.texture.as_surface().clear_color(0.0,0.0,0.0,1.0);
+            self.vstrips.clear();
+        }
+        if settings.dtft_samples != self.settings.dtft_samples {
+            self.analyser = ColumnAnalyser::new(settings.dtft_samples);
+        }
+        self.band_history.clear();
+        self.peak_tracker.clear();
+        self.reassigned.clear();
+        self.settings = settings;
+    }
+
     pub fn start(&mut self, ticks: u64){
         self.start_ticks=ticks;
+        self.offset_ms=0;
         self.running=true;
     }
 
+    //how far into the source we are, in milliseconds
+    pub fn position_ms(&self, ticks: u64) -> u64{
+        if !self.running {return 0;}
+        ticks - self.start_ticks + self.offset_ms
+    }
+
+    //jumps to position_ms into the source and re-renders the part of the spectrogram that would be on screen
+    pub fn seek(&mut self, ticks: u64, position_ms: u64, app_data: &std::sync::Arc<std::sync::Mutex<AppData>>, fd: &FilterData){
+        self.start_ticks=ticks;
+        self.running=true;
+        let visible_ms = (self.settings.time_pixels as f32 * self.settings.milliseconds_per_pixel) as u64;
+        let mut t = position_ms.saturating_sub(visible_ms);
+        self.rendered_ticks = t;
+        self.band_history.clear();
+        self.peak_tracker.clear();
+        self.reassigned.clear();
+        self.vstrips.clear();
+        self.texture.as_surface().clear_color(0.0,0.0,0.0,1.0);
+        //step through the history a frame at a time as if we had been running all along
+        while t < position_ms {
+            t = std::cmp::min(t + 16, position_ms);
+            self.offset_ms = t;
+            self.update_stft(ticks, app_data, fd);
+        }
+        self.offset_ms = position_ms;
+    }
+
 
 
 
     pub fn update_stft(&mut self, ticks: u64, app_data: &std::sync::Arc<std::sync::Mutex<AppData>>, fd: &FilterData){
         if !self.running {return;}
-        let ticks = ticks-self.start_ticks;
+        let ticks = ticks-self.start_ticks+self.offset_ms;
         let settings=&mut self.settings;
 
         let mut signal = Vec::<rustfft::num_complex::Complex<f32>>::new();
         let dtft_len: u32;
         let dtft_display_len: u32;
         let mut needed_pixels: u32;
+        let sample_rate: u32;
+        let flagged: bool;
         { //lock the data mutex here
             let data_arc = app_data.clone();
             let mut data = data_arc.lock().unwrap();
-            let sample_point: u64 = ticks * data.get_sample_rate().unwrap() as u64 / 1000; //what point (index) in the data are we at
+            sample_rate = data.get_sample_rate().unwrap();
+            let sample_point: u64 = ticks * sample_rate as u64 / 1000; //what point (index) in the data are we at
+            self.analyser.prepare(settings, fd, sample_rate);
 
 
             //if we're too near the begining to do a DTFT or we're past the end of the data then we draw our texture and return
@@ -112,8 +427,8 @@ impl<'a> WaveformDrawer<'a> {
             }
 
 
-            dtft_len = std::cmp::min(sample_point, settings.dtft_samples as u64) as u32; //how many points to sample for the DTFT
-            dtft_display_len = std::cmp::min(sample_point, settings.dtft_display_samples as u64) as u32; //how many points to sample for the DTFT
+            dtft_len = std::cmp::min(sample_point, self.analyser.window_length(settings) as u64) as u32; //how many points to sample for the DTFT
+            dtft_display_len = std::cmp::min(settings.dtft_display_samples, settings.fft_len()/2); //how many bins to colour, only up to nyquist, the same from the first column on
 
             //how many pixels (width) these samples will take up
             needed_pixels=((ticks - self.rendered_ticks) as f32 / settings.milliseconds_per_pixel) as u32;
@@ -127,75 +442,63 @@ impl<'a> WaveformDrawer<'a> {
                     signal[i as usize].re=slice[i as usize];
                 }
             }
+            //a window touching a flagged span is still drawn but left out of the band powers
+            let window_s = ((sample_point - dtft_len as u64) as f64/sample_rate as f64, sample_point as f64/sample_rate as f64);
+            flagged = fd.exclude_artifacts && data.wave_data.as_ref()
+                .map(|w| w.artifacts_for(settings.channel as usize).iter().any(|s| s.overlaps(window_s.0, window_s.1)))
+                .unwrap_or(false);
         } //unlock data mutex here
 
         if needed_pixels != 0 {
 
-            match fd.window_shape {
-                0 /*none*/ => {},
-                1 /*Hann*/ => {
-                    for i in (0)..dtft_len {
-                        signal[i as usize].re = signal[i as usize].re*self.dft_windower.hann(i,dtft_len);
+            let spectrum = match self.analyser.reassign(&mut signal, settings, fd) {
+                Some((spectrum, points)) => {
+                    self.reassigned.push(self.rendered_ticks as f64, ticks as f64, needed_pixels, settings.fft_len() as usize);
+                    for p in points {
+                        self.reassigned.add(ticks as f64 + p.time as f64*1000.0/sample_rate as f64, p.bin, p.power);
                     }
+                    spectrum
                 },
-                2 /*Hamming*/ => {
-                    for i in (0)..dtft_len {
-                       signal[i as usize].re = signal[i as usize].re*self.dft_windower.hamming(i,dtft_len);
-                    }
-                },
-                3 /*Nuttall*/ => {
-                    for i in (0)..dtft_len {
-                       signal[i as usize].re = signal[i as usize].re*self.dft_windower.nuttall(i,dtft_len);
-                    }
-                },
-                4 /*Sine*/ => {
-                    for i in (0)..dtft_len {
-                       signal[i as usize].re = signal[i as usize].re*self.dft_windower.sine(i,dtft_len);
-                    }
+                None => {
+                    self.reassigned.clear();
+                    self.analyser.spectrum(&mut signal, settings, fd)
                 },
-                5 /*Kaiser*/ => {
-                    for i in (0)..dtft_len {
-                       signal[i as usize].re = signal[i as usize].re*self.dft_windower.kaiser(i,dtft_len);
-                    }
-                },
-                _=>{}
-            }
+            };
 
-            let mut spectrum = signal.clone();
-            let mut fft_planner = rustfft::FFTplanner::new(false);
-            let fft = fft_planner.plan_fft(dtft_len as usize);
-            fft.process(&mut signal, &mut spectrum);
-
-            let mut mean_norm : f32 = 0.0;
-            for i in 0..dtft_display_len {
-                let norm=spectrum[i as usize].norm();
-                mean_norm += norm;
+            if !flagged {
+                let powers = bandpower::band_powers(&spectrum, settings.bin_hz(sample_rate), &fd.bands, self.analyser.window_energy(settings));
+                self.band_history.push(ticks, powers);
+            }
+            if settings.peaks > 0 {
+                let found = peaks::find_peaks(&spectrum, std::cmp::min(settings.dtft_display_samples, settings.fft_len()/2) as usize, settings.bin_hz(sample_rate), settings.peaks as usize);
+                self.peak_tracker.push(ticks as f64/1000.0, &found, settings.peak_jump_hz);
             }
-            if mean_norm == 0.0 {mean_norm=1.0;}
-            mean_norm /= (dtft_display_len/2) as f32;
-
-            let mut vstrip=VStrip::new(settings.dtft_display_samples,needed_pixels);
-            for i in 0..dtft_display_len {
-                let norm_spec_val = if fd.amp_manual {spectrum[i as usize]*fd.amp.exp()} else {spectrum[i as usize]/mean_norm};
-                //let norm_spec_val=spectrum[i as usize]/mean_norm;
-
-                let ired=std::cmp::min(   ((norm_spec_val*fd.red.0).norm().atan()*fd.red.1)   as u64,255);
-                //let igre=std::cmp::min(   ((((norm_spec_val.norm()*fd.green.0)+2.718).ln()-1.0)*fd.green.1)   as u64,255);
-                let igre=std::cmp::min(   (norm_spec_val.norm()*fd.green.0.exp() + (1.0+norm_spec_val.norm()).ln() * fd.green.1.exp() )   as u64,255);
-                let iblu=std::cmp::min(   (mean_norm*fd.blue.1.exp())   as u64,fd.blue.0 as u64);
 
-                vstrip.write_pixel(dtft_display_len-i-1, ired as u8, igre as u8, iblu as u8);
+            if settings.transform == Transform::Reassigned {
+                //only columns a whole window old can't receive any more power
+                let window_ms = settings.dtft_samples as f64*1000.0/sample_rate as f64;
+                for column in self.reassigned.ready(ticks as f64 - window_ms) {
+                    let mut vstrip=VStrip::new(settings.dtft_display_samples,column.pixels);
+                    for (i, colour) in column_colours(&column.spectrum(), dtft_display_len, fd).iter().enumerate() {
+                        vstrip.write_pixel(dtft_display_len-i as u32-1, colour.0, colour.1, colour.2);
+                    }
+                    self.vstrips.push(vstrip);
+                }
+            } else {
+                let mut vstrip=VStrip::new(settings.dtft_display_samples,needed_pixels);
+                for (i, colour) in column_colours(&spectrum, dtft_display_len, fd).iter().enumerate() {
+                    vstrip.write_pixel(dtft_display_len-i as u32-1, colour.0, colour.1, colour.2);
+                }
+                self.vstrips.push(vstrip);
             }
             self.rendered_ticks=ticks; //update the counter now that we're done drawing
-            self.vstrips.push(vstrip);
         }
 
     }
 
 
 
-    pub fn generate_and_draw_texture(&mut self, target: &mut glium::Frame){
-        let (fb_w,fb_h)=target.get_dimensions();
+    pub fn generate_and_draw_texture(&mut self, target: &mut glium::Frame, viewport: &Viewport){
         if !self.running {return;}
         if self.vstrips.len()!=0 {
 
@@ -265,13 +568,11 @@ impl<'a> WaveformDrawer<'a> {
         }
 
         //now we have the final texture, we can draw it!
+        if !self.settings.visible {return;}
         let tex=&self.texture;
         let width = tex.get_width();
         let height = tex.get_height().unwrap();
-        let target_width = self.settings.width * fb_w as f32 / 100.0;
-        let target_height = self.settings.height * fb_h as f32 / 100.0;
-        let target_x = fb_w as f32 *(self.settings.x - self.settings.width/2.0 +50.0)/100.0;
-        let target_y = fb_h as f32 *(self.settings.y - self.settings.height/2.0 +50.0)/100.0;
+        let (target_x, target_y, target_width, target_height) = viewport.pixel_rect(self.settings.x, self.settings.y, self.settings.width, self.settings.height);
         let sfb = tex.as_surface();
         target.blit_from_simple_framebuffer(&sfb,
             &glium::Rect{
@@ -280,11 +581,10 @@ impl<'a> WaveformDrawer<'a> {
                 width: width,
                 height: height},
             &glium::BlitTarget{
-                //basically 0,0 is the centre of the screen and texture and distances are in pixels, so we need to transform the coordinates a bit
-                left: target_x as u32,
-                bottom: target_y as u32,
-                width: target_width as i32,
-                height: target_height as i32},
+                left: target_x,
+                bottom: target_y,
+                width: target_width,
+                height: target_height},
             glium::uniforms::MagnifySamplerFilter::Linear);
     }
 }
@@ -0,0 +1,40 @@
+use std;
+
+//A computation too slow for the GUI thread, run on a thread of its own with copies of everything it needs
+//the same way the recorder hands its samples to a writer thread. The GUI polls it once a frame and keeps drawing meanwhile.
+//Dropping a worker that hasn't finished leaves the thread to run out on its own and its result is thrown away.
+pub struct Worker<T> {
+    receiver: std::sync::mpsc::Receiver<Result<T, String>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    started: std::time::Instant,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    pub fn start<F>(work: F) -> Worker<T> where F: FnOnce() -> Result<T, String> + Send + 'static {
+        let (sender, receiver) = std::sync::mpsc::channel::<Result<T, String>>();
+        let thread = std::thread::spawn(move || {
+            let _ = sender.send(work());
+        });
+        Worker{
+            receiver: receiver,
+            thread: Some(thread),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    //the result once there is one, the thread has finished by then so it is joined straight away
+    pub fn poll(&mut self) -> Option<Result<T, String>> {
+        let result = match self.receiver.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return None,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => Err(String::from("The worker thread panicked.")),
+        };
+        if let Some(thread) = self.thread.take() {let _ = thread.join();}
+        Some(result)
+    }
+
+    pub fn seconds(&self) -> f32 {
+        let d = self.started.elapsed();
+        d.as_secs() as f32 + d.subsec_nanos() as f32*1e-9
+    }
+}